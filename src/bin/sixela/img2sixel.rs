@@ -0,0 +1,284 @@
+// sixela::bin::sixela::img2sixel
+//
+//! Encodes image files as sixel images, mirroring the options of libsixel's `img2sixel`.
+//
+// TOC
+// - const USAGE
+// - fn run
+// - struct Options
+// - fn expand_gray
+// - enum Length
+// - fn parse_* helpers
+
+use super::{open_output, parse_args, read_input, Arg, CliResult, OptSpec};
+use sixela::{
+    Diffusion, LargestDim, LoadedImage, PixelFormat, Quality, RepColor, ResampleMethod, Sixel,
+};
+
+const USAGE: &str = "\
+Usage: sixela [Options] imagefiles
+       sixela [Options] < imagefile
+       sixela sixel2img --help
+
+Reads PNM, BMP, TGA, QOI, farbfeld, PNG, GIF and sixel images,
+and writes them as sixel images to the standard output.
+
+Options:
+-o, --outfile=OUTFILE      write to OUTFILE instead of the standard output
+-7, --7bit-mode            use 7-bit control sequences (default)
+-8, --8bit-mode            use 8-bit control sequences
+-p, --colors=COLORS        reduce the image to COLORS colors, from 1 to 256
+                           (default: 256)
+-d, --diffusion=TYPE       choose the method of diffusion:
+                             auto, none, atkinson, fs, jajuni, stucki, burkes,
+                             a_dither, x_dither, bayer2, bayer4, bayer8,
+                             blue_noise, sierra3, sierra2, sierra_lite,
+                             stevenson_arce (default: auto)
+-f, --find-largest=TYPE    choose the method of finding the largest dimension
+                           of the median cut boxes: auto, norm, lum
+                           (default: auto)
+-s, --select-color=TYPE    choose the method of selecting the representative
+                           color of the median cut boxes: auto, center,
+                           average, histogram (default: auto)
+-q, --quality=MODE         choose the quality of the palette:
+                             auto, high, low, full (default: auto)
+-w, --width=WIDTH          resize the image to WIDTH, as pixels (`200`, `200px`),
+                           a percentage (`50%`) or `auto` (default: auto)
+-h, --height=HEIGHT        resize the image to HEIGHT, like WIDTH
+-I, --high-color           use high color output, ignoring -p and -q
+-P, --penetrate            split the output into packets that penetrate
+                           GNU Screen
+-H, --help                 show this help
+-V, --version              show the version
+";
+
+const OPTIONS: &[OptSpec] = &[
+    OptSpec::value('o', "outfile"),
+    OptSpec::flag('7', "7bit-mode"),
+    OptSpec::flag('8', "8bit-mode"),
+    OptSpec::value('p', "colors"),
+    OptSpec::value('d', "diffusion"),
+    OptSpec::value('f', "find-largest"),
+    OptSpec::value('s', "select-color"),
+    OptSpec::value('q', "quality"),
+    OptSpec::value('w', "width"),
+    OptSpec::value('h', "height"),
+    OptSpec::flag('I', "high-color"),
+    OptSpec::flag('P', "penetrate"),
+    OptSpec::flag('H', "help"),
+    OptSpec::flag('V', "version"),
+];
+
+/// Runs the encoder with the given arguments.
+pub(super) fn run(args: Vec<String>) -> CliResult<()> {
+    let mut options = Options::default();
+    let mut files = Vec::new();
+    for arg in parse_args(args, OPTIONS)? {
+        let (short, value) = match arg {
+            Arg::Free(file) => {
+                files.push(file);
+                continue;
+            }
+            Arg::Opt(short, value) => (short, value.unwrap_or_default()),
+        };
+        options.palette_options |= matches!(short, 'p' | 'f' | 's' | 'q' | 'I');
+        match short {
+            'o' => options.outfile = Some(value),
+            '7' => options.control_8bit = false,
+            '8' => options.control_8bit = true,
+            'p' => options.colors = parse_colors(&value)?,
+            'd' => options.diffuse = parse_diffusion(&value)?,
+            'f' => options.largest = parse_largest(&value)?,
+            's' => options.rep = parse_rep(&value)?,
+            'q' => options.quality = parse_quality(&value)?,
+            'w' => options.width = Length::parse(&value)?,
+            'h' => options.height = Length::parse(&value)?,
+            'I' => options.high_color = true,
+            'P' => options.penetrate = true,
+            'H' => {
+                print!("{USAGE}");
+                return Ok(());
+            }
+            _ => {
+                println!("sixela {}", env!("CARGO_PKG_VERSION"));
+                return Ok(());
+            }
+        }
+    }
+    if files.is_empty() {
+        files.push("-".into());
+    }
+
+    let mut output = open_output(options.outfile.as_deref())?;
+    for file in &files {
+        let mut image =
+            LoadedImage::load(&read_input(file)?).map_err(|e| format!["{file}: {e}"])?;
+        if options.palette_options {
+            expand_gray(&mut image);
+        }
+        options
+            .sixel(&image)
+            .build_to(&mut output)
+            .map_err(|e| format!["{file}: {e}"])?;
+    }
+    output.flush().map_err(|e| e.to_string())
+}
+
+/// The encoding options.
+#[derive(Clone, Debug, Default)]
+struct Options {
+    outfile: Option<String>,
+    control_8bit: bool,
+    colors: Option<u16>,
+    diffuse: Diffusion,
+    largest: LargestDim,
+    rep: RepColor,
+    quality: Quality,
+    width: Length,
+    height: Length,
+    high_color: bool,
+    penetrate: bool,
+    /// Whether any option that affects the palette was given.
+    palette_options: bool,
+}
+
+impl Options {
+    /// Returns a new sixel builder for the `image` with the options.
+    fn sixel<'a>(&self, image: &'a LoadedImage) -> Sixel<'a> {
+        let quality = if self.high_color {
+            Quality::HighColor
+        } else {
+            self.quality
+        };
+        let mut sixel = image
+            .sixel()
+            .control_8bit(self.control_8bit)
+            .diffuse(self.diffuse)
+            .largest(self.largest)
+            .rep(self.rep)
+            .quality(quality)
+            .penetrate(self.penetrate);
+        if let Some(colors) = self.colors {
+            sixel = sixel.colors(colors);
+        }
+        let (width, height) = (image.width as i32, image.height as i32);
+        let scaled = |size: i32, to: i32, from: i32| {
+            ((size as i64 * to as i64 + from as i64 / 2) / from as i64) as i32
+        };
+        let size = match (self.width.resolve(width), self.height.resolve(height)) {
+            (None, None) => None,
+            (Some(w), None) => Some((w, scaled(height, w, width))),
+            (None, Some(h)) => Some((scaled(width, h, height), h)),
+            (Some(w), Some(h)) => Some((w, h)),
+        };
+        if let Some((w, h)) = size {
+            sixel = sixel.resize(w.max(1), h.max(1), ResampleMethod::default());
+        }
+        sixel
+    }
+}
+
+/// Expands the gray levels of a `G8` image into `RGB888` pixels.
+///
+/// The grayscale images are otherwise encoded with the grayscale palette,
+/// ignoring the options that affect the palette.
+fn expand_gray(image: &mut LoadedImage) {
+    if image.pixelformat == PixelFormat::G8 {
+        image.pixels = image.pixels.iter().flat_map(|&gray| [gray; 3]).collect();
+        image.pixelformat = PixelFormat::RGB888;
+    }
+}
+
+/// A length of the output image.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Length {
+    /// Derived from the other dimension, keeping the aspect ratio.
+    #[default]
+    Auto,
+    /// A number of pixels.
+    Pixels(i32),
+    /// A percentage of the original length.
+    Percent(i32),
+}
+
+impl Length {
+    fn parse(value: &str) -> CliResult<Self> {
+        let number = |n: &str| n.parse().ok().filter(|&n| n > 0);
+        let length = if value == "auto" {
+            Some(Self::Auto)
+        } else if let Some(percent) = value.strip_suffix('%') {
+            number(percent).map(Self::Percent)
+        } else {
+            number(value.strip_suffix("px").unwrap_or(value)).map(Self::Pixels)
+        };
+        length.ok_or(format!["invalid length: {value}"])
+    }
+
+    /// Returns the length in pixels for an image of the `original` length, or `None` if auto.
+    fn resolve(self, original: i32) -> Option<i32> {
+        match self {
+            Self::Auto => None,
+            Self::Pixels(pixels) => Some(pixels),
+            Self::Percent(percent) => Some((original as i64 * percent as i64 / 100) as i32),
+        }
+    }
+}
+
+fn parse_colors(value: &str) -> CliResult<Option<u16>> {
+    match value.parse() {
+        Ok(colors @ 1..=256) => Ok(Some(colors)),
+        _ => Err(format!["invalid number of colors: {value}"]),
+    }
+}
+
+fn parse_diffusion(value: &str) -> CliResult<Diffusion> {
+    Ok(match value {
+        "auto" => Diffusion::Auto,
+        "none" => Diffusion::None,
+        "atkinson" => Diffusion::Atkinson,
+        "fs" => Diffusion::FS,
+        "jajuni" => Diffusion::JaJuNi,
+        "stucki" => Diffusion::Stucki,
+        "burkes" => Diffusion::Burkes,
+        "a_dither" => Diffusion::ADither,
+        "x_dither" => Diffusion::XDither,
+        "bayer2" => Diffusion::Bayer2,
+        "bayer4" => Diffusion::Bayer4,
+        "bayer8" => Diffusion::Bayer8,
+        "blue_noise" => Diffusion::BlueNoise,
+        "sierra3" => Diffusion::Sierra3,
+        "sierra2" => Diffusion::Sierra2,
+        "sierra_lite" => Diffusion::SierraLite,
+        "stevenson_arce" => Diffusion::StevensonArce,
+        _ => return Err(format!["invalid diffusion type: {value}"]),
+    })
+}
+
+fn parse_largest(value: &str) -> CliResult<LargestDim> {
+    Ok(match value {
+        "auto" => LargestDim::Auto,
+        "norm" => LargestDim::Norm,
+        "lum" => LargestDim::Lum,
+        _ => return Err(format!["invalid find type: {value}"]),
+    })
+}
+
+fn parse_rep(value: &str) -> CliResult<RepColor> {
+    Ok(match value {
+        "auto" => RepColor::Auto,
+        "center" => RepColor::Center,
+        "average" => RepColor::AverageColors,
+        "histogram" => RepColor::AveragePixels,
+        _ => return Err(format!["invalid select type: {value}"]),
+    })
+}
+
+fn parse_quality(value: &str) -> CliResult<Quality> {
+    Ok(match value {
+        "auto" => Quality::Auto,
+        "high" => Quality::High,
+        "low" => Quality::Low,
+        "full" => Quality::Full,
+        _ => return Err(format!["invalid quality mode: {value}"]),
+    })
+}
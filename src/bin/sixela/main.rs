@@ -0,0 +1,143 @@
+// sixela::bin::sixela
+//
+//! The `sixela` command line tool.
+//
+// TOC
+// - fn main
+// - struct OptSpec
+// - enum Arg
+// - fn parse_args
+// - fn read_input
+// - fn open_output
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    process::ExitCode,
+};
+
+mod img2sixel;
+mod sixel2img;
+
+/// The result of a command, with an error message to show.
+type CliResult<T> = Result<T, String>;
+
+fn main() -> ExitCode {
+    let args: CliResult<Vec<String>> = std::env::args_os()
+        .skip(1)
+        .map(|arg| arg.into_string().map_err(|arg| format!["invalid argument: {arg:?}"]))
+        .collect();
+    let result = args.and_then(|mut args| match args.first().map(String::as_str) {
+        Some("sixel2img") => sixel2img::run(args.split_off(1)),
+        _ => img2sixel::run(args),
+    });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("sixela: {message}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// A command line option, with its short and long names.
+#[derive(Clone, Copy, Debug)]
+struct OptSpec {
+    short: char,
+    long: &'static str,
+    /// Whether the option takes a value.
+    value: bool,
+}
+
+impl OptSpec {
+    const fn flag(short: char, long: &'static str) -> Self {
+        Self { short, long, value: false }
+    }
+    const fn value(short: char, long: &'static str) -> Self {
+        Self { short, long, value: true }
+    }
+}
+
+/// A parsed command line argument.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Arg {
+    /// An option, by its short name, with its value if it takes one.
+    Opt(char, Option<String>),
+    /// A free argument, like a file name.
+    Free(String),
+}
+
+/// Parses the arguments according to the option `specs`.
+///
+/// The options take their values either attached (`-p16`, `--colors=16`)
+/// or as the next argument (`-p 16`, `--colors 16`), and the flags can be
+/// grouped (`-8P`). A lone `-` is a free argument, and `--` ends the options.
+fn parse_args(args: Vec<String>, specs: &[OptSpec]) -> CliResult<Vec<Arg>> {
+    let mut parsed = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            parsed.extend(args.by_ref().map(Arg::Free));
+        } else if let Some(long) = arg.strip_prefix("--") {
+            let (name, attached) = match long.split_once('=') {
+                Some((name, value)) => (name, Some(value.to_string())),
+                None => (long, None),
+            };
+            let spec = specs
+                .iter()
+                .find(|spec| spec.long == name)
+                .ok_or_else(|| format!["unknown option: --{name}"])?;
+            let value = match (spec.value, attached) {
+                (true, Some(value)) => Some(value),
+                (true, None) => Some(args.next().ok_or(format!["missing value for --{name}"])?),
+                (false, Some(_)) => return Err(format!["unexpected value for --{name}"]),
+                (false, None) => None,
+            };
+            parsed.push(Arg::Opt(spec.short, value));
+        } else if let Some(shorts) = arg.strip_prefix('-').filter(|s| !s.is_empty()) {
+            for (i, short) in shorts.char_indices() {
+                let spec = specs
+                    .iter()
+                    .find(|spec| spec.short == short)
+                    .ok_or_else(|| format!["unknown option: -{short}"])?;
+                if spec.value {
+                    let rest = &shorts[i + short.len_utf8()..];
+                    let value = if rest.is_empty() {
+                        args.next().ok_or(format!["missing value for -{short}"])?
+                    } else {
+                        rest.to_string()
+                    };
+                    parsed.push(Arg::Opt(short, Some(value)));
+                    break;
+                }
+                parsed.push(Arg::Opt(short, None));
+            }
+        } else {
+            parsed.push(Arg::Free(arg));
+        }
+    }
+    Ok(parsed)
+}
+
+/// Reads the whole input file, or the standard input if it's `-`.
+fn read_input(path: &str) -> CliResult<Vec<u8>> {
+    let mut bytes = Vec::new();
+    if path == "-" {
+        io::stdin().lock().read_to_end(&mut bytes)
+    } else {
+        File::open(path).and_then(|mut file| file.read_to_end(&mut bytes))
+    }
+    .map_err(|e| format!["{path}: {e}"])?;
+    Ok(bytes)
+}
+
+/// Opens the output file, or the standard output if there's none or it's `-`.
+fn open_output(path: Option<&str>) -> CliResult<Box<dyn Write>> {
+    match path {
+        None | Some("-") => Ok(Box::new(BufWriter::new(io::stdout().lock()))),
+        Some(path) => {
+            let file = File::create(path).map_err(|e| format!["{path}: {e}"])?;
+            Ok(Box::new(BufWriter::new(file)))
+        }
+    }
+}
@@ -0,0 +1,184 @@
+// sixela::bin::sixela::sixel2img
+//
+//! Decodes sixel streams into PNM or PNG images.
+//
+// TOC
+// - const USAGE
+// - fn run
+// - enum ImageFormat
+// - fn write_image
+// - fn frame_path
+// - fn parse_color
+
+use super::{open_output, parse_args, read_input, Arg, CliResult, OptSpec};
+use sixela::SixelImage;
+use std::io::Write;
+
+const USAGE: &str = "\
+Usage: sixela sixel2img [Options] [sixelfile]
+       sixela sixel2img [Options] < sixelfile
+
+Decodes a sixel stream into a PNM or PNG image.
+
+Options:
+-o, --outfile=OUTFILE      write to OUTFILE instead of the standard output
+-F, --format=FORMAT        choose the image format: pnm, png
+                           (default: from the OUTFILE extension, or pnm)
+-b, --background=COLOR     fill the unpainted pixels with COLOR, as `#rrggbb`
+                           (default: transparent for PNG, black for PNM)
+-n, --frame=N              extract the Nth image of the stream (default: 1)
+-a, --all-frames           extract all the images of the stream, numbering
+                           the OUTFILE names, or concatenating the PNM images
+-H, --help                 show this help
+";
+
+const OPTIONS: &[OptSpec] = &[
+    OptSpec::value('o', "outfile"),
+    OptSpec::value('F', "format"),
+    OptSpec::value('b', "background"),
+    OptSpec::value('n', "frame"),
+    OptSpec::flag('a', "all-frames"),
+    OptSpec::flag('H', "help"),
+];
+
+/// Runs the decoder with the given arguments.
+pub(super) fn run(args: Vec<String>) -> CliResult<()> {
+    let (mut outfile, mut format, mut background) = (None, None, None);
+    let (mut frame, mut all_frames) = (1, false);
+    let mut files = Vec::new();
+    for arg in parse_args(args, OPTIONS)? {
+        match arg {
+            Arg::Free(file) => files.push(file),
+            Arg::Opt('o', value) => outfile = value,
+            Arg::Opt('F', Some(value)) => format = Some(ImageFormat::parse(&value)?),
+            Arg::Opt('b', Some(value)) => background = Some(parse_color(&value)?),
+            Arg::Opt('n', Some(value)) => {
+                frame = value
+                    .parse()
+                    .ok()
+                    .filter(|&n| n > 0)
+                    .ok_or(format!["invalid frame number: {value}"])?;
+            }
+            Arg::Opt('a', _) => all_frames = true,
+            _ => {
+                print!("{USAGE}");
+                return Ok(());
+            }
+        }
+    }
+    let file = match files.as_slice() {
+        [] => "-",
+        [file] => file,
+        _ => return Err("only one sixel file can be decoded at a time".into()),
+    };
+    let format = format.unwrap_or_else(|| match outfile.as_deref() {
+        Some(path) if path.to_ascii_lowercase().ends_with(".png") => ImageFormat::Png,
+        _ => ImageFormat::Pnm,
+    });
+
+    let images = SixelImage::decode_all(&read_input(file)?).map_err(|e| format!["{file}: {e}"])?;
+    if images.is_empty() {
+        return Err(format!["{file}: no sixel images found"]);
+    }
+    if !all_frames {
+        let image = images
+            .get(frame - 1)
+            .ok_or(format!["{file}: frame {frame} not found, of {}", images.len()])?;
+        let mut output = open_output(outfile.as_deref())?;
+        write_image(&mut output, image, format, background)?;
+        return output.flush().map_err(|e| e.to_string());
+    }
+    match outfile.as_deref() {
+        // numbered files
+        Some(path) if path != "-" => {
+            for (i, image) in images.iter().enumerate() {
+                let path = frame_path(path, i + 1);
+                let mut output = open_output(Some(&path))?;
+                write_image(&mut output, image, format, background)?;
+                output.flush().map_err(|e| format!["{path}: {e}"])?;
+            }
+            Ok(())
+        }
+        // a stream of concatenated images
+        _ if format == ImageFormat::Pnm => {
+            let mut output = open_output(None)?;
+            for image in &images {
+                write_image(&mut output, image, format, background)?;
+            }
+            output.flush().map_err(|e| e.to_string())
+        }
+        _ => Err("all the PNG frames need an output file".into()),
+    }
+}
+
+/// The format of the decoded images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ImageFormat {
+    /// A binary PPM image (`P6`).
+    Pnm,
+    Png,
+}
+
+impl ImageFormat {
+    fn parse(value: &str) -> CliResult<Self> {
+        match value {
+            "pnm" | "ppm" => Ok(Self::Pnm),
+            "png" => Ok(Self::Png),
+            _ => Err(format!["invalid image format: {value}"]),
+        }
+    }
+}
+
+/// Writes the `image` in the given `format`.
+///
+/// The unpainted pixels are filled with the `background` color, if any. Otherwise
+/// they are transparent in PNG images, and black in PNM images, which have no alpha.
+fn write_image(
+    output: &mut dyn Write,
+    image: &SixelImage,
+    format: ImageFormat,
+    background: Option<[u8; 3]>,
+) -> CliResult<()> {
+    let (width, height) = (image.width, image.height);
+    match format {
+        ImageFormat::Pnm => {
+            let pixels = image.to_rgb888(background.unwrap_or_default());
+            write!(output, "P6\n{width} {height}\n255\n")
+                .and_then(|()| output.write_all(&pixels))
+                .map_err(|e| e.to_string())
+        }
+        ImageFormat::Png => {
+            let (pixels, color) = match background {
+                Some(background) => (image.to_rgb888(background), png::ColorType::Rgb),
+                None => (image.to_rgba8888(), png::ColorType::Rgba),
+            };
+            let mut encoder = png::Encoder::new(output, width as u32, height as u32);
+            encoder.set_color(color);
+            encoder.set_depth(png::BitDepth::Eight);
+            encoder
+                .write_header()
+                .and_then(|mut writer| writer.write_image_data(&pixels))
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// Returns the `path` with the frame `number` inserted before its extension.
+fn frame_path(path: &str, number: usize) -> String {
+    match path.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() && !ext.contains('/') => {
+            format!["{stem}-{number}.{ext}"]
+        }
+        _ => format!["{path}-{number}"],
+    }
+}
+
+/// Parses a `#rrggbb` color, with an optional `#`.
+fn parse_color(value: &str) -> CliResult<[u8; 3]> {
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    let channel = |i: usize| hex.get(i..i + 2).and_then(|c| u8::from_str_radix(c, 16).ok());
+    match (hex.len(), channel(0), channel(2), channel(4)) {
+        (6, Some(red), Some(green), Some(blue)) => Ok([red, green, blue]),
+        _ => Err(format!["invalid color: {value}"]),
+    }
+}
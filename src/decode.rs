@@ -0,0 +1,473 @@
+// sixela::decode
+//
+// TOC
+// - struct SixelImage
+// - struct Decoder
+
+use crate::{dither::pal_vt340_color, SixelError, SixelResult, SIXEL_PALETTE_MAX};
+use alloc::vec;
+use devela::Vec;
+
+/// Maximum number of pixels a decoded image is allowed to have.
+const DECODE_PIXELS_MAX: usize = 1 << 26;
+
+const ESC: u8 = 0x1B;
+const DCS_8BIT: u8 = 0x90;
+const ST_8BIT: u8 = 0x9C;
+
+/// A sixel image decoded from a DCS sixel sequence.
+///
+/// Holds the palette, the dimensions and one palette index per pixel.
+///
+/// # Example
+/// ```
+/// # use sixela::{Sixel, SixelImage};
+/// // 8x12 pixels, red on top and blue at the bottom
+/// let rgb: Vec<u8> = (0..8 * 12)
+///     .flat_map(|i| if i < 8 * 6 { [255, 0, 0] } else { [0, 0, 255] })
+///     .collect();
+/// let sixel = Sixel::with_bytes_size(&rgb, 8, 12).build().unwrap();
+///
+/// let image = SixelImage::decode(sixel.as_bytes()).unwrap();
+/// assert_eq![(image.width, image.height), (8, 12)];
+/// assert![image.painted.iter().all(|&p| p)];
+/// // colors only differ by the palette quantization error
+/// let decoded = image.to_rgb888([0; 3]);
+/// assert![decoded.iter().zip(&rgb).all(|(a, b)| a.abs_diff(*b) <= 8)];
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SixelImage {
+    /// The width in pixels.
+    pub width: usize,
+    /// The height in pixels.
+    pub height: usize,
+    /// The palette, with one RGB triplet per color register.
+    pub palette: Vec<[u8; 3]>,
+    /// The palette index of each pixel, in row-major order.
+    pub pixels: Vec<u8>,
+    /// Whether each pixel has been painted by the sixel data.
+    ///
+    /// Unpainted pixels show the background.
+    pub painted: Vec<bool>,
+    /// The DCS parameters (P1, P2, P3). Omitted parameters are 0.
+    pub params: [u16; 3],
+    /// The pixel aspect ratio as (numerator, denominator) (Pan, Pad).
+    ///
+    /// Taken from the raster attributes, or else derived from P1.
+    pub aspect: (u16, u16),
+}
+
+impl SixelImage {
+    /// Decodes the first sixel image found in `bytes`.
+    ///
+    /// Both 7-bit (`ESC P` … `ESC \`) and 8-bit (`0x90` … `0x9C`) controls are
+    /// accepted, and a missing string terminator at the end of the data is tolerated.
+    ///
+    /// # Errors
+    /// Returns [`BadInput`][SixelError::BadInput] if no sixel sequence is found,
+    /// or if the image would be too large.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{SixelError, SixelImage};
+    /// // raster attributes larger than the painted area, and two HLS colors
+    /// let sixel = b"\x1bPq\"1;1;8;12#1;1;0;50;100!3~#2;1;120;50;100~$-#1!2A\x1b\\";
+    /// let image = SixelImage::decode(sixel).unwrap();
+    /// assert_eq![(image.width, image.height, image.aspect), (8, 12, (1, 1))];
+    /// assert_eq![image.palette[1..], [[0, 0, 255], [255, 0, 0]]];
+    /// // the repeated sixels paint 3 columns, before the single one
+    /// assert_eq![image.pixels[..5], [1, 1, 1, 2, 0]];
+    /// assert_eq![image.painted[..5], [true, true, true, true, false]];
+    /// // the second band only paints its second row
+    /// assert_eq![image.painted[7 * 8..7 * 8 + 3], [true, true, false]];
+    /// assert_eq![image.painted.iter().filter(|&&p| p).count(), 4 * 6 + 2];
+    ///
+    /// // without raster attributes the size is the painted area,
+    /// // and the aspect ratio comes from P1
+    /// let image = SixelImage::decode(b"\x1bP2q!5~\x1b\\").unwrap();
+    /// assert_eq![(image.width, image.height, image.aspect), (5, 6, (5, 1))];
+    ///
+    /// // images of more than 2^26 pixels are rejected
+    /// let too_wide = b"\x1bPq!100000000~\x1b\\";
+    /// assert_eq![SixelImage::decode(too_wide), Err(SixelError::BadInput)];
+    /// let too_big = b"\x1bPq\"1;1;10000;10000~\x1b\\";
+    /// assert_eq![SixelImage::decode(too_big), Err(SixelError::BadInput)];
+    ///
+    /// // out of range color parameters are clamped
+    /// let image = SixelImage::decode(b"\x1bPq#1;1;0;2000000000;100~\x1b\\").unwrap();
+    /// assert_eq![image.palette[1], [255, 255, 255]];
+    /// let image = SixelImage::decode(b"\x1bPq#1;2;4000000000;0;0~\x1b\\").unwrap();
+    /// assert_eq![image.palette[1], [255, 0, 0]];
+    /// ```
+    pub fn decode(bytes: &[u8]) -> SixelResult<Self> {
+        let mut pos = 0;
+        while let Some(start) = find_dcs(bytes, pos) {
+            match Decoder::new().decode(bytes, start)? {
+                (Some(image), _) => return Ok(image),
+                (None, end) => pos = end,
+            }
+        }
+        Err(SixelError::BadInput)
+    }
+
+    /// Decodes all the sixel images found in `bytes`, in order.
+    ///
+    /// Returns an empty vector if there are none.
+    ///
+    /// # Errors
+    /// Returns [`BadInput`][SixelError::BadInput] if any image would be too large.
+    pub fn decode_all(bytes: &[u8]) -> SixelResult<Vec<Self>> {
+        let mut images = Vec::new();
+        let mut pos = 0;
+        while let Some(start) = find_dcs(bytes, pos) {
+            let (image, end) = Decoder::new().decode(bytes, start)?;
+            images.extend(image);
+            pos = end;
+        }
+        Ok(images)
+    }
+
+    /// Returns the pixels as `RGB888`, filling unpainted pixels with `background`.
+    #[must_use]
+    pub fn to_rgb888(&self, background: [u8; 3]) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(self.pixels.len() * 3);
+        for (&index, &painted) in self.pixels.iter().zip(&self.painted) {
+            let color = if painted {
+                self.palette[index as usize]
+            } else {
+                background
+            };
+            rgb.extend_from_slice(&color);
+        }
+        rgb
+    }
+
+    /// Returns the pixels as `RGBA8888`, with unpainted pixels fully transparent.
+    #[must_use]
+    pub fn to_rgba8888(&self) -> Vec<u8> {
+        let mut rgba = Vec::with_capacity(self.pixels.len() * 4);
+        for (&index, &painted) in self.pixels.iter().zip(&self.painted) {
+            if painted {
+                let [r, g, b] = self.palette[index as usize];
+                rgba.extend_from_slice(&[r, g, b, 255]);
+            } else {
+                rgba.extend_from_slice(&[0; 4]);
+            }
+        }
+        rgba
+    }
+}
+
+/// Returns the position right after the next DCS introducer, starting at `pos`.
+fn find_dcs(bytes: &[u8], mut pos: usize) -> Option<usize> {
+    while pos < bytes.len() {
+        match bytes[pos] {
+            DCS_8BIT => return Some(pos + 1),
+            ESC if bytes.get(pos + 1) == Some(&b'P') => return Some(pos + 2),
+            _ => pos += 1,
+        }
+    }
+    None
+}
+
+/// Returns the position after the string terminator found from `pos`, or the end.
+fn skip_to_st(bytes: &[u8], mut pos: usize) -> usize {
+    while pos < bytes.len() {
+        match bytes[pos] {
+            ST_8BIT => return pos + 1,
+            ESC if bytes.get(pos + 1) == Some(&b'\\') => return pos + 2,
+            _ => pos += 1,
+        }
+    }
+    pos
+}
+
+/// Returns the pixel aspect ratio (Pan, Pad) selected by the P1 parameter.
+const fn aspect_from_p1(p1: u16) -> (u16, u16) {
+    match p1 {
+        2 => (5, 1),
+        3 | 4 => (3, 1),
+        7..=9 => (1, 1),
+        _ => (2, 1),
+    }
+}
+
+/// Converts a DEC HLS color (hue 0 = blue, percentages) into RGB.
+fn hls_to_rgb(hue: i32, lum: i32, sat: i32) -> [u8; 3] {
+    let percent_to_byte = |v: i32| ((v.clamp(0, 100) * 255 + 50) / 100) as u8;
+    if sat == 0 {
+        let v = percent_to_byte(lum);
+        return [v, v, v];
+    }
+    // DEC hues are rotated 120 degrees from the usual ones (red is 120)
+    let hue = (hue + 240).rem_euclid(360);
+    let q = if lum < 50 {
+        lum * (100 + sat) / 100
+    } else {
+        lum + sat - lum * sat / 100
+    };
+    let p = 2 * lum - q;
+    let channel = |t: i32| {
+        let t = t.rem_euclid(360);
+        let v = if t < 60 {
+            p + (q - p) * t / 60
+        } else if t < 180 {
+            q
+        } else if t < 240 {
+            p + (q - p) * (240 - t) / 60
+        } else {
+            p
+        };
+        percent_to_byte(v)
+    };
+    [channel(hue + 120), channel(hue), channel(hue - 120)]
+}
+
+/// The state of a sixel decoding pass.
+struct Decoder {
+    /// Color registers.
+    registers: [[u8; 3]; SIXEL_PALETTE_MAX],
+    /// The highest color register used or defined.
+    max_register: usize,
+    /// Currently selected color register.
+    color: u8,
+    /// Allocated width of the pixel buffers.
+    stride: usize,
+    /// Allocated height of the pixel buffers.
+    rows: usize,
+    pixels: Vec<u8>,
+    painted: Vec<bool>,
+    /// Rightmost and bottommost painted extents.
+    extent: (usize, usize),
+    /// Size declared by the raster attributes.
+    raster: (usize, usize),
+    aspect: Option<(u16, u16)>,
+}
+
+impl Decoder {
+    fn new() -> Self {
+        let mut registers = [[0; 3]; SIXEL_PALETTE_MAX];
+        // VT340 default color map, which stores color 0 last
+        for (n, reg) in registers.iter_mut().take(16).enumerate() {
+            let i = ((n + 15) % 16) * 3;
+            *reg = [pal_vt340_color[i], pal_vt340_color[i + 1], pal_vt340_color[i + 2]];
+        }
+        Self {
+            registers,
+            max_register: 0,
+            color: 0,
+            stride: 0,
+            rows: 0,
+            pixels: Vec::new(),
+            painted: Vec::new(),
+            extent: (0, 0),
+            raster: (0, 0),
+            aspect: None,
+        }
+    }
+
+    /// Decodes the sequence whose parameters start at `pos`.
+    ///
+    /// Returns the image, unless it's not a sixel sequence,
+    /// and the position after the string terminator.
+    fn decode(mut self, bytes: &[u8], mut pos: usize) -> SixelResult<(Option<SixelImage>, usize)> {
+        /* DCS P1 ; P2 ; P3 q */
+        let mut params = [0u16; 3];
+        let mut nparam = 0;
+        let mut is_sixel = false;
+        while pos < bytes.len() {
+            let byte = bytes[pos];
+            pos += 1;
+            match byte {
+                b'0'..=b'9' if nparam < 3 => {
+                    let digit = (byte - b'0') as u16;
+                    params[nparam] = params[nparam].saturating_mul(10).saturating_add(digit);
+                }
+                b';' => nparam += 1,
+                /* final character */
+                b'@'..=b'~' => {
+                    is_sixel = byte == b'q';
+                    break;
+                }
+                _ => {}
+            }
+        }
+        if !is_sixel {
+            return Ok((None, skip_to_st(bytes, pos)));
+        }
+
+        let (mut x, mut y) = (0, 0);
+        let mut repeat = 1;
+        while pos < bytes.len() {
+            let byte = bytes[pos];
+            pos += 1;
+            match byte {
+                /* sixel data */
+                b'?'..=b'~' => {
+                    self.put_sixel(x, y, repeat, byte - b'?')?;
+                    x += repeat;
+                    repeat = 1;
+                }
+                /* DECGRI Graphics Repeat Introducer ! Pn Ch */
+                b'!' => {
+                    let (args, _, next) = parse_params::<1>(bytes, pos);
+                    repeat = (args[0] as usize).max(1);
+                    pos = next;
+                }
+                /* DECGCI Graphics Color Introducer # Pc ; Pu; Px; Py; Pz */
+                b'#' => {
+                    let (args, count, next) = parse_params::<5>(bytes, pos);
+                    pos = next;
+                    let reg = args[0] as usize % SIXEL_PALETTE_MAX;
+                    if count >= 5 {
+                        let [_, pu, px, py, pz] = args;
+                        // clamp before any arithmetic, so that huge values can't overflow
+                        let (py, pz) = (py.min(100) as i32, pz.min(100) as i32);
+                        match pu {
+                            1 => self.registers[reg] = hls_to_rgb(px.min(360) as i32, py, pz),
+                            2 => {
+                                let px = px.min(100) as i32;
+                                let to_byte = |v: i32| ((v * 255 + 50) / 100) as u8;
+                                self.registers[reg] = [to_byte(px), to_byte(py), to_byte(pz)];
+                            }
+                            _ => {}
+                        }
+                    }
+                    self.color = reg as u8;
+                    self.max_register = self.max_register.max(reg);
+                }
+                /* DECGCR Graphics Carriage Return */
+                b'$' => x = 0,
+                /* DECGNL Graphics Next Line */
+                b'-' => {
+                    x = 0;
+                    y += 6;
+                }
+                /* DECGRA Set Raster Attributes " Pan; Pad; Ph; Pv */
+                b'"' => {
+                    let (args, count, next) = parse_params::<4>(bytes, pos);
+                    pos = next;
+                    if count >= 2 && args[0] > 0 && args[1] > 0 {
+                        self.aspect = Some((args[0] as u16, args[1] as u16));
+                    }
+                    if count >= 4 {
+                        self.raster = (args[2] as usize, args[3] as usize);
+                    }
+                }
+                /* String Terminator */
+                ST_8BIT => break,
+                ESC => {
+                    if bytes.get(pos) == Some(&b'\\') {
+                        pos += 1;
+                    }
+                    break;
+                }
+                _ => {}
+            }
+        }
+
+        let width = self.extent.0.max(self.raster.0);
+        let height = self.extent.1.max(self.raster.1);
+        self.reserve(width, height)?;
+        let mut pixels = Vec::with_capacity(width * height);
+        let mut painted = Vec::with_capacity(width * height);
+        for row in 0..height {
+            let start = row * self.stride;
+            pixels.extend_from_slice(&self.pixels[start..start + width]);
+            painted.extend_from_slice(&self.painted[start..start + width]);
+        }
+        let image = SixelImage {
+            width,
+            height,
+            palette: self.registers[..=self.max_register].to_vec(),
+            pixels,
+            painted,
+            params,
+            aspect: self.aspect.unwrap_or(aspect_from_p1(params[0])),
+        };
+        Ok((Some(image), pos))
+    }
+
+    /// Paints `count` columns of the sixel `bits` at (`x`, `y`).
+    fn put_sixel(&mut self, x: usize, y: usize, count: usize, bits: u8) -> SixelResult<()> {
+        if bits == 0 {
+            return Ok(());
+        }
+        let last_bit = 7 - bits.leading_zeros() as usize;
+        self.reserve(x + count, y + last_bit + 1)?;
+        for bit in 0..=last_bit {
+            if bits & (1 << bit) != 0 {
+                let start = (y + bit) * self.stride + x;
+                self.pixels[start..start + count].fill(self.color);
+                self.painted[start..start + count].fill(true);
+            }
+        }
+        self.extent.0 = self.extent.0.max(x + count);
+        self.extent.1 = self.extent.1.max(y + last_bit + 1);
+        self.max_register = self.max_register.max(self.color as usize);
+        Ok(())
+    }
+
+    /// Makes sure the pixel buffers can hold an image of the given size.
+    fn reserve(&mut self, width: usize, height: usize) -> SixelResult<()> {
+        if width <= self.stride && height <= self.rows {
+            return Ok(());
+        }
+        // grow geometrically, but never below the declared raster size
+        let grow = |needed: usize, current: usize, raster: usize| {
+            if needed <= current {
+                current
+            } else {
+                needed.max(current.saturating_mul(2)).max(raster)
+            }
+        };
+        let stride = grow(width, self.stride, self.raster.0);
+        let rows = grow(height, self.rows, self.raster.1);
+        match stride.checked_mul(rows) {
+            Some(size) if size <= DECODE_PIXELS_MAX => {}
+            _ => return Err(SixelError::BadInput),
+        }
+        let mut pixels = vec![0; stride * rows];
+        let mut painted = vec![false; stride * rows];
+        for row in 0..self.rows {
+            let (src, dst) = (row * self.stride, row * stride);
+            pixels[dst..dst + self.stride].copy_from_slice(&self.pixels[src..src + self.stride]);
+            painted[dst..dst + self.stride].copy_from_slice(&self.painted[src..src + self.stride]);
+        }
+        self.pixels = pixels;
+        self.painted = painted;
+        self.stride = stride;
+        self.rows = rows;
+        Ok(())
+    }
+}
+
+/// Parses up to `N` numeric parameters separated by `;`, starting at `pos`.
+///
+/// Returns the parameters, how many were found, and the position after them.
+fn parse_params<const N: usize>(bytes: &[u8], mut pos: usize) -> ([u32; N], usize, usize) {
+    let mut args = [0u32; N];
+    let mut count = 0;
+    let mut in_number = false;
+    while pos < bytes.len() {
+        match bytes[pos] {
+            b'0'..=b'9' => {
+                if count < N {
+                    let digit = (bytes[pos] - b'0') as u32;
+                    args[count] = args[count].saturating_mul(10).saturating_add(digit);
+                }
+                in_number = true;
+            }
+            b';' => {
+                count += 1;
+                in_number = false;
+            }
+            _ => break,
+        }
+        pos += 1;
+    }
+    if in_number || count > 0 {
+        count += 1;
+    }
+    (args, count.min(N), pos)
+}
@@ -3,105 +3,214 @@
 #![allow(dead_code, reason = "crate private structs")]
 
 mod palettes;
-use palettes::*;
+pub(crate) use palettes::*;
 
 use alloc::vec;
 use devela::Vec;
 
 use crate::{
-    pixelformat::sixel_helper_normalize_pixelformat,
-    quant::{sixel_quant_apply_palette, sixel_quant_make_palette},
-    Diffusion, LargestDim, PixelFormat, Quality, RepColor, SixelError, SixelResult,
-    SIXEL_PALETTE_MAX,
+    output::check_image,
+    pixelformat::{
+        sixel_helper_blend_alpha, sixel_helper_get_alpha, sixel_helper_normalize_pixelformat,
+    },
+    quant::{sixel_quant_apply_palette, sixel_quant_make_palette, sixel_quant_refine_palette},
+    AlphaMode, ColorSpace, Diffusion, LargestDim, PixelFormat, Quality, Quantizer, RepColor,
+    SixelError, SixelResult, SIXEL_PALETTE_MAX,
 };
+/// The maximum number of pixels of the frames used to build a shared palette.
+pub(crate) const PALETTE_SAMPLE_PIXELS: usize = 1 << 22;
 
-// /// Predefined dithering modes for sixel output.
-// ///
-// /// # Adaptation
-// /// - Derived from `sixel_builtin_dither_t` in the `libsixel` C library.
-// /// - Represents various terminal and grayscale dithering options.
-// #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-// pub enum DitherMode {
-//     /// monochrome terminal with dark background
-//     MonoDark,
-//     /// monochrome terminal with light background
-//     MonoLight,
-//     /// xterm 16color
-//     XTerm16,
-//     /// xterm 256color
-//     XTerm256,
-//     /// vt340 monochrome
-//     VT340Mono,
-//     /// vt340 color
-//     VT340Color,
-//     /// 1bit grayscale
-//     G1,
-//     /// 2bit grayscale
-//     G2,
-//     /// 4bit grayscale
-//     G4,
-//     /// 8bit grayscale
-//     G8,
-// }
+// only used by `vec!`, which needs `alloc`
+#[cfg(feature = "alloc")]
+use crate::quant::CACHE_TABLE_SIZE;
+
+/// Predefined dithering modes for sixel output.
+///
+/// Each mode has a fixed palette, onto which the pixels are mapped directly,
+/// without computing a palette from the image.
+///
+/// # Adaptation
+/// - Derived from `sixel_builtin_dither_t` in the `libsixel` C library.
+/// - Represents various terminal and grayscale dithering options.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DitherMode {
+    /// Monochrome terminal with dark background.
+    ///
+    /// The dark pixels are left undrawn, and the light ones use the foreground color.
+    MonoDark,
+    /// Monochrome terminal with light background.
+    ///
+    /// The light pixels are left undrawn, and the dark ones use the foreground color.
+    MonoLight,
+    /// xterm 16 colors.
+    XTerm16,
+    /// xterm 256 colors.
+    XTerm256,
+    /// VT340 monochrome, with 16 gray levels.
+    VT340Mono,
+    /// VT340 color, with 16 colors.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherMode, Sixel};
+    /// let red = [204, 35, 35];
+    /// let sixel = Sixel::with_bytes_size(&red, 1, 1).palette_preset(DitherMode::VT340Color);
+    /// let sixel = sixel.build().unwrap();
+    /// assert![sixel.contains("#1;2;80;14;14") && sixel.ends_with("#1@\x1b\\")];
+    /// ```
+    VT340Color,
+    /// 1-bit grayscale.
+    G1,
+    /// 2-bit grayscale.
+    G2,
+    /// 4-bit grayscale.
+    G4,
+    /// 8-bit grayscale.
+    G8,
+}
+
+impl DitherMode {
+    /// Returns the palette of this mode, as consecutive RGB triplets.
+    #[must_use]
+    pub const fn palette(self) -> &'static [u8] {
+        match self {
+            DitherMode::MonoDark => &pal_mono_dark,
+            DitherMode::MonoLight => &pal_mono_light,
+            DitherMode::XTerm16 => pal_xterm256.split_at(16 * 3).0,
+            DitherMode::XTerm256 => &pal_xterm256,
+            DitherMode::VT340Mono => &pal_vt340_mono,
+            DitherMode::VT340Color => &pal_vt340_color,
+            DitherMode::G1 => &pal_gray_1bit,
+            DitherMode::G2 => &pal_gray_2bit,
+            DitherMode::G4 => &pal_gray_4bit,
+            DitherMode::G8 => &pal_gray_8bit,
+        }
+    }
+
+    /// Returns the grayscale mode whose palette indices match the levels of `format`.
+    pub(crate) const fn gray(format: PixelFormat) -> Option<Self> {
+        match format {
+            PixelFormat::G1 => Some(DitherMode::G1),
+            PixelFormat::G2 => Some(DitherMode::G2),
+            PixelFormat::G4 => Some(DitherMode::G4),
+            PixelFormat::G8 => Some(DitherMode::G8),
+            _ => None,
+        }
+    }
+}
 
 /// Configuration for sixel dithering.
 ///
+/// Holds the palette and the methods used to build it and to map the pixels onto it.
+/// It can be reused to encode several images with [`SixelOutput`][crate::SixelOutput].
+///
+/// # Example
+/// ```
+/// # use sixela::{DitherConf, PixelFormat, SixelOutput};
+/// let rgb: Vec<u8> = (0..8 * 12).flat_map(|i| [i as u8 * 2, 0, 255 - i as u8]).collect();
+///
+/// let mut dither = DitherConf::new(16).unwrap();
+/// dither.initialize(&rgb, 8, 12, PixelFormat::RGB888).unwrap();
+///
+/// let mut output = SixelOutput::new(Vec::new());
+/// output.encode(&rgb, 8, 12, &mut dither).unwrap();
+/// assert![output.into_inner().starts_with(b"\x1bPq")];
+/// ```
 ///
 /// # Adaptation
 /// - Based on `sixel_dither_t` from the `libsixel` C library,
 ///   adapted with adjustments for idiomatic Rust usage.
 #[derive(Debug)]
-pub(crate) struct DitherConf {
+pub struct DitherConf {
     /// Palette definition.
-    pub palette: Vec<u8>,
+    pub(crate) palette: Vec<u8>,
     /// Cache table.
-    pub cachetable: Option<Vec<u16>>,
+    pub(crate) cachetable: Option<Vec<u16>>,
     /// The number of requested colors.
-    pub reqcolors: i32,
+    pub(crate) reqcolors: i32,
     /// The number of active colors.
-    pub ncolors: i32,
+    pub(crate) ncolors: i32,
     /// The number of original colors.
-    pub origcolors: i32,
+    pub(crate) origcolors: i32,
     /// Pixel is 15bpp compressible.
-    pub optimized: bool,
+    pub(crate) optimized: bool,
     /// Minimize palette size.
-    pub optimize_palette: bool,
+    pub(crate) optimize_palette: bool,
     /// For complexion correction.
-    pub complexion: i32,
+    pub(crate) complexion: i32,
     /// Do not output palette section if true.
-    pub bodyonly: bool,
+    pub(crate) bodyonly: bool,
     /// Method for finding the largest dimention for splitting.
-    pub method_for_largest: LargestDim,
+    pub(crate) method_for_largest: LargestDim,
     /// Method for choosing a color from the box.
-    pub method_for_rep: RepColor,
+    pub(crate) method_for_rep: RepColor,
+    /// Method of color quantization.
+    pub(crate) quantizer: Quantizer,
+    /// Color space for splitting the boxes and looking up the colors.
+    pub(crate) color_space: ColorSpace,
+    /// The maximum number of k-means iterations refining the palette.
+    pub(crate) refine_iterations: u32,
+    /// The mean squared error before and after refining the palette.
+    pub(crate) palette_mse: Option<(f64, f64)>,
     /// Method for diffusing
-    pub method_for_diffuse: Diffusion,
+    pub(crate) method_for_diffuse: Diffusion,
+    /// Diffuse the error in linear light.
+    pub(crate) linear_diffusion: bool,
+    /// Scan the odd rows from right to left when diffusing the error.
+    pub(crate) serpentine: bool,
+    /// The percentage of the error to diffuse, from 0 to 100.
+    pub(crate) diffusion_strength: u8,
     /// Quality of histogram.
-    pub quality_mode: Quality,
+    pub(crate) quality_mode: Quality,
     /// Background color.
-    pub keycolor: i32,
+    pub(crate) keycolor: i32,
     /// Pixelformat for internal processing.
-    pub pixelformat: PixelFormat,
+    pub(crate) pixelformat: PixelFormat,
+    /// Handling of the alpha channel.
+    pub(crate) alpha_mode: AlphaMode,
+    /// The predefined mode of the fixed palette, if any.
+    pub(crate) dither_mode: Option<DitherMode>,
 }
 
 impl DitherConf {
     /// Creates a new dither configuration with the specified number of colors.
-    pub fn new(mut ncolors: i32) -> SixelResult<Self> {
-        let quality_mode = if ncolors < 0 {
-            ncolors = SIXEL_PALETTE_MAX as i32;
-            Quality::HighColor
-        } else {
-            if ncolors > SIXEL_PALETTE_MAX as i32 {
-                return Err(SixelError::BadInput);
-            }
-            if ncolors < 1 {
-                return Err(SixelError::BadInput);
-                // sixel_helper_set_additional_message(
-                // "DitherConf::new: palette ncolors must be more than 0");
-            }
-            Quality::Low
-        };
-        Ok(Self {
+    ///
+    /// # Errors
+    /// Returns [`BadArgument`][SixelError::BadArgument]
+    /// if `ncolors` is 0 or greater than 256.
+    pub fn new(ncolors: usize) -> SixelResult<Self> {
+        if !(1..=SIXEL_PALETTE_MAX).contains(&ncolors) {
+            return Err(SixelError::BadArgument);
+        }
+        Ok(Self::with_quality(ncolors as i32, Quality::Low))
+    }
+
+    /// Creates a new dither configuration for [`HighColor`][Quality::HighColor] output.
+    ///
+    /// The palette is built while encoding, so it doesn't need to be initialized.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherConf, SixelOutput};
+    /// let rgb: Vec<u8> = (0..=255).flat_map(|i| [i, 255 - i, i / 2]).collect();
+    ///
+    /// let mut dither = DitherConf::new_highcolor();
+    /// let mut output = SixelOutput::new(Vec::new());
+    /// for _frame in 0..2 {
+    ///     output.encode(&rgb, 16, 16, &mut dither).unwrap();
+    /// }
+    /// let sixel = output.into_inner();
+    /// assert_eq![sixel.windows(3).filter(|w| w == b"\x1bPq").count(), 2];
+    /// ```
+    #[must_use]
+    pub fn new_highcolor() -> Self {
+        Self::with_quality(SIXEL_PALETTE_MAX as i32, Quality::HighColor)
+    }
+
+    /// Creates a new configuration with the given number of colors and quality mode.
+    fn with_quality(ncolors: i32, quality_mode: Quality) -> Self {
+        Self {
             palette: vec![0; ncolors as usize * 3],
             cachetable: None,
             reqcolors: ncolors,
@@ -114,36 +223,44 @@ impl DitherConf {
             bodyonly: false,
             method_for_largest: LargestDim::Norm,
             method_for_rep: RepColor::Center,
+            quantizer: Quantizer::MedianCut,
+            color_space: ColorSpace::Rgb,
+            refine_iterations: 0,
+            palette_mse: None,
             method_for_diffuse: Diffusion::FS,
+            linear_diffusion: false,
+            serpentine: false,
+            diffusion_strength: 100,
             quality_mode,
             pixelformat: PixelFormat::RGB888,
-        })
-    }
-
-    // /// MAYBE
-    // pub fn new_mode(dither_mode: DitherMode) -> SixelResult<Self> {
-    //     let (ncolors, palette, keycolor) = match dither_mode {
-    //         DitherMode::MonoDark => (2, pal_mono_dark.to_vec(), 0),
-    //         DitherMode::MonoLight => (2, pal_mono_light.to_vec(), 0),
-    //         DitherMode::XTerm16 => (16, pal_xterm256.to_vec(), -1),
-    //         DitherMode::XTerm256 => (256, pal_xterm256.to_vec(), -1),
-    //         DitherMode::VT340Mono => (16, pal_vt340_mono.to_vec(), -1),
-    //         DitherMode::VT340Color => (16, pal_vt340_color.to_vec(), -1),
-    //         DitherMode::G1 => (2, pal_gray_1bit.to_vec(), -1),
-    //         DitherMode::G2 => (4, pal_gray_2bit.to_vec(), -1),
-    //         DitherMode::G4 => (16, pal_gray_4bit.to_vec(), -1),
-    //         DitherMode::G8 => (256, pal_gray_8bit.to_vec(), -1),
-    //     };
-    //
-    //     let mut result = DitherConf::new(ncolors)?;
-    //     result.palette = palette;
-    //     result.keycolor = keycolor;
-    //     result.optimized = true;
-    //     result.optimize_palette = false;
-    //     Ok(result)
-    // }
-
-    /// TODO
+            alpha_mode: AlphaMode::Ignore,
+            dither_mode: None,
+        }
+    }
+
+    /// Creates a new dither configuration with the fixed palette of a predefined mode.
+    ///
+    /// The palette doesn't need to be initialized,
+    /// and it's not optimized, so the color indices are the same for every image.
+    #[must_use]
+    pub fn new_mode(dither_mode: DitherMode) -> Self {
+        let palette = dither_mode.palette();
+        let keycolor = match dither_mode {
+            DitherMode::MonoDark | DitherMode::MonoLight => 0,
+            _ => -1,
+        };
+        let mut result = Self::with_quality((palette.len() / 3) as i32, Quality::Low);
+        result.palette = palette.to_vec();
+        result.keycolor = keycolor;
+        result.optimized = true;
+        result.optimize_palette = false;
+        result.dither_mode = Some(dither_mode);
+        result
+    }
+
+    /// Sets the method for finding the largest dimension for splitting.
+    ///
+    /// [`Auto`][LargestDim::Auto] selects [`Norm`][LargestDim::Norm].
     pub fn set_method_for_largest(&mut self, method_for_largest: LargestDim) {
         self.method_for_largest = if matches!(method_for_largest, LargestDim::Auto) {
             LargestDim::Norm
@@ -152,7 +269,9 @@ impl DitherConf {
         };
     }
 
-    /// TODO
+    /// Sets the method for choosing the representative color of each box.
+    ///
+    /// [`Auto`][RepColor::Auto] selects [`Center`][RepColor::Center].
     pub fn set_method_for_rep(&mut self, method_for_rep: RepColor) {
         self.method_for_rep = if matches!(method_for_rep, RepColor::Auto) {
             RepColor::Center
@@ -161,7 +280,55 @@ impl DitherConf {
         };
     }
 
-    /// TODO
+    /// Sets the method of color quantization used by [`initialize`][Self::initialize].
+    ///
+    /// Defaults to [`MedianCut`][Quantizer::MedianCut].
+    #[inline]
+    pub fn set_quantizer(&mut self, quantizer: Quantizer) {
+        self.quantizer = quantizer;
+    }
+
+    /// Sets the color space for measuring the distances between colors,
+    /// when splitting the median cut boxes and looking up the nearest palette color.
+    ///
+    /// Defaults to [`Rgb`][ColorSpace::Rgb].
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{ColorSpace, DitherConf, PixelFormat, SixelOutput};
+    /// let rgb: Vec<u8> = (0..64 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 96]).collect();
+    ///
+    /// let mut dither = DitherConf::new(16).unwrap();
+    /// dither.set_color_space(ColorSpace::Oklab);
+    /// dither.initialize(&rgb, 64, 64, PixelFormat::RGB888).unwrap();
+    /// let mut output = SixelOutput::new(Vec::new());
+    /// output.encode(&rgb, 64, 64, &mut dither).unwrap();
+    /// ```
+    pub fn set_color_space(&mut self, color_space: ColorSpace) {
+        if self.color_space != color_space {
+            self.color_space = color_space;
+            // the cached lookups depend on the color space
+            self.cachetable = None;
+        }
+    }
+
+    /// Sets the maximum number of k-means iterations that refine the palette
+    /// built by [`initialize`][Self::initialize], or 0 to not refine it.
+    ///
+    /// Each iteration moves every palette color to the average of the histogram colors
+    /// nearest to it, and the refinement stops early once the palette doesn't change.
+    /// The error can be read afterwards with [`get_palette_mse`][Self::get_palette_mse].
+    ///
+    /// Defaults to 0.
+    #[inline]
+    pub fn set_refine_iterations(&mut self, iterations: u32) {
+        self.refine_iterations = iterations;
+    }
+
+    /// Sets the quality mode.
+    ///
+    /// [`Auto`][Quality::Auto] selects [`High`][Quality::High] for 8 colors or less,
+    /// and [`Low`][Quality::Low] otherwise.
     pub fn set_quality_mode(&mut self, quality_mode: Quality) {
         self.quality_mode = if matches!(quality_mode, Quality::Auto) {
             if self.ncolors <= 8 {
@@ -174,61 +341,123 @@ impl DitherConf {
         };
     }
 
-    /// TODO
-    #[allow(clippy::too_many_arguments)]
+    /// Builds the palette from the given image, using the configured methods.
+    ///
+    /// It does nothing in [`HighColor`][Quality::HighColor] mode.
+    ///
+    /// # Errors
+    /// Returns [`BadInput`][SixelError::BadInput] if the size is 0,
+    /// or `data` is not long enough for the given size and pixel format.
     pub fn initialize(
         &mut self,
         data: &[u8],
-        width: i32,
-        height: i32,
+        width: usize,
+        height: usize,
         pixelformat: PixelFormat,
-        method_for_largest: LargestDim,
-        method_for_rep: RepColor,
-        quality_mode: Quality,
     ) -> SixelResult<()> {
+        self.initialize_frames(&[data], width, height, pixelformat)
+    }
+
+    /// Builds a single palette from the given frames of the same size and pixel format.
+    ///
+    /// Only an evenly spaced subset of up to [`PALETTE_SAMPLE_PIXELS`] pixels worth of
+    /// frames is used, so that the memory doesn't grow with the number of frames.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`initialize`][Self::initialize] for any of the frames.
+    pub(crate) fn initialize_frames(
+        &mut self,
+        frames: &[&[u8]],
+        width: usize,
+        height: usize,
+        pixelformat: PixelFormat,
+    ) -> SixelResult<()> {
+        if frames.is_empty() {
+            return Err(SixelError::BadInput);
+        }
+        for frame in frames {
+            check_image(frame, width, height, pixelformat)?;
+        }
+        if matches!(self.quality_mode, Quality::HighColor) {
+            return Ok(());
+        }
+        self.dither_mode = None;
         self.set_pixelformat(pixelformat);
-        #[expect(clippy::single_match_else, reason = "could be extended")]
-        let input_pixels = match pixelformat {
-            PixelFormat::RGB888 => data.to_vec(),
-            _ => {
-                /* normalize pixelformat */
-                let mut normalized_pixels = vec![0; (width * height * 3) as usize];
-                self.set_pixelformat(sixel_helper_normalize_pixelformat(
-                    &mut normalized_pixels,
-                    data,
-                    pixelformat,
-                    width,
-                    height,
-                )?);
-                normalized_pixels
+        let samples = (PALETTE_SAMPLE_PIXELS / (width * height)).clamp(1, frames.len());
+        let mut input_pixels = Vec::new();
+        let mut has_mask = false;
+        for i in 0..samples {
+            let frame = frames[i * frames.len() / samples];
+            let (pixels, mask) = self.normalize_pixels(frame, width as i32, height as i32)?;
+            if let Some(mask) = mask {
+                /* leave the transparent pixels out of the palette */
+                has_mask = true;
+                input_pixels.extend(
+                    pixels
+                        .chunks_exact(3)
+                        .zip(mask)
+                        .filter(|(_, transparent)| !transparent)
+                        .flat_map(|(pixel, _)| pixel),
+                );
+            } else {
+                input_pixels.extend_from_slice(&pixels);
             }
-        };
-
-        self.set_method_for_largest(method_for_largest);
-        self.set_method_for_rep(method_for_rep);
-        self.set_quality_mode(quality_mode);
+        }
+        let mut reqcolors = self.reqcolors;
+        if has_mask {
+            /* reserve a color for the transparent pixels unless there's a transparent index */
+            if self.keycolor < 0 {
+                reqcolors = reqcolors.min(SIXEL_PALETTE_MAX as i32 - 1);
+            }
+            if input_pixels.is_empty() {
+                self.palette = vec![0; 3];
+                self.ncolors = 1;
+                self.origcolors = 0;
+                self.palette_mse = None;
+                self.optimized = true;
+                return Ok(());
+            }
+        }
+        self.set_quality_mode(self.quality_mode);
 
         let buf = sixel_quant_make_palette(
             &input_pixels,
-            width * height * 3,
+            input_pixels.len() as i32,
             PixelFormat::RGB888,
-            self.reqcolors,
+            reqcolors,
             &mut self.ncolors,
             &mut self.origcolors,
+            self.color_space,
             self.method_for_largest,
             self.method_for_rep,
             self.quality_mode,
+            self.quantizer,
         )?;
 
         self.palette = buf;
+        self.palette_mse = if self.refine_iterations > 0 {
+            Some(sixel_quant_refine_palette(
+                &mut self.palette,
+                &input_pixels,
+                input_pixels.len() as i32,
+                PixelFormat::RGB888,
+                self.quality_mode,
+                self.refine_iterations,
+            )?)
+        } else {
+            None
+        };
         self.optimized = true;
-        if self.origcolors <= self.reqcolors {
+        if self.origcolors <= reqcolors {
             self.method_for_diffuse = Diffusion::None;
         }
         Ok(())
     }
 
-    /// Set diffusion method.
+    /// Sets the diffusion method.
+    ///
+    /// [`Auto`][Diffusion::Auto] selects [`FS`][Diffusion::FS] for more than 16 colors,
+    /// and [`Atkinson`][Diffusion::Atkinson] otherwise.
     pub fn set_diffusion_method(&mut self, method: Diffusion) {
         self.method_for_diffuse = if matches!(method, Diffusion::Auto) {
             if self.ncolors > 16 {
@@ -241,72 +470,223 @@ impl DitherConf {
         };
     }
 
-    /// Get number of palette colors.
+    /// Sets whether to diffuse the error in linear light, instead of in gamma encoded sRGB.
+    ///
+    /// The pixels are converted to linear light before computing and diffusing the error,
+    /// and back afterwards, which keeps the average brightness of the dithered areas.
+    /// It applies to the error diffusion methods, for both the palette
+    /// and the [`HighColor`][Quality::HighColor] encoding. Defaults to `false`.
     #[inline]
-    pub fn get_num_of_palette_colors(&self) -> i32 {
-        self.ncolors
+    pub fn set_linear_diffusion(&mut self, linear: bool) {
+        self.linear_diffusion = linear;
     }
 
-    /// Get number of histogram colors.
+    /// Sets whether to scan in serpentine, alternating the direction of each row.
+    ///
+    /// The odd rows are scanned from right to left, mirroring the error diffusion kernel,
+    /// which avoids the directional artifacts of always scanning from left to right.
+    /// It applies to the error diffusion methods, for both the palette
+    /// and the [`HighColor`][Quality::HighColor] encoding. Defaults to `false`.
     #[inline]
-    pub fn get_num_of_histogram_colors(&self) -> i32 {
-        self.origcolors
+    pub fn set_serpentine(&mut self, serpentine: bool) {
+        self.serpentine = serpentine;
     }
 
-    /// Get the palette.
+    /// Sets the percentage of the error to diffuse to the neighbor pixels, up to 100.
+    ///
+    /// Lower strengths reduce the noise in flat areas, while still breaking up the gradients.
+    /// It applies to the error diffusion methods of the palette encoding, not to the ordered
+    /// dithers nor to the [`HighColor`][Quality::HighColor] encoding. Defaults to `100`.
     #[inline]
-    pub fn get_palette(&self) -> &[u8] {
-        &self.palette
+    pub fn set_diffusion_strength(&mut self, percent: u8) {
+        self.diffusion_strength = percent.min(100);
     }
 
-    /// Set the palette.
+    /// Returns the number of palette colors.
     #[inline]
-    pub fn set_palette(&mut self, palette: Vec<u8>) {
-        self.palette = palette;
+    #[must_use]
+    pub fn get_num_of_palette_colors(&self) -> usize {
+        self.ncolors as usize
     }
 
-    /// set the factor of complexion color correcting
-    //  /* complexion score (>= 1) */
+    /// Returns the number of histogram colors, if the palette has been initialized.
     #[inline]
-    pub fn set_complexion_score(&mut self, score: i32) {
-        self.complexion = score;
+    #[must_use]
+    pub fn get_num_of_histogram_colors(&self) -> Option<usize> {
+        usize::try_from(self.origcolors).ok()
     }
 
-    /// Set whether omitting palette definition.
+    /// Returns the mean squared error per color component of the sampled histogram colors,
+    /// before and after refining the palette, if it has been refined.
+    ///
+    /// See [`set_refine_iterations`][Self::set_refine_iterations].
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherConf, PixelFormat};
+    /// let rgb: Vec<u8> = (0..64 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 96]).collect();
     ///
-    /// false: outputs palette section.
+    /// let mut dither = DitherConf::new(16).unwrap();
+    /// dither.set_refine_iterations(8);
+    /// dither.initialize(&rgb, 64, 64, PixelFormat::RGB888).unwrap();
+    /// let (before, after) = dither.get_palette_mse().unwrap();
+    /// assert![after <= before];
+    /// ```
+    #[inline]
+    #[must_use]
+    pub const fn get_palette_mse(&self) -> Option<(f64, f64)> {
+        self.palette_mse
+    }
+
+    /// Returns the palette, as consecutive RGB triplets.
+    #[inline]
+    #[must_use]
+    pub fn get_palette(&self) -> &[u8] {
+        &self.palette[..self.ncolors as usize * 3]
+    }
+
+    /// Sets the palette.
+    ///
+    /// The palette is used as is, without being initialized from an image,
+    /// and it's also the table of the indices of the `PAL*` pixel formats.
+    ///
+    /// # Errors
+    /// Returns [`BadArgument`][SixelError::BadArgument]
+    /// if the palette is empty or has more than 256 colors.
+    pub fn set_palette(&mut self, palette: &[[u8; 3]]) -> SixelResult<()> {
+        if palette.is_empty() || palette.len() > SIXEL_PALETTE_MAX {
+            return Err(SixelError::BadArgument);
+        }
+        self.palette = palette.iter().flatten().copied().collect();
+        self.ncolors = palette.len() as i32;
+        self.reqcolors = self.ncolors;
+        self.optimized = true;
+        self.cachetable = None;
+        self.palette_mse = None;
+        self.dither_mode = None;
+        Ok(())
+    }
+
+    /// Sets the factor of complexion color correcting.
+    ///
+    /// It multiplies the weight of the red channel when looking up colors. Defaults to 1.
+    ///
+    /// It's only applied in the [`Rgb`][ColorSpace::Rgb] color space,
+    /// since the perceptual color spaces already weight the skin tones better.
+    #[inline]
+    pub fn set_complexion_score(&mut self, score: u8) {
+        self.complexion = score.max(1) as i32;
+    }
+
+    /// Sets whether to omit the palette definition.
+    ///
+    /// `false` outputs the palette section (default).
     #[inline]
     pub fn set_body_only(&mut self, bodyonly: bool) {
         self.bodyonly = bodyonly;
     }
 
-    /// Set whether optimize palette size.
+    /// Sets whether to minimize the palette size.
     ///
-    /// false: optimizes the palette size.
+    /// `true` removes the unused colors from the palette when encoding.
     #[inline]
     pub fn set_optimize_palette(&mut self, do_op: bool) {
         self.optimize_palette = do_op;
     }
 
-    /// Set the pixel format
+    /// Sets the pixel format of the images to encode.
     #[inline]
     pub fn set_pixelformat(&mut self, pixelformat: PixelFormat) {
         self.pixelformat = pixelformat;
     }
 
-    /// Set the transparent color index.
+    /// Sets the transparent color index, or `None` for no transparency (default).
+    #[inline]
+    pub fn set_transparent(&mut self, index: Option<u8>) {
+        self.keycolor = index.map_or(-1, i32::from);
+    }
+
+    /// Returns the transparent color index, if any.
+    #[inline]
+    #[must_use]
+    pub fn get_transparent(&self) -> Option<u8> {
+        u8::try_from(self.keycolor).ok()
+    }
+
+    /// Sets the handling of the alpha channel.
+    #[inline]
+    pub fn set_alpha_mode(&mut self, alpha_mode: AlphaMode) {
+        self.alpha_mode = alpha_mode;
+    }
+
+    /// Returns the handling of the alpha channel.
     #[inline]
-    pub fn set_transparent(&mut self, index: i32) {
-        self.keycolor = index;
+    #[must_use]
+    pub fn get_alpha_mode(&self) -> AlphaMode {
+        self.alpha_mode
     }
 
-    /* set transparent */
+    /// Maps the pixels of an image onto the palette.
+    ///
+    /// Returns one palette index per pixel.
+    ///
+    /// With [`AlphaMode::Threshold`], the transparent pixels get the
+    /// [transparent index][Self::get_transparent] if there's one,
+    /// or else the index that follows the last palette color.
+    /// The transparent pixels don't diffuse their error into their neighbors.
+    ///
+    /// # Errors
+    /// Returns [`BadInput`][SixelError::BadInput] if the size is 0,
+    /// or `pixels` is not long enough for the given size and pixel format,
+    /// and [`BadArgument`][SixelError::BadArgument] if there are transparent pixels
+    /// but the palette is full, without a transparent index.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{AlphaMode, Diffusion, DitherConf, DitherMode, PixelFormat, Quality};
+    /// let mut dither = DitherConf::new(2).unwrap();
+    /// dither.set_palette(&[[0, 0, 0], [255, 255, 255]]).unwrap();
+    /// // the full quality looks up every pixel without a cache table
+    /// dither.set_quality_mode(Quality::Full);
+    /// let indices = dither.apply_palette(&[10, 10, 10, 250, 250, 250], 2, 1).unwrap();
+    /// assert_eq![indices, [0, 1]];
+    ///
+    /// // the error of the transparent gray doesn't lighten the opaque one
+    /// dither.set_diffusion_method(Diffusion::FS);
+    /// dither.set_pixelformat(PixelFormat::RGBA8888);
+    /// dither.set_alpha_mode(AlphaMode::Threshold(128));
+    /// let grays = [60, 60, 60, 0, 60, 60, 60, 255];
+    /// assert_eq![dither.apply_palette(&grays, 2, 1).unwrap(), [2, 0]];
+    ///
+    /// // a full palette needs a transparent index
+    /// let mut dither = DitherConf::new_mode(DitherMode::XTerm256);
+    /// dither.set_pixelformat(PixelFormat::RGBA8888);
+    /// dither.set_alpha_mode(AlphaMode::Threshold(128));
+    /// let pixels = [0, 0, 0, 0, 238, 238, 238, 255];
+    /// assert![dither.apply_palette(&pixels, 2, 1).is_err()];
+    /// dither.set_transparent(Some(0));
+    /// assert_eq![dither.apply_palette(&pixels, 2, 1).unwrap(), [0, 255]];
+    /// ```
     pub fn apply_palette(
+        &mut self,
+        pixels: &[u8],
+        width: usize,
+        height: usize,
+    ) -> SixelResult<Vec<u8>> {
+        let (width, height) = check_image(pixels, width, height, self.pixelformat)?;
+        Ok(self.apply_palette_masked(pixels, width, height)?.0)
+    }
+
+    /// Maps the pixels of an image onto the palette.
+    ///
+    /// Returns one palette index per pixel,
+    /// and the index of the transparent pixels with [`AlphaMode::Threshold`].
+    pub(crate) fn apply_palette_masked(
         &mut self,
         pixels: &[u8],
         width: i32,
         height: i32,
-    ) -> SixelResult<Vec<u8>> {
+    ) -> SixelResult<(Vec<u8>, Option<i32>)> {
         let bufsize = width * height;
         let mut dest = vec![0; bufsize as usize];
 
@@ -320,26 +700,14 @@ impl DitherConf {
             && self.palette != pal_mono_dark
             && self.palette != pal_mono_light
         {
-            self.cachetable = Some(vec![0; 1 << (3 * 5)]);
+            self.cachetable = Some(vec![0; CACHE_TABLE_SIZE]);
         }
 
-        let mut input_pixels = if !matches!(self.pixelformat, PixelFormat::RGB888) {
-            /* normalize pixelformat */
-            let mut normalized_pixels = vec![0; (width * height * 3) as usize];
-            self.pixelformat = sixel_helper_normalize_pixelformat(
-                &mut normalized_pixels,
-                pixels,
-                self.pixelformat,
-                width,
-                height,
-            )?;
-            normalized_pixels
-        } else {
-            pixels.to_vec()
-        };
+        let (mut input_pixels, mask) = self.normalize_pixels(pixels, width, height)?;
         let ncolors = sixel_quant_apply_palette(
             &mut dest,
             &mut input_pixels,
+            mask.as_deref(),
             width,
             height,
             3,
@@ -349,10 +717,86 @@ impl DitherConf {
             self.optimized,
             self.optimize_palette,
             self.complexion,
-            Some(self.cachetable.as_mut().unwrap()),
+            self.color_space,
+            self.linear_diffusion,
+            self.serpentine,
+            self.diffusion_strength as i32,
+            self.cachetable.as_deref_mut(),
         )?;
         self.ncolors = ncolors;
 
-        Ok(dest)
+        let Some(mask) = mask else {
+            return Ok((dest, None));
+        };
+        let keycolor = if self.keycolor >= 0 {
+            self.keycolor
+        } else if ncolors < SIXEL_PALETTE_MAX as i32 {
+            ncolors
+        } else if mask.contains(&true) {
+            /* a full fixed palette has no index left for the transparent pixels */
+            return Err(SixelError::BadArgument);
+        } else {
+            return Ok((dest, None));
+        };
+        for (index, _) in dest.iter_mut().zip(mask).filter(|(_, transparent)| *transparent) {
+            *index = keycolor as u8;
+        }
+        Ok((dest, Some(keycolor)))
+    }
+
+    /// Normalizes the pixels into `RGB888`, applying the alpha mode.
+    ///
+    /// Returns the normalized pixels,
+    /// and which pixels are transparent with [`AlphaMode::Threshold`].
+    pub(crate) fn normalize_pixels(
+        &self,
+        pixels: &[u8],
+        width: i32,
+        height: i32,
+    ) -> SixelResult<(Vec<u8>, Option<Vec<bool>>)> {
+        let len = (width * height * 3) as usize;
+        let mut normalized_pixels = if matches!(self.pixelformat, PixelFormat::RGB888) {
+            pixels[..len].to_vec()
+        } else if matches!(self.pixelformat, PixelFormat::G1 | PixelFormat::G2 | PixelFormat::G4) {
+            /* unpack the gray levels, and scale them to 8 bits */
+            let mut levels = vec![0; (width * height) as usize];
+            sixel_helper_normalize_pixelformat(
+                &mut levels,
+                pixels,
+                self.pixelformat,
+                width,
+                height,
+            )?;
+            let max = (1 << self.pixelformat.bpp()) - 1;
+            levels
+                .iter()
+                .flat_map(|&level| [(level as usize * 255 / max) as u8; 3])
+                .collect()
+        } else {
+            let mut normalized_pixels = vec![0; len];
+            sixel_helper_normalize_pixelformat(
+                &mut normalized_pixels,
+                pixels,
+                self.pixelformat,
+                width,
+                height,
+            )?;
+            normalized_pixels
+        };
+        let alpha = match self.alpha_mode {
+            AlphaMode::Ignore => None,
+            _ => sixel_helper_get_alpha(pixels, self.pixelformat, width, height),
+        };
+        let mask = match (self.alpha_mode, alpha) {
+            (AlphaMode::Threshold(threshold), Some(alpha)) => {
+                Some(alpha.iter().map(|&a| a < threshold).collect())
+            }
+            (AlphaMode::Blend(background), Some(alpha)) => {
+                sixel_helper_blend_alpha(&mut normalized_pixels, &alpha, background);
+                None
+            }
+            _ => None,
+        };
+        Ok((normalized_pixels, mask))
     }
 }
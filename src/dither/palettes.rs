@@ -1,7 +1,5 @@
 // sixela::dither::palettes
 
-#![allow(unused, reason = "commented out for now")]
-
 /// monochrome terminal with dark background
 pub(crate) const pal_mono_dark: [u8; 6] = [0x00, 0x00, 0x00, 0xff, 0xff, 0xff];
 
@@ -155,20 +153,20 @@ pub(crate) const pal_vt340_mono: [u8; 48] = [
 
 /// vt340 color
 pub(crate) const pal_vt340_color: [u8; 48] = [
-    0x21, 0x21, 0x21, // 1   Gray-2
-    0x42, 0x42, 0x42, // 2   Gray-4
-    0x66, 0x66, 0x66, // 3   Gray-6
-    0x0F, 0x0F, 0x0F, // 4   Gray-1
-    0x33, 0x33, 0x33, // 5   Gray-3
-    0x54, 0x54, 0x54, // 6   Gray-5
-    0x75, 0x75, 0x75, // 7   White 7
-    0x00, 0x00, 0x00, // 8   Black 0
-    0x21, 0x21, 0x21, // 9   Gray-2
-    0x42, 0x42, 0x42, // 10  Gray-4
-    0x66, 0x66, 0x66, // 11  Gray-6
-    0x0F, 0x0F, 0x0F, // 12  Gray-1
-    0x33, 0x33, 0x33, // 13  Gray-3
-    0x54, 0x54, 0x54, // 14  Gray-5
-    0x75, 0x75, 0x75, // 15  White 7
+    0x33, 0x33, 0xCC, // 1   Blue
+    0xCC, 0x23, 0x23, // 2   Red
+    0x33, 0xCC, 0x33, // 3   Green
+    0xCC, 0x33, 0xCC, // 4   Magenta
+    0x33, 0xCC, 0xCC, // 5   Cyan
+    0xCC, 0xCC, 0x33, // 6   Yellow
+    0x77, 0x77, 0x77, // 7   Gray 50%
+    0x44, 0x44, 0x44, // 8   Gray 25%
+    0x56, 0x56, 0x99, // 9   Blue*
+    0x99, 0x44, 0x44, // 10  Red*
+    0x56, 0x99, 0x56, // 11  Green*
+    0x99, 0x56, 0x99, // 12  Magenta*
+    0x56, 0x99, 0x99, // 13  Cyan*
+    0x99, 0x99, 0x56, // 14  Yellow*
+    0xCC, 0xCC, 0xCC, // 15  Gray 75%
     0x00, 0x00, 0x00, // 0   Black
 ];
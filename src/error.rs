@@ -1,6 +1,6 @@
 // sixela::error
 
-use devela::Error;
+use devela::{Error, IoErrorKind};
 
 /// A sixel-related result.
 pub type SixelResult<T> = Result<T, SixelError>;
@@ -16,6 +16,8 @@ pub enum SixelError {
     BadIntegerOverflow,
     /// Feature not implemented.
     NotImplemented,
+    /// Error writing to the output.
+    Io(IoErrorKind),
 }
 
 mod _core_impls {
@@ -31,6 +33,7 @@ mod _core_impls {
                 SixelError::BadInput => write!(f, "bad input detected"),
                 SixelError::BadIntegerOverflow => write!(f, "integer overflow"),
                 SixelError::NotImplemented => write!(f, "feature not implemented"),
+                SixelError::Io(kind) => write!(f, "I/O error: {kind:?}"),
             }
         }
     }
@@ -0,0 +1,154 @@
+// sixela::image
+//
+// TOC
+// - Sixel from image buffers
+// - image buffers from SixelImage
+
+use crate::{PixelFormat, Sixel, SixelError, SixelImage, SixelResult};
+use ::image::{DynamicImage, ImageBuffer, RgbImage, RgbaImage};
+use core::ops::Deref;
+
+/* Sixel from image buffers */
+
+/// # `image` methods
+impl<'a> Sixel<'a> {
+    /// Returns a new sixel builder for the pixels of an [`image`][::image] crate image.
+    ///
+    /// The pixel format is selected from the color type of the image,
+    /// as `G8`, `GA88`, `RGB888` or `RGBA8888`.
+    ///
+    /// # Errors
+    /// Returns [`NotImplemented`][SixelError::NotImplemented] for images with 16-bit or
+    /// floating point samples, which can be converted first with [`DynamicImage::to_rgba8`].
+    ///
+    /// Returns [`BadArgument`][SixelError::BadArgument] if either dimension exceeds `i32::MAX`.
+    ///
+    /// # Example
+    /// ```
+    /// # use image::{DynamicImage, Rgb, RgbImage};
+    /// # use sixela::{PixelFormat, Sixel, SixelImage};
+    /// let image = DynamicImage::ImageRgb8(RgbImage::from_pixel(8, 12, Rgb([255, 0, 0])));
+    /// let sixel = Sixel::from_image(&image).unwrap();
+    /// assert_eq![(sixel.width, sixel.height, sixel.format), (8, 12, PixelFormat::RGB888)];
+    ///
+    /// let decoded = SixelImage::decode(sixel.build().unwrap().as_bytes()).unwrap();
+    /// let rgba = decoded.to_rgba_image();
+    /// assert_eq![rgba.dimensions(), (8, 12)];
+    /// assert![rgba.pixels().all(|p| p.0[0] > 240 && p.0[3] == 255)];
+    /// ```
+    #[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+    pub fn from_image(image: &'a DynamicImage) -> SixelResult<Self> {
+        let (bytes, format) = match image {
+            DynamicImage::ImageLuma8(buffer) => (buffer.as_raw().as_slice(), PixelFormat::G8),
+            DynamicImage::ImageLumaA8(buffer) => (buffer.as_raw().as_slice(), PixelFormat::GA88),
+            DynamicImage::ImageRgb8(buffer) => (buffer.as_raw().as_slice(), PixelFormat::RGB888),
+            DynamicImage::ImageRgba8(buffer) => (buffer.as_raw().as_slice(), PixelFormat::RGBA8888),
+            _ => return Err(SixelError::NotImplemented),
+        };
+        image_sixel(bytes, image.width(), image.height(), format)
+    }
+}
+
+#[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+impl<'a> TryFrom<&'a DynamicImage> for Sixel<'a> {
+    type Error = SixelError;
+    fn try_from(image: &'a DynamicImage) -> SixelResult<Self> {
+        Self::from_image(image)
+    }
+}
+
+/// Implements `TryFrom` an image buffer of 8-bit samples for `Sixel`.
+macro_rules! impl_try_from_buffer {
+    ($($pixel:ident => $format:ident),+ $(,)?) => { $(
+        #[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+        impl<'a, C: Deref<Target = [u8]>> TryFrom<&'a ImageBuffer<::image::$pixel<u8>, C>>
+            for Sixel<'a>
+        {
+            type Error = SixelError;
+            fn try_from(image: &'a ImageBuffer<::image::$pixel<u8>, C>) -> SixelResult<Self> {
+                image_sixel(image.as_raw(), image.width(), image.height(), PixelFormat::$format)
+            }
+        }
+    )+ };
+}
+impl_try_from_buffer![Luma => G8, LumaA => GA88, Rgb => RGB888, Rgba => RGBA8888];
+
+/// Returns a new sixel builder for the given image bytes, size and pixel format.
+fn image_sixel(
+    bytes: &[u8],
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+) -> SixelResult<Sixel<'_>> {
+    let width = i32::try_from(width).map_err(|_| SixelError::BadArgument)?;
+    let height = i32::try_from(height).map_err(|_| SixelError::BadArgument)?;
+    Ok(Sixel::with_bytes_size(bytes, width, height).format(format))
+}
+
+/* image buffers from SixelImage */
+
+/// # `image` methods
+impl SixelImage {
+    /// Returns the pixels as an [`RgbImage`], filling unpainted pixels with `background`.
+    ///
+    /// # Example
+    /// ```
+    /// # use image::{GrayImage, Rgb};
+    /// # use sixela::{Sixel, SixelImage};
+    /// let gray = GrayImage::from_raw(4, 1, vec![0, 51, 128, 255]).unwrap();
+    /// let sixel = Sixel::try_from(&gray).unwrap().build().unwrap();
+    ///
+    /// let rgb = SixelImage::decode(sixel.as_bytes()).unwrap().to_rgb_image([0; 3]);
+    /// assert_eq![rgb.dimensions(), (4, 1)];
+    /// let levels: Vec<_> = rgb.pixels().copied().collect();
+    /// assert_eq![levels, [Rgb([0; 3]), Rgb([51; 3]), Rgb([128; 3]), Rgb([255; 3])]];
+    /// ```
+    #[must_use]
+    #[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+    pub fn to_rgb_image(&self, background: [u8; 3]) -> RgbImage {
+        let (width, height) = (self.width as u32, self.height as u32);
+        RgbImage::from_raw(width, height, self.to_rgb888(background))
+            .expect("the decoded pixels fill the image")
+    }
+
+    /// Returns the pixels as an [`RgbaImage`], with unpainted pixels fully transparent.
+    ///
+    /// # Example
+    /// ```
+    /// # use image::{Rgba, RgbaImage};
+    /// # use sixela::{AlphaMode, Sixel, SixelImage};
+    /// let (red, blue, clear) = (Rgba([255, 0, 0, 255]), Rgba([0, 0, 255, 255]), Rgba([0; 4]));
+    /// let image = RgbaImage::from_fn(3, 2, |x, y| [red, blue, clear][((x + y) % 3) as usize]);
+    /// let sixel = Sixel::try_from(&image)
+    ///     .unwrap()
+    ///     .alpha(AlphaMode::Threshold(128))
+    ///     .palette(&[[255, 0, 0], [0, 0, 255]])
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// // the transparent pixels are left undrawn
+    /// let rgba = SixelImage::decode(sixel.as_bytes()).unwrap().to_rgba_image();
+    /// assert_eq![rgba, image];
+    /// ```
+    #[must_use]
+    #[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+    pub fn to_rgba_image(&self) -> RgbaImage {
+        let (width, height) = (self.width as u32, self.height as u32);
+        RgbaImage::from_raw(width, height, self.to_rgba8888())
+            .expect("the decoded pixels fill the image")
+    }
+}
+
+#[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+impl From<&SixelImage> for RgbaImage {
+    fn from(image: &SixelImage) -> Self {
+        image.to_rgba_image()
+    }
+}
+
+#[cfg_attr(feature = "nightly", doc(cfg(feature = "image")))]
+impl From<&SixelImage> for DynamicImage {
+    fn from(image: &SixelImage) -> Self {
+        DynamicImage::ImageRgba8(image.to_rgba_image())
+    }
+}
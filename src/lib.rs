@@ -54,19 +54,35 @@ compile_error!("You can't enable the `std` and `no_std` features at the same tim
 #[cfg(all(feature = "safe", feature = "unsafe"))]
 compile_error!("You can't enable `safe` and `unsafe*` features at the same time.");
 
+mod decode;
+mod dither;
 mod error;
+#[cfg(feature = "image")]
+mod image;
+#[cfg(feature = "loaders")]
+mod loaders;
 mod output;
+#[cfg(feature = "std")]
+mod terminal;
 // no public items:
-mod dither;
 mod pixelformat;
 mod quant;
+mod resample;
 
 /// All items are flat re-exported here. <br/><hr>
 #[doc(hidden)]
 pub mod all {
+    #[doc(inline)]
+    #[cfg(feature = "loaders")]
+    pub use super::loaders::*;
+    #[doc(inline)]
+    #[cfg(feature = "std")]
+    pub use super::terminal::*;
     #[doc(inline)]
     #[allow(unused_imports, reason = "crate private items")]
-    pub use super::{dither::*, error::*, output::*, pixelformat::*, quant::*};
+    pub use super::{
+        decode::*, dither::*, error::*, output::*, pixelformat::*, quant::*, resample::*,
+    };
 }
 #[doc(inline)]
 pub use all::*;
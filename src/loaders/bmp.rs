@@ -0,0 +1,278 @@
+// sixela::loaders::bmp
+//
+// TOC
+// - fn load_bmp
+// - struct BitMask
+// - fn decode_rle
+
+use super::{check_size, scale_to_u8, ByteReader, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+use alloc::vec;
+use devela::Vec;
+
+const BI_RGB: u32 = 0;
+const BI_RLE8: u32 = 1;
+const BI_RLE4: u32 = 2;
+const BI_BITFIELDS: u32 = 3;
+const BI_ALPHABITFIELDS: u32 = 6;
+
+/// Loads a Windows or OS/2 bitmap, as `RGB888`, or as `RGBA8888` if it has an alpha mask.
+///
+/// Supports 1, 4 and 8 bits per pixel with a palette, optionally run-length encoded,
+/// and 16, 24 and 32 bits per pixel, optionally with bit fields.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// // a 2x2 bitmap with a BITMAPINFOHEADER, followed by its palette or masks, and its data
+/// let bmp = |height: i32, bpp: u16, compression: u32, extra: &[u8], data: &[u8]| {
+///     let offset = 14 + 40 + extra.len() as u32;
+///     let mut bmp = b"BM".to_vec();
+///     bmp.extend((offset + data.len() as u32).to_le_bytes());
+///     bmp.extend([0; 4]);
+///     bmp.extend(offset.to_le_bytes());
+///     bmp.extend(40_u32.to_le_bytes());
+///     bmp.extend(2_i32.to_le_bytes());
+///     bmp.extend(height.to_le_bytes());
+///     bmp.extend(1_u16.to_le_bytes());
+///     bmp.extend(bpp.to_le_bytes());
+///     bmp.extend(compression.to_le_bytes());
+///     bmp.extend([0; 12]);
+///     bmp.extend((extra.len() as u32 / 4).to_le_bytes()); // the palette colors
+///     bmp.extend([0; 4]);
+///     bmp.extend(extra);
+///     bmp.extend(data);
+///     bmp
+/// };
+/// let load = |bmp: &[u8]| LoadedImage::load(bmp).map(|image| image.pixels);
+///
+/// // 24 bits per pixel, in BGR rows padded to 4 bytes, from the bottom row
+/// let data = [255, 0, 0, 255, 255, 255, 0, 0, 0, 0, 255, 0, 255, 0, 0, 0];
+/// let (red, green, blue, white) = ([255, 0, 0], [0, 255, 0], [0, 0, 255], [255; 3]);
+/// assert_eq![load(&bmp(2, 24, 0, &[], &data)), Ok([red, green, blue, white].concat())];
+/// // or from the top row, with a negative height
+/// assert_eq![load(&bmp(-2, 24, 0, &[], &data)), Ok([blue, white, red, green].concat())];
+///
+/// // run-length encoded indices into a BGRA palette of black and red
+/// let palette = [0, 0, 0, 0, 0, 0, 255, 0];
+/// // bottom row: a run of 2 reds, and the end of line; top row: 1 black and 1 red
+/// let rle8 = [2, 1, 0, 0, 1, 0, 1, 1, 0, 1];
+/// let image = LoadedImage::load(&bmp(2, 8, 1, &palette, &rle8)).unwrap();
+/// assert_eq![image.pixelformat, PixelFormat::RGB888];
+/// assert_eq![image.pixels, [[0; 3], red, red, red].concat()];
+/// // with 4 bits per index, a run alternates the 2 indices of its byte
+/// let rle4 = [2, 0x10, 0, 0, 2, 0x01, 0, 1];
+/// assert_eq![load(&bmp(2, 4, 2, &palette, &rle4)), Ok([[0; 3], red, red, [0; 3]].concat())];
+///
+/// // 32 bits per pixel with alpha bit fields, here the RGBA bytes in order
+/// let masks = [0xFF, 0xFF00, 0xFF_0000, 0xFF00_0000_u32].map(u32::to_le_bytes).concat();
+/// let data = [0, 0, 255, 255, 255, 255, 255, 128, 255, 0, 0, 255, 0, 255, 0, 0];
+/// let image = LoadedImage::load(&bmp(2, 32, 6, &masks, &data)).unwrap();
+/// assert_eq![image.pixelformat, PixelFormat::RGBA8888];
+/// assert_eq![image.pixels, [255, 0, 0, 255, 0, 255, 0, 0, 0, 0, 255, 255, 255, 255, 255, 128]];
+///
+/// // truncated data
+/// let bmp = bmp(2, 24, 0, &[], &[0; 16]);
+/// assert_eq![load(&bmp[..bmp.len() - 1]), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_bmp(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut r = ByteReader::new(bytes, 0);
+    if r.take(2)? != b"BM" {
+        return Err(SixelError::BadInput);
+    }
+    r.skip(8)?;
+    let data_offset = r.u32_le()? as usize;
+    let header_size = r.u32_le()? as usize;
+
+    let (width, height, bpp, compression, mut colors);
+    let mut masks = None;
+    if header_size == 12 {
+        // OS/2 BITMAPCOREHEADER
+        width = r.u16_le()? as i32;
+        height = r.u16_le()? as i32;
+        r.skip(2)?;
+        bpp = r.u16_le()?;
+        compression = BI_RGB;
+        colors = 0;
+    } else if header_size >= 40 {
+        // BITMAPINFOHEADER and its later versions
+        width = r.u32_le()? as i32;
+        height = r.u32_le()? as i32;
+        r.skip(2)?;
+        bpp = r.u16_le()?;
+        compression = r.u32_le()?;
+        r.skip(12)?;
+        colors = r.u32_le()? as usize;
+        r.skip(4)?;
+        if matches!(compression, BI_BITFIELDS | BI_ALPHABITFIELDS) {
+            // the masks are inside the later headers, or else follow the header
+            let with_alpha = header_size >= 56 || compression == BI_ALPHABITFIELDS;
+            let [red, green, blue] = [r.u32_le()?, r.u32_le()?, r.u32_le()?];
+            let alpha = if with_alpha { r.u32_le()? } else { 0 };
+            masks = Some([red, green, blue, alpha].map(BitMask::new));
+        }
+        r = ByteReader::new(bytes, 14 + header_size.max(r.pos - 14));
+    } else {
+        return Err(SixelError::BadInput);
+    }
+    if width <= 0 || height == 0 || height == i32::MIN {
+        return Err(SixelError::BadInput);
+    }
+    let (width, top_down) = (width as usize, height < 0);
+    let height = height.unsigned_abs() as usize;
+    check_size(width, height)?;
+
+    // the palette, for up to 8 bits per pixel
+    let mut palette = Vec::new();
+    if bpp <= 8 {
+        let entry_size = if header_size == 12 { 3 } else { 4 };
+        if colors == 0 || colors > 1 << bpp {
+            colors = 1 << bpp;
+        }
+        for _ in 0..colors {
+            let entry = r.take(entry_size)?;
+            palette.push([entry[2], entry[1], entry[0]]);
+        }
+    }
+    let data = bytes.get(data_offset..).ok_or(SixelError::BadInput)?;
+
+    // the palette indices, or the packed pixels, of each row from the bottom
+    let indices;
+    let stride = (width * bpp as usize).div_ceil(32) * 4;
+    let rows: Vec<&[u8]> = match (compression, bpp) {
+        (BI_RGB, 1 | 4 | 8 | 16 | 24 | 32) | (BI_BITFIELDS | BI_ALPHABITFIELDS, 16 | 32) => {
+            let data = data.get(..stride * height).ok_or(SixelError::BadInput)?;
+            data.chunks_exact(stride).collect()
+        }
+        (BI_RLE8, 8) | (BI_RLE4, 4) => {
+            indices = decode_rle(data, width, height, bpp == 4)?;
+            indices.chunks_exact(width).collect()
+        }
+        _ => return Err(SixelError::NotImplemented),
+    };
+    let rle = matches!(compression, BI_RLE8 | BI_RLE4);
+
+    let masks = masks.unwrap_or(match bpp {
+        16 => [0x7C00, 0x03E0, 0x001F, 0].map(BitMask::new),
+        _ => [0xFF_0000, 0xFF00, 0xFF, 0].map(BitMask::new),
+    });
+    let has_alpha = bpp >= 16 && masks[3].mask != 0;
+    let channels = if has_alpha { 4 } else { 3 };
+    let mut pixels = vec![0; width * height * channels];
+    for (y, row) in rows.iter().enumerate() {
+        let y = if top_down { y } else { height - 1 - y };
+        let out = &mut pixels[y * width * channels..(y + 1) * width * channels];
+        for (x, pixel) in out.chunks_exact_mut(channels).enumerate() {
+            let color = match bpp {
+                1 | 4 | 8 => {
+                    let index = if rle {
+                        row[x] as usize
+                    } else {
+                        let per_byte = 8 / bpp as usize;
+                        let shift = 8 - bpp as usize * (x % per_byte + 1);
+                        (row[x / per_byte] as usize >> shift) & ((1 << bpp) - 1)
+                    };
+                    let [red, green, blue] = palette.get(index).copied().unwrap_or_default();
+                    [red, green, blue, 255]
+                }
+                24 => [row[x * 3 + 2], row[x * 3 + 1], row[x * 3], 255],
+                _ => {
+                    let value = if bpp == 16 {
+                        u16::from_le_bytes([row[x * 2], row[x * 2 + 1]]) as u32
+                    } else {
+                        let b = &row[x * 4..x * 4 + 4];
+                        u32::from_le_bytes([b[0], b[1], b[2], b[3]])
+                    };
+                    masks.map(|mask| mask.extract(value))
+                }
+            };
+            pixel.copy_from_slice(&color[..channels]);
+        }
+    }
+    let pixelformat = if has_alpha {
+        PixelFormat::RGBA8888
+    } else {
+        PixelFormat::RGB888
+    };
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::BMP })
+}
+
+/// A bit field mask of a color channel.
+#[derive(Clone, Copy, Debug)]
+struct BitMask {
+    mask: u32,
+    shift: u32,
+    max: u32,
+}
+
+impl BitMask {
+    const fn new(mask: u32) -> Self {
+        let shift = if mask == 0 { 0 } else { mask.trailing_zeros() };
+        Self { mask, shift, max: mask >> shift }
+    }
+
+    /// Returns the value of the channel in a pixel, scaled to 8 bits.
+    const fn extract(self, pixel: u32) -> u8 {
+        scale_to_u8((pixel & self.mask) >> self.shift, self.max)
+    }
+}
+
+/// Decodes the run-length encoded palette indices of a bitmap,
+/// with 4 bits per index if `nibbles`, or 8 bits otherwise.
+///
+/// Returns one index per pixel, for each row from the bottom.
+/// The pixels skipped by deltas and early line ends are left at index 0.
+fn decode_rle(data: &[u8], width: usize, height: usize, nibbles: bool) -> SixelResult<Vec<u8>> {
+    let mut indices = vec![0; width * height];
+    let (mut x, mut y) = (0, 0);
+    let mut r = ByteReader::new(data, 0);
+    // the high nibble comes first
+    let nibble = |byte: u8, i: usize| if i % 2 == 0 { byte >> 4 } else { byte & 0xF };
+    let mut put = |x: &mut usize, y: usize, index: u8| {
+        if *x < width && y < height {
+            indices[y * width + *x] = index;
+        }
+        *x += 1;
+    };
+    loop {
+        let (count, value) = (r.u8()? as usize, r.u8()?);
+        if count > 0 {
+            // a run of the same index, or of two alternating indices
+            for i in 0..count {
+                let index = if nibbles { nibble(value, i) } else { value };
+                put(&mut x, y, index);
+            }
+            continue;
+        }
+        match value {
+            // end of line
+            0 => (x, y) = (0, y + 1),
+            // end of bitmap
+            1 => break,
+            // delta
+            2 => {
+                x += r.u8()? as usize;
+                y += r.u8()? as usize;
+            }
+            // a literal run of indices, padded to 16 bits
+            len => {
+                let len = len as usize;
+                let size = if nibbles { len.div_ceil(2) } else { len };
+                let literal = r.take(size)?;
+                for i in 0..len {
+                    let index = if nibbles {
+                        nibble(literal[i / 2], i)
+                    } else {
+                        literal[i]
+                    };
+                    put(&mut x, y, index);
+                }
+                r.skip(size % 2)?;
+            }
+        }
+        if y >= height {
+            break;
+        }
+    }
+    Ok(indices)
+}
@@ -0,0 +1,43 @@
+// sixela::loaders::farbfeld
+//
+// TOC
+// - fn load_farbfeld
+
+use super::{check_size, scale_to_u8, ByteReader, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+
+/// Loads a farbfeld image, as `RGBA8888`.
+///
+/// Its 16-bit big-endian components are scaled to 8 bits.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// let mut farbfeld = b"farbfeld\0\0\0\x02\0\0\0\x01".to_vec(); // 2x1
+/// farbfeld.extend([0xFF, 0xFF, 0x80, 0x00, 0, 0, 0xFF, 0xFF]);
+/// farbfeld.extend([0x01, 0x01, 0x02, 0x02, 0xFE, 0xFE, 0x7F, 0xFF]);
+///
+/// let image = LoadedImage::load(&farbfeld).unwrap();
+/// assert_eq![image.pixelformat, PixelFormat::RGBA8888];
+/// assert_eq![image.pixels, [255, 128, 0, 255, 1, 2, 254, 127]];
+///
+/// // truncated data
+/// let truncated = &farbfeld[..farbfeld.len() - 1];
+/// assert_eq![LoadedImage::load(truncated), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_farbfeld(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut r = ByteReader::new(bytes, 0);
+    if r.take(8)? != b"farbfeld" {
+        return Err(SixelError::BadInput);
+    }
+    let width = r.u32_be()? as usize;
+    let height = r.u32_be()? as usize;
+    check_size(width, height)?;
+    let pixels = r
+        .take(width * height * 8)?
+        .chunks_exact(2)
+        .map(|c| scale_to_u8(u16::from_be_bytes([c[0], c[1]]) as u32, u16::MAX as u32))
+        .collect();
+    let pixelformat = PixelFormat::RGBA8888;
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::Farbfeld })
+}
@@ -0,0 +1,59 @@
+// sixela::loaders::gif
+//
+// TOC
+// - fn load_gif
+
+use super::{check_size, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+use ::gif::{ColorOutput, DecodeOptions};
+use alloc::vec;
+
+/// Loads the first frame of a GIF image, with the `gif` crate, as `RGBA8888`.
+///
+/// The frame is placed over a transparent canvas of the size of the logical screen.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// use gif::{Encoder, Frame};
+/// // a 1x2 frame at the right of a 2x2 screen, with a palette of red and blue
+/// let mut gif = Vec::new();
+/// let mut encoder = Encoder::new(&mut gif, 2, 2, &[255, 0, 0, 0, 0, 255]).unwrap();
+/// let buffer = vec![0, 1].into();
+/// let frame = Frame { left: 1, width: 1, height: 2, buffer, ..Frame::default() };
+/// encoder.write_frame(&frame).unwrap();
+/// drop(encoder);
+///
+/// let image = LoadedImage::load(&gif).unwrap();
+/// assert_eq![(image.width, image.height, image.pixelformat), (2, 2, PixelFormat::RGBA8888)];
+/// assert_eq![image.pixels, [[0; 4], [255, 0, 0, 255], [0; 4], [0, 0, 255, 255]].concat()];
+///
+/// // truncated data
+/// assert_eq![LoadedImage::load(&gif[..20]), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_gif(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut options = DecodeOptions::new();
+    options.set_color_output(ColorOutput::RGBA);
+    let mut decoder = options.read_info(bytes).map_err(|_| SixelError::BadInput)?;
+    let (width, height) = (decoder.width() as usize, decoder.height() as usize);
+    check_size(width, height)?;
+    let frame = decoder
+        .read_next_frame()
+        .map_err(|_| SixelError::BadInput)?
+        .ok_or(SixelError::BadInput)?;
+
+    let mut pixels = vec![0; width * height * 4];
+    let (left, top) = (frame.left as usize, frame.top as usize);
+    let frame_width = frame.width as usize;
+    for (y, row) in frame.buffer.chunks_exact(frame_width * 4).enumerate() {
+        let y = top + y;
+        if y >= height || left >= width {
+            break;
+        }
+        let len = frame_width.min(width - left) * 4;
+        let start = (y * width + left) * 4;
+        pixels[start..start + len].copy_from_slice(&row[..len]);
+    }
+    let pixelformat = PixelFormat::RGBA8888;
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::GIF })
+}
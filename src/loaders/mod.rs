@@ -0,0 +1,173 @@
+// sixela::loaders
+//
+// TOC
+// - struct LoadedImage
+// - struct ByteReader
+// - fn check_size
+// - fn scale_to_u8
+
+use crate::{Format, PixelFormat, Sixel, SixelError, SixelImage, SixelResult};
+use devela::Vec;
+
+mod bmp;
+mod farbfeld;
+#[cfg(feature = "gif")]
+mod gif;
+#[cfg(feature = "png")]
+mod png;
+mod pnm;
+mod qoi;
+mod tga;
+
+/// Maximum number of pixels a loaded image is allowed to have.
+const LOAD_PIXELS_MAX: usize = 1 << 26;
+
+/// An image loaded from an image file, ready to be encoded as a [`Sixel`].
+///
+/// PNM, BMP, TGA, QOI, farbfeld and sixel files are always supported,
+/// while PNG and GIF files require the `png` and `gif` features.
+///
+/// # Example
+/// ```
+/// # use sixela::{Format, LoadedImage, PixelFormat};
+/// // 2x1 pixels (Red, Blue)
+/// let ppm = b"P6\n# a comment\n2 1\n255\n\xff\x00\x00\x00\x00\xff";
+/// let image = LoadedImage::load(ppm).unwrap();
+/// assert_eq![(image.width, image.height), (2, 1)];
+/// assert_eq![(image.format, image.pixelformat), (Format::PNM, PixelFormat::RGB888)];
+/// assert_eq![image.pixels, [255, 0, 0, 0, 0, 255]];
+///
+/// let sixel = image.sixel().build().unwrap();
+/// assert![sixel.starts_with("\x1bPq\"1;1;2;1#")];
+/// ```
+#[cfg_attr(feature = "nightly", doc(cfg(feature = "loaders")))]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct LoadedImage {
+    /// The width in pixels.
+    pub width: usize,
+    /// The height in pixels.
+    pub height: usize,
+    /// The pixels in row-major order, laid out as `pixelformat`.
+    pub pixels: Vec<u8>,
+    /// The layout of the pixels.
+    pub pixelformat: PixelFormat,
+    /// The format of the image file.
+    pub format: Format,
+}
+
+impl LoadedImage {
+    /// Loads an image file, detecting its format from its magic bytes.
+    ///
+    /// The files without known magic bytes are tried as [`TGA`][Format::TGA] images.
+    ///
+    /// # Errors
+    /// Returns [`NotImplemented`][SixelError::NotImplemented] for the unsupported formats,
+    /// and [`BadInput`][SixelError::BadInput] for malformed files.
+    pub fn load(bytes: &[u8]) -> SixelResult<Self> {
+        Self::load_format(bytes, Format::detect(bytes).unwrap_or(Format::TGA))
+    }
+
+    /// Loads an image file of the given format.
+    ///
+    /// Only the first image of animated and multi-image files is loaded.
+    ///
+    /// # Errors
+    /// Returns [`NotImplemented`][SixelError::NotImplemented] for the unsupported formats,
+    /// and [`BadInput`][SixelError::BadInput] for malformed files.
+    pub fn load_format(bytes: &[u8], format: Format) -> SixelResult<Self> {
+        match format {
+            Format::PNM => pnm::load_pnm(bytes),
+            Format::BMP => bmp::load_bmp(bytes),
+            Format::TGA => tga::load_tga(bytes),
+            Format::QOI => qoi::load_qoi(bytes),
+            Format::Farbfeld => farbfeld::load_farbfeld(bytes),
+            Format::SIXEL => {
+                let image = SixelImage::decode(bytes)?;
+                Ok(Self {
+                    width: image.width,
+                    height: image.height,
+                    pixels: image.to_rgba8888(),
+                    pixelformat: PixelFormat::RGBA8888,
+                    format,
+                })
+            }
+            #[cfg(feature = "png")]
+            Format::PNG => png::load_png(bytes),
+            #[cfg(feature = "gif")]
+            Format::GIF => gif::load_gif(bytes),
+            _ => Err(SixelError::NotImplemented),
+        }
+    }
+
+    /// Returns a new sixel builder for the pixels of the image.
+    #[must_use]
+    pub fn sixel(&self) -> Sixel<'_> {
+        Sixel::with_bytes_size(&self.pixels, self.width as i32, self.height as i32)
+            .format(self.pixelformat)
+    }
+}
+
+/// Reads the fields of a binary image file.
+///
+/// Every read fails with [`BadInput`][SixelError::BadInput] past the end of the bytes.
+#[derive(Clone, Copy, Debug)]
+struct ByteReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> ByteReader<'a> {
+    /// Returns a new reader starting at `pos`.
+    const fn new(bytes: &'a [u8], pos: usize) -> Self {
+        Self { bytes, pos }
+    }
+
+    /// Returns the next `len` bytes.
+    fn take(&mut self, len: usize) -> SixelResult<&'a [u8]> {
+        let end = self.pos.checked_add(len).ok_or(SixelError::BadInput)?;
+        let bytes = self.bytes.get(self.pos..end).ok_or(SixelError::BadInput)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    /// Skips the next `len` bytes.
+    fn skip(&mut self, len: usize) -> SixelResult<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn u8(&mut self) -> SixelResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+    fn u16_le(&mut self) -> SixelResult<u16> {
+        let b = self.take(2)?;
+        Ok(u16::from_le_bytes([b[0], b[1]]))
+    }
+    fn u32_le(&mut self) -> SixelResult<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+    fn u32_be(&mut self) -> SixelResult<u32> {
+        let b = self.take(4)?;
+        Ok(u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+}
+
+/// Checks that the image is not empty and not too big to be loaded.
+fn check_size(width: usize, height: usize) -> SixelResult<()> {
+    match width.checked_mul(height) {
+        Some(pixels) if pixels > 0 && pixels <= LOAD_PIXELS_MAX => Ok(()),
+        _ => Err(SixelError::BadInput),
+    }
+}
+
+/// Scales a sample from the range `0..=max` to the range `0..=255`, rounding it.
+#[must_use]
+const fn scale_to_u8(value: u32, max: u32) -> u8 {
+    if max == 0 {
+        0
+    } else if value >= max {
+        255
+    } else {
+        ((value as u64 * 255 + max as u64 / 2) / max as u64) as u8
+    }
+}
@@ -0,0 +1,60 @@
+// sixela::loaders::png
+//
+// TOC
+// - fn load_png
+
+use super::{check_size, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+use ::png::{ColorType, Decoder, Transformations};
+use alloc::vec;
+
+/// Loads a PNG image, with the `png` crate.
+///
+/// Palettes and low bit depths are expanded, and 16-bit samples are stripped to 8 bits,
+/// so grayscale images are loaded as `G8` or `GA88`, and color images as `RGB888` or `RGBA8888`.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// use png::{BitDepth, ColorType, Encoder};
+/// let encode = |color: ColorType, depth: BitDepth, palette: &[u8], data: &[u8]| {
+///     let mut png = Vec::new();
+///     let mut encoder = Encoder::new(&mut png, 2, 1);
+///     encoder.set_color(color);
+///     encoder.set_depth(depth);
+///     if !palette.is_empty() {
+///         encoder.set_palette(palette);
+///     }
+///     encoder.write_header().unwrap().write_image_data(data).unwrap();
+///     png
+/// };
+/// let load = |png: &[u8]| LoadedImage::load(png).map(|image| (image.pixelformat, image.pixels));
+///
+/// // the indices are expanded into the palette colors
+/// let png = encode(ColorType::Indexed, BitDepth::One, &[255, 0, 0, 0, 0, 255], &[0b0100_0000]);
+/// assert_eq![load(&png), Ok((PixelFormat::RGB888, vec![255, 0, 0, 0, 0, 255]))];
+/// // the 16-bit samples are stripped to 8 bits
+/// let png = encode(ColorType::Grayscale, BitDepth::Sixteen, &[], &[0x12, 0x34, 0xAB, 0xCD]);
+/// assert_eq![load(&png), Ok((PixelFormat::G8, vec![0x12, 0xAB]))];
+///
+/// // truncated data
+/// assert_eq![load(&png[..png.len() - 20]), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_png(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut decoder = Decoder::new(bytes);
+    decoder.set_transformations(Transformations::EXPAND | Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|_| SixelError::BadInput)?;
+    let (width, height) = (reader.info().width as usize, reader.info().height as usize);
+    check_size(width, height)?;
+    let mut pixels = vec![0; reader.output_buffer_size()];
+    let frame = reader.next_frame(&mut pixels).map_err(|_| SixelError::BadInput)?;
+    pixels.truncate(frame.buffer_size());
+    let pixelformat = match frame.color_type {
+        ColorType::Grayscale => PixelFormat::G8,
+        ColorType::GrayscaleAlpha => PixelFormat::GA88,
+        ColorType::Rgb => PixelFormat::RGB888,
+        ColorType::Rgba => PixelFormat::RGBA8888,
+        ColorType::Indexed => return Err(SixelError::BadInput),
+    };
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::PNG })
+}
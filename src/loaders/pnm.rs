@@ -0,0 +1,221 @@
+// sixela::loaders::pnm
+//
+// TOC
+// - fn load_pnm
+// - fn load_pam
+// - fn raster
+// - fn raw_samples
+// - struct PnmHeader
+
+use super::{check_size, scale_to_u8, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+use alloc::vec;
+use devela::Vec;
+
+/// Loads a Netpbm image: PBM (`P1`, `P4`), PGM (`P2`, `P5`), PPM (`P3`, `P6`) or PAM (`P7`).
+///
+/// Bitmaps and graymaps are loaded as `G8`, pixmaps as `RGB888`, and arbitrary maps
+/// as `G8`, `GA88`, `RGB888` or `RGBA8888` depending on their depth.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// let load = |pnm: &[u8]| LoadedImage::load(pnm).map(|image| (image.pixelformat, image.pixels));
+///
+/// // plain bitmap, where 1 is black, and graymap with a maximum value of 4
+/// assert_eq![load(b"P1 3 1 010"), Ok((PixelFormat::G8, vec![255, 0, 255]))];
+/// assert_eq![load(b"P2\n2 1\n4\n0 2\n"), Ok((PixelFormat::G8, vec![0, 128]))];
+/// // plain pixmap
+/// assert_eq![load(b"P3 1 1 255 1 2 3"), Ok((PixelFormat::RGB888, vec![1, 2, 3]))];
+/// // raw bitmap, with each row padded to a byte
+/// assert_eq![load(b"P4 3 1\n\x40"), Ok((PixelFormat::G8, vec![255, 0, 255]))];
+/// // raw graymap with 16-bit samples
+/// assert_eq![load(b"P5 1 1 65535\n\x80\x00"), Ok((PixelFormat::G8, vec![128]))];
+/// // arbitrary map with gray and alpha
+/// let pam = b"P7\nWIDTH 1\nHEIGHT 1\nDEPTH 2\nMAXVAL 255\nTUPLTYPE GRAYSCALE_ALPHA\nENDHDR\n";
+/// let pam = [&pam[..], &[7, 200]].concat();
+/// assert_eq![load(&pam), Ok((PixelFormat::GA88, vec![7, 200]))];
+///
+/// // truncated data
+/// assert_eq![load(b"P3 1 1 255 1 2"), Err(SixelError::BadInput)];
+/// assert_eq![load(b"P6 1 1 255\n\x01\x02"), Err(SixelError::BadInput)];
+/// assert_eq![load(&pam[..pam.len() - 1]), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_pnm(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let kind = match bytes {
+        [b'P', kind @ b'1'..=b'7', ..] => kind - b'0',
+        _ => return Err(SixelError::BadInput),
+    };
+    if kind == 7 {
+        return load_pam(bytes);
+    }
+    let mut header = PnmHeader { bytes, pos: 2 };
+    let width = header.number()?;
+    let height = header.number()?;
+    let maxval = if matches!(kind, 1 | 4) {
+        1
+    } else {
+        header.number()?
+    };
+    check_size(width, height)?;
+    if maxval == 0 || maxval > u16::MAX as usize {
+        return Err(SixelError::BadInput);
+    }
+    let channels = if matches!(kind, 3 | 6) { 3 } else { 1 };
+    let samples = width * height * channels;
+    let mut pixels = Vec::with_capacity(samples);
+    match kind {
+        // plain bitmap, with optionally separated digits, where 1 is black
+        1 => {
+            while pixels.len() < samples {
+                header.skip_space();
+                match header.bytes.get(header.pos) {
+                    Some(b'0') => pixels.push(255),
+                    Some(b'1') => pixels.push(0),
+                    _ => return Err(SixelError::BadInput),
+                }
+                header.pos += 1;
+            }
+        }
+        // plain graymap and pixmap
+        2 | 3 => {
+            while pixels.len() < samples {
+                pixels.push(scale_to_u8(header.number()? as u32, maxval as u32));
+            }
+        }
+        // raw bitmap, with each row padded to a byte, where 1 is black
+        4 => {
+            let row_bytes = width.div_ceil(8);
+            let raster = raster(bytes, header.pos + 1, row_bytes * height)?;
+            for row in raster.chunks_exact(row_bytes) {
+                pixels.extend((0..width).map(|x| {
+                    if row[x / 8] & (0x80 >> (x % 8)) != 0 {
+                        0
+                    } else {
+                        255
+                    }
+                }));
+            }
+        }
+        // raw graymap and pixmap
+        _ => pixels = raw_samples(bytes, header.pos + 1, samples, maxval as u32)?,
+    }
+    let pixelformat = if channels == 3 {
+        PixelFormat::RGB888
+    } else {
+        PixelFormat::G8
+    };
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::PNM })
+}
+
+/// Loads a PAM image, with a depth of 1 to 4 channels.
+fn load_pam(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut header = PnmHeader { bytes, pos: 2 };
+    let (mut width, mut height, mut depth, mut maxval) = (0, 0, 0, 0);
+    loop {
+        match header.token()? {
+            b"WIDTH" => width = header.number()?,
+            b"HEIGHT" => height = header.number()?,
+            b"DEPTH" => depth = header.number()?,
+            b"MAXVAL" => maxval = header.number()?,
+            // the tuple type is implied by the depth
+            b"TUPLTYPE" => {
+                header.token()?;
+            }
+            b"ENDHDR" => break,
+            _ => return Err(SixelError::BadInput),
+        }
+    }
+    check_size(width, height)?;
+    if maxval == 0 || maxval > u16::MAX as usize {
+        return Err(SixelError::BadInput);
+    }
+    let pixelformat = match depth {
+        1 => PixelFormat::G8,
+        2 => PixelFormat::GA88,
+        3 => PixelFormat::RGB888,
+        4 => PixelFormat::RGBA8888,
+        _ => return Err(SixelError::NotImplemented),
+    };
+    let pixels = raw_samples(bytes, header.pos + 1, width * height * depth, maxval as u32)?;
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::PNM })
+}
+
+/// Returns the `len` bytes of the raster starting at `pos`.
+fn raster(bytes: &[u8], pos: usize, len: usize) -> SixelResult<&[u8]> {
+    bytes.get(pos..pos.saturating_add(len)).ok_or(SixelError::BadInput)
+}
+
+/// Returns the raw samples starting at `pos`, scaled to 8 bits.
+///
+/// The samples take 2 big-endian bytes when `maxval` is greater than 255.
+fn raw_samples(bytes: &[u8], pos: usize, samples: usize, maxval: u32) -> SixelResult<Vec<u8>> {
+    if maxval > 255 {
+        let raster = raster(bytes, pos, samples * 2)?;
+        Ok(raster
+            .chunks_exact(2)
+            .map(|s| scale_to_u8(u16::from_be_bytes([s[0], s[1]]) as u32, maxval))
+            .collect())
+    } else if maxval == 255 {
+        Ok(raster(bytes, pos, samples)?.to_vec())
+    } else {
+        let raster = raster(bytes, pos, samples)?;
+        let mut pixels = vec![0; samples];
+        for (p, &s) in pixels.iter_mut().zip(raster) {
+            *p = scale_to_u8(s as u32, maxval);
+        }
+        Ok(pixels)
+    }
+}
+
+/// Reads the whitespace separated tokens of a Netpbm header, skipping the comments.
+struct PnmHeader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PnmHeader<'a> {
+    /// Skips the whitespace and the comments, up to the end of their lines.
+    fn skip_space(&mut self) {
+        while let Some(&b) = self.bytes.get(self.pos) {
+            if b == b'#' {
+                while self.bytes.get(self.pos).is_some_and(|&b| b != b'\n') {
+                    self.pos += 1;
+                }
+            } else if b.is_ascii_whitespace() {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    /// Returns the next token, leaving the position at the whitespace after it.
+    fn token(&mut self) -> SixelResult<&'a [u8]> {
+        self.skip_space();
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(|b| !b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+        if start == self.pos {
+            return Err(SixelError::BadInput);
+        }
+        Ok(&self.bytes[start..self.pos])
+    }
+
+    /// Returns the next token as a decimal number.
+    fn number(&mut self) -> SixelResult<usize> {
+        let token = self.token()?;
+        let mut number: usize = 0;
+        for &digit in token {
+            if !digit.is_ascii_digit() {
+                return Err(SixelError::BadInput);
+            }
+            number = number
+                .checked_mul(10)
+                .and_then(|n| n.checked_add((digit - b'0') as usize))
+                .ok_or(SixelError::BadIntegerOverflow)?;
+        }
+        Ok(number)
+    }
+}
@@ -0,0 +1,89 @@
+// sixela::loaders::qoi
+//
+// TOC
+// - fn load_qoi
+
+use super::{check_size, ByteReader, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+use devela::Vec;
+
+const QOI_OP_INDEX: u8 = 0x00;
+const QOI_OP_DIFF: u8 = 0x40;
+const QOI_OP_LUMA: u8 = 0x80;
+const QOI_OP_RGB: u8 = 0xFE;
+const QOI_OP_RGBA: u8 = 0xFF;
+
+/// Loads a Quite OK Image, as `RGB888` or `RGBA8888` depending on its channels.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// let mut qoi = b"qoif\0\0\0\x02\0\0\0\x03\x03\0".to_vec(); // 2x3 RGB
+/// qoi.extend([0xFE, 255, 0, 0]); // QOI_OP_RGB: red
+/// qoi.push(0x5B); // QOI_OP_DIFF: -1, 0, +1
+/// qoi.extend([0xAA, 0xA6]); // QOI_OP_LUMA: green +10, red +12 and blue +8
+/// qoi.push(0x32); // QOI_OP_INDEX: red, at its hash position 50
+/// qoi.push(0xC1); // QOI_OP_RUN: red, 2 more times
+/// qoi.extend([0, 0, 0, 0, 0, 0, 0, 1]); // end marker
+///
+/// let image = LoadedImage::load(&qoi).unwrap();
+/// assert_eq![(image.width, image.height, image.pixelformat), (2, 3, PixelFormat::RGB888)];
+/// let red = [255, 0, 0];
+/// assert_eq![image.pixels, [red, [254, 0, 1], [10, 10, 9], red, red, red].concat()];
+///
+/// // truncated data
+/// assert_eq![LoadedImage::load(&qoi[..qoi.len() - 9]), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_qoi(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut r = ByteReader::new(bytes, 0);
+    if r.take(4)? != b"qoif" {
+        return Err(SixelError::BadInput);
+    }
+    let width = r.u32_be()? as usize;
+    let height = r.u32_be()? as usize;
+    let channels = r.u8()? as usize;
+    r.skip(1)?; // the colorspace doesn't change the values
+    check_size(width, height)?;
+    let pixelformat = match channels {
+        3 => PixelFormat::RGB888,
+        4 => PixelFormat::RGBA8888,
+        _ => return Err(SixelError::BadInput),
+    };
+
+    let mut index = [[0_u8; 4]; 64];
+    let mut px = [0, 0, 0, 255_u8];
+    let mut pixels = Vec::with_capacity(width * height * channels);
+    let mut run = 0;
+    for _ in 0..width * height {
+        if run > 0 {
+            run -= 1;
+        } else {
+            let op = r.u8()?;
+            match op {
+                QOI_OP_RGB => px[..3].copy_from_slice(r.take(3)?),
+                QOI_OP_RGBA => px.copy_from_slice(r.take(4)?),
+                _ => match op & 0xC0 {
+                    QOI_OP_INDEX => px = index[op as usize],
+                    QOI_OP_DIFF => {
+                        px[0] = px[0].wrapping_add((op >> 4) & 0x3).wrapping_sub(2);
+                        px[1] = px[1].wrapping_add((op >> 2) & 0x3).wrapping_sub(2);
+                        px[2] = px[2].wrapping_add(op & 0x3).wrapping_sub(2);
+                    }
+                    QOI_OP_LUMA => {
+                        let next = r.u8()?;
+                        let dg = (op & 0x3F).wrapping_sub(32);
+                        px[0] = px[0].wrapping_add(dg.wrapping_sub(8).wrapping_add(next >> 4));
+                        px[1] = px[1].wrapping_add(dg);
+                        px[2] = px[2].wrapping_add(dg.wrapping_sub(8).wrapping_add(next & 0xF));
+                    }
+                    // QOI_OP_RUN
+                    _ => run = op & 0x3F,
+                },
+            }
+            let [red, green, blue, alpha] = px.map(|c| c as usize);
+            index[(red * 3 + green * 5 + blue * 7 + alpha * 11) % 64] = px;
+        }
+        pixels.extend_from_slice(&px[..channels]);
+    }
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::QOI })
+}
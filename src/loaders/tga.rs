@@ -0,0 +1,161 @@
+// sixela::loaders::tga
+//
+// TOC
+// - fn load_tga
+// - fn read_color
+
+use super::{check_size, scale_to_u8, ByteReader, LoadedImage};
+use crate::{Format, PixelFormat, SixelError, SixelResult};
+use alloc::vec;
+use devela::Vec;
+
+/// Loads a Truevision TGA image, optionally run-length encoded.
+///
+/// Grayscale images are loaded as `G8`, or as `GA88` with alpha,
+/// and color-mapped and true-color images as `RGB888`, or as `RGBA8888` with alpha.
+///
+/// # Example
+/// ```
+/// # use sixela::{LoadedImage, PixelFormat, SixelError};
+/// // the header of a 2x2 image, without an id nor a color map
+/// let header = |image_type: u8, depth: u8, descriptor: u8| {
+///     vec![0, 0, image_type, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 0, 2, 0, depth, descriptor]
+/// };
+/// let load = |tga: &[u8]| LoadedImage::load(tga).map(|image| image.pixels);
+/// let (red, green, blue, white) = ([255, 0, 0], [0, 255, 0], [0, 0, 255], [255; 3]);
+///
+/// // true-color BGR pixels, from the bottom left corner
+/// let tga = [header(2, 24, 0), vec![0, 0, 255, 0, 255, 0, 255, 0, 0, 255, 255, 255]].concat();
+/// assert_eq![load(&tga), Ok([blue, white, red, green].concat())];
+///
+/// // run-length encoded, from the top left corner: a run of 2 reds, then 2 raw pixels
+/// let rle = [0x81, 0, 0, 255, 0x01, 255, 0, 0, 255, 255, 255];
+/// let tga = [header(10, 24, 0x20), rle.to_vec()].concat();
+/// assert_eq![load(&tga), Ok([red, red, blue, white].concat())];
+///
+/// // color-mapped, from the top right corner, with a map of 2 BGR colors
+/// let mut tga = header(1, 8, 0x30);
+/// (tga[1], tga[5], tga[7]) = (1, 2, 24);
+/// tga.extend([0, 255, 0, 255, 0, 0]);
+/// tga.extend([0, 0, 1, 0]);
+/// assert_eq![load(&tga), Ok([green, green, green, blue].concat())];
+///
+/// // grayscale
+/// let tga = [header(3, 8, 0x20), vec![0, 85, 170, 255]].concat();
+/// let image = LoadedImage::load(&tga).unwrap();
+/// assert_eq![(image.pixelformat, image.pixels), (PixelFormat::G8, vec![0, 85, 170, 255])];
+///
+/// // truncated data
+/// assert_eq![load(&tga[..tga.len() - 1]), Err(SixelError::BadInput)];
+/// ```
+pub(super) fn load_tga(bytes: &[u8]) -> SixelResult<LoadedImage> {
+    let mut r = ByteReader::new(bytes, 0);
+    let id_len = r.u8()? as usize;
+    let colormap_type = r.u8()?;
+    let image_type = r.u8()?;
+    let colormap_first = r.u16_le()? as usize;
+    let colormap_len = r.u16_le()? as usize;
+    let colormap_depth = r.u8()?;
+    r.skip(4)?;
+    let width = r.u16_le()? as usize;
+    let height = r.u16_le()? as usize;
+    let depth = r.u8()?;
+    let descriptor = r.u8()?;
+
+    let (rle, kind) = (image_type & 0x8 != 0, image_type & !0x8);
+    let alpha_bits = descriptor & 0xF;
+    let valid = colormap_type <= 1
+        && matches!(kind, 1..=3)
+        && (kind == 1) == (colormap_type == 1)
+        && match kind {
+            1 => depth == 8 && matches!(colormap_depth, 15 | 16 | 24 | 32),
+            2 => matches!(depth, 15 | 16 | 24 | 32),
+            _ => matches!(depth, 8 | 16),
+        };
+    if !valid {
+        return Err(SixelError::BadInput);
+    }
+    check_size(width, height)?;
+    r.skip(id_len)?;
+
+    // the color map, as RGBA colors
+    let mut colormap = Vec::new();
+    if colormap_type == 1 {
+        for _ in 0..colormap_len {
+            colormap.push(read_color(&mut r, colormap_depth)?);
+        }
+    }
+    let entry_depth = if kind == 1 { colormap_depth } else { depth };
+    let (channels, pixelformat) = match (kind, entry_depth, alpha_bits) {
+        (3, 8, _) => (1, PixelFormat::G8),
+        (3, _, _) => (2, PixelFormat::GA88),
+        (_, 32, 1..) => (4, PixelFormat::RGBA8888),
+        _ => (3, PixelFormat::RGB888),
+    };
+
+    // read the pixels in file order, as RGBA colors or as gray and alpha values
+    let read_pixel = |r: &mut ByteReader| -> SixelResult<[u8; 4]> {
+        match kind {
+            1 => {
+                let index = (r.u8()? as usize).wrapping_sub(colormap_first);
+                Ok(colormap.get(index).copied().unwrap_or([0, 0, 0, 255]))
+            }
+            2 => read_color(r, depth),
+            _ if depth == 8 => Ok([r.u8()?, 0, 0, 0]),
+            _ => Ok([r.u8()?, r.u8()?, 0, 0]),
+        }
+    };
+    let mut file_pixels = Vec::with_capacity(width * height);
+    while file_pixels.len() < width * height {
+        if rle {
+            let packet = r.u8()?;
+            let count = (packet & 0x7F) as usize + 1;
+            if packet & 0x80 != 0 {
+                let pixel = read_pixel(&mut r)?;
+                file_pixels.extend((0..count).map(|_| pixel));
+            } else {
+                for _ in 0..count {
+                    file_pixels.push(read_pixel(&mut r)?);
+                }
+            }
+        } else {
+            file_pixels.push(read_pixel(&mut r)?);
+        }
+    }
+    file_pixels.truncate(width * height);
+
+    // reorder them from the top left corner
+    let (right_to_left, top_down) = (descriptor & 0x10 != 0, descriptor & 0x20 != 0);
+    let mut pixels = vec![0; width * height * channels];
+    for (i, color) in file_pixels.iter().enumerate() {
+        let (mut x, mut y) = (i % width, i / width);
+        if right_to_left {
+            x = width - 1 - x;
+        }
+        if !top_down {
+            y = height - 1 - y;
+        }
+        let offset = (y * width + x) * channels;
+        pixels[offset..offset + channels].copy_from_slice(&color[..channels]);
+    }
+    Ok(LoadedImage { width, height, pixels, pixelformat, format: Format::TGA })
+}
+
+/// Reads a little-endian BGR(A) color of the given bit depth, as RGBA.
+fn read_color(r: &mut ByteReader, depth: u8) -> SixelResult<[u8; 4]> {
+    match depth {
+        15 | 16 => {
+            let c = r.u16_le()? as u32;
+            let channel = |shift: u32| scale_to_u8((c >> shift) & 0x1F, 0x1F);
+            Ok([channel(10), channel(5), channel(0), 255])
+        }
+        24 => {
+            let b = r.take(3)?;
+            Ok([b[2], b[1], b[0], 255])
+        }
+        _ => {
+            let b = r.take(4)?;
+            Ok([b[2], b[1], b[0], b[3]])
+        }
+    }
+}
@@ -0,0 +1,242 @@
+// sixela::output::animation
+//
+// TOC
+// - struct SixelAnimation
+// - struct SixelChunk
+
+use super::SIXEL_DEFAULT_GIF_DELAY;
+use crate::{Loop, Rewind, Sixel, SixelError, SixelResult};
+use alloc::{format, vec};
+use core::time::Duration;
+use devela::{sys::Write as IoWrite, ConstDefault, Vec};
+
+/// A configurable builder of sixel animations, from a sequence of frames with delays.
+///
+/// Every frame is encoded with the options of the [`Sixel`] builder, including the size
+/// and pixel format of the frames, and the cursor is [rewound][Rewind] around each frame
+/// so that they overwrite each other.
+///
+/// The frames share a single palette, computed from all of them,
+/// unless the palette is fixed or the quality is [`HighColor`][crate::Quality::HighColor].
+///
+/// # Example
+/// ```
+/// # use core::time::Duration;
+/// # use sixela::{Loop, Sixel, SixelAnimation};
+/// let (red, blue) = ([255, 0, 0].repeat(16), [0, 0, 255].repeat(16));
+/// let animation = SixelAnimation::new(Sixel::with_size(4, 4))
+///     .frame(&red, Duration::from_millis(100))
+///     .frame(&blue, Duration::from_millis(200))
+///     .loop_count(2);
+///
+/// let chunks = animation.build_chunks().unwrap();
+/// assert_eq![chunks.len(), 2];
+/// assert_eq![chunks[1].delay, Duration::from_millis(200)];
+/// assert![chunks[0].bytes.starts_with(b"\x1b7\x1bPq") && chunks[0].bytes.ends_with(b"\x1b8")];
+///
+/// // the single byte stream repeats the frames for each play
+/// let stream = animation.build_bytes().unwrap();
+/// assert_eq![stream.windows(3).filter(|w| w == b"\x1bPq").count(), 4];
+/// assert_eq![animation.plays(), Some(2)];
+///
+/// // the unchanged frames are empty, besides the cursor rewinding
+/// let chunks = animation.frame(&blue, Duration::ZERO).differential(true).build_chunks().unwrap();
+/// assert_eq![chunks[2].bytes, b"\x1b7\x1b8"];
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SixelAnimation<'a> {
+    pub sixel: Sixel<'a>,
+    pub frames: Vec<(&'a [u8], Duration)>,
+    pub loop_mode: Loop,
+    pub loop_count: u32,
+    pub rewind: Rewind,
+    pub differential: bool,
+}
+impl<'a> ConstDefault for SixelAnimation<'a> {
+    const DEFAULT: Self = Self::new(Sixel::DEFAULT);
+}
+impl Default for SixelAnimation<'_> {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A single encoded frame of a [`SixelAnimation`], to be displayed for its `delay`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct SixelChunk {
+    /// The sixel data of the frame, including the cursor rewinding sequences.
+    pub bytes: Vec<u8>,
+    /// The time to wait before displaying the next frame.
+    pub delay: Duration,
+}
+
+#[rustfmt::skip]
+impl<'a> SixelAnimation<'a> {
+    /// Returns a new empty animation builder, that encodes the frames with the given options.
+    ///
+    /// The bytes of the `sixel` builder are ignored.
+    #[inline] #[must_use]
+    pub const fn new(sixel: Sixel<'a>) -> Self {
+        Self {
+            sixel,
+            frames: Vec::new(),
+            loop_mode: Loop::DEFAULT,
+            loop_count: 1,
+            rewind: Rewind::DEFAULT,
+            differential: false,
+        }
+    }
+
+    /// Adds a frame, to be displayed for the given `delay`.
+    ///
+    /// A zero delay is replaced with the default delay of 1 hundredth of a second.
+    #[inline] #[must_use]
+    pub fn frame(mut self, bytes: &'a [u8], delay: Duration) -> Self {
+        let delay = if delay.is_zero() {
+            Duration::from_millis(SIXEL_DEFAULT_GIF_DELAY * 10)
+        } else {
+            delay
+        };
+        self.frames.push((bytes, delay)); self
+    }
+    /// Sets the loop mode.
+    #[inline] #[must_use]
+    pub const fn loop_mode(mut self, loop_mode: Loop) -> Self {
+        self.loop_mode = loop_mode; self
+    }
+    /// Sets the number of times to play the animation with [`Loop::Auto`],
+    /// or 0 to play it forever. Defaults to 1.
+    #[inline] #[must_use]
+    pub const fn loop_count(mut self, loop_count: u32) -> Self {
+        self.loop_count = loop_count; self
+    }
+    /// Sets how the cursor returns to the origin of the animation before each frame.
+    ///
+    /// # Example
+    /// ```
+    /// # use core::time::Duration;
+    /// # use sixela::{Rewind, Sixel, SixelAnimation};
+    /// let black = [0, 0, 0];
+    /// let mut animation = SixelAnimation::new(Sixel::with_size(1, 1))
+    ///     .frame(&black, Duration::ZERO)
+    ///     .rewind(Rewind::Position(2, 3));
+    /// let chunks = animation.build_chunks().unwrap();
+    /// assert_eq![chunks[0].bytes, b"\x1b[2;3H\x1bPq\"1;1;1;1#0;2;0;0;0#0@\x1b\\"];
+    ///
+    /// // with 8-bit controls the CSI is the single byte 0x9B
+    /// animation.sixel = animation.sixel.control_8bit(true);
+    /// let chunks = animation.build_chunks().unwrap();
+    /// assert_eq![chunks[0].bytes, b"\x9b2;3H\x90q\"1;1;1;1#0;2;0;0;0#0@\x9c"];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn rewind(mut self, rewind: Rewind) -> Self {
+        self.rewind = rewind; self
+    }
+
+    /// Sets whether to encode only the bands that changed from the previous frame.
+    ///
+    /// See [`SixelOutput::set_differential`][crate::SixelOutput::set_differential].
+    #[inline] #[must_use]
+    pub const fn differential(mut self, differential: bool) -> Self {
+        self.differential = differential; self
+    }
+
+    /// Returns the number of times to play the animation, or `None` to play it forever.
+    #[inline] #[must_use]
+    pub const fn plays(&self) -> Option<u32> {
+        match self.loop_mode {
+            Loop::Auto if self.loop_count == 0 => None,
+            Loop::Auto => Some(self.loop_count),
+            Loop::Force => None,
+            Loop::Disable => Some(1),
+        }
+    }
+}
+
+impl SixelAnimation<'_> {
+    /// Builds one chunk of sixel data per frame, for a single play of the animation.
+    ///
+    /// To loop the animation, display the same chunks again for each of the
+    /// [`plays`][Self::plays], waiting for the delay of each chunk.
+    ///
+    /// # Errors
+    /// Returns [`BadInput`][SixelError::BadInput] if there are no frames,
+    /// or any of the errors of [`Sixel::build_to`] for any of the frames.
+    pub fn build_chunks(&self) -> SixelResult<Vec<SixelChunk>> {
+        if self.frames.is_empty() || self.sixel.width <= 0 || self.sixel.height <= 0 {
+            return Err(SixelError::BadInput);
+        }
+        let mut frames = Vec::with_capacity(self.frames.len());
+        for (bytes, delay) in &self.frames {
+            frames.push((self.sixel.resized(bytes)?, *delay));
+        }
+        let (_, format, width, height) = frames[0].0;
+        let frame_len = format.required_bytes(width, height);
+        if frames.iter().any(|((bytes, ..), _)| bytes.len() < frame_len) {
+            return Err(SixelError::BadInput);
+        }
+
+        // compute a single palette from a sample of the frames
+        let all: Vec<&[u8]> = frames.iter().map(|((bytes, ..), _)| &bytes[..]).collect();
+        let mut dither_conf = self.sixel.new_dither_conf(&all, format, width, height)?;
+        // keep the same palette indices for every frame
+        dither_conf.set_optimize_palette(false);
+
+        let (prefix, suffix) = self.rewind_sequences();
+        let mut sixel_output = self.sixel.new_output(Vec::new());
+        sixel_output.set_differential(self.differential);
+        let mut chunks = Vec::with_capacity(frames.len());
+        for ((bytes, ..), delay) in &frames {
+            sixel_output.get_mut().extend_from_slice(&prefix);
+            sixel_output.encode(bytes, width, height, &mut dither_conf)?;
+            sixel_output.get_mut().extend_from_slice(suffix);
+            let bytes = core::mem::take(sixel_output.get_mut());
+            chunks.push(SixelChunk { bytes, delay: *delay });
+        }
+        Ok(chunks)
+    }
+
+    /// Builds the animation as a single byte vector, with the frames of every play.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`build_to`][Self::build_to].
+    pub fn build_bytes(&self) -> SixelResult<Vec<u8>> {
+        let mut sixel = Vec::new();
+        self.build_to(&mut sixel)?;
+        Ok(sixel)
+    }
+
+    /// Builds the animation as a single byte stream, writing the frames of every play
+    /// into `writer`, without waiting for their delays.
+    ///
+    /// # Errors
+    /// Returns [`BadArgument`][SixelError::BadArgument] if the animation plays forever,
+    /// [`Io`][SixelError::Io] if the writer fails,
+    /// or any of the errors of [`build_chunks`][Self::build_chunks].
+    pub fn build_to<W: IoWrite>(&self, mut writer: W) -> SixelResult<()> {
+        let plays = self.plays().ok_or(SixelError::BadArgument)?;
+        let chunks = self.build_chunks()?;
+        for _ in 0..plays {
+            for chunk in &chunks {
+                writer.write_all(&chunk.bytes).map_err(|e| SixelError::Io(e.kind()))?;
+            }
+        }
+        writer.flush().map_err(|e| SixelError::Io(e.kind()))
+    }
+
+    /// Returns the sequences to write before and after each frame.
+    fn rewind_sequences(&self) -> (Vec<u8>, &'static [u8]) {
+        match self.rewind {
+            Rewind::SaveRestore => (b"\x1B7".to_vec(), b"\x1B8"),
+            Rewind::Position(row, col) => {
+                let mut prefix = if self.sixel.control_8bit {
+                    vec![0x9B]
+                } else {
+                    b"\x1B[".to_vec()
+                };
+                prefix.extend_from_slice(format!("{row};{col}H").as_bytes());
+                (prefix, b"")
+            }
+        }
+    }
+}
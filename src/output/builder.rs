@@ -2,10 +2,16 @@
 //
 
 use crate::{
-    Diffusion, DitherConf, EncodePolicy, LargestDim, PixelFormat, Quality, RepColor, SixelError,
-    SixelOutput, SixelResult,
+    resample::resample_pixels, AlphaMode, BackgroundSelect, ColorSpace, Diffusion, DitherConf,
+    DitherMode, EncodePolicy, LargestDim, PixelFormat, Quality, Quantizer, RepColor,
+    ResampleMethod, SixelError, SixelOutput, SixelResult,
+};
+use alloc::borrow::Cow;
+use core::fmt;
+use devela::{
+    sys::{IoError, IoErrorKind, IoResult, Write as IoWrite},
+    ConstDefault, String, Vec,
 };
-use devela::{ConstDefault, String, ToString, Vec};
 
 /// A configurable sixel string builder from a slice of pixel data bytes.
 ///
@@ -14,13 +20,16 @@ use devela::{ConstDefault, String, ToString, Vec};
 ///
 /// # Example
 /// ```
+/// # use devela::ConstDefault;
 /// # use sixela::Sixel;
 /// // 2x2 pixels (Red, Green, Blue, White)
 /// const IMAGE_HEX: &[u8] = b"FF000000FF000000FFFFFFFF";
 /// //                         RRGGBBrrggbbRRGGBBrrggbb
 /// println!("{}", Sixel::with_bytes_size(IMAGE_HEX, 2, 2).build().unwrap());
+///
+/// assert_eq![Sixel::default(), Sixel::DEFAULT];
 /// ```
-#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub struct Sixel<'a> {
     pub bytes: Option<&'a [u8]>,
     pub width: i32,
@@ -30,6 +39,25 @@ pub struct Sixel<'a> {
     pub largest: LargestDim,
     pub rep: RepColor,
     pub quality: Quality,
+    pub control_8bit: bool,
+    pub alpha: AlphaMode,
+    pub aspect_ratio: (u16, u16),
+    pub background: BackgroundSelect,
+    pub grid_size: u16,
+    pub raster_attributes: bool,
+    pub palette_preset: Option<DitherMode>,
+    pub palette: Option<&'a [[u8; 3]]>,
+    pub resize: Option<(i32, i32)>,
+    pub fit_within: Option<(i32, i32)>,
+    pub resample: ResampleMethod,
+    pub quantizer: Quantizer,
+    pub refine_palette: u32,
+    pub color_space: ColorSpace,
+    pub linear_diffusion: bool,
+    pub serpentine: bool,
+    pub diffusion_strength: u8,
+    pub colors: u16,
+    pub penetrate: bool,
 }
 impl<'a> ConstDefault for Sixel<'a> {
     const DEFAULT: Self = Self {
@@ -41,8 +69,32 @@ impl<'a> ConstDefault for Sixel<'a> {
         largest: LargestDim::DEFAULT,
         rep: RepColor::DEFAULT,
         quality: Quality::DEFAULT,
+        control_8bit: false,
+        alpha: AlphaMode::DEFAULT,
+        aspect_ratio: (1, 1),
+        background: BackgroundSelect::DEFAULT,
+        grid_size: 0,
+        raster_attributes: true,
+        palette_preset: None,
+        palette: None,
+        resize: None,
+        fit_within: None,
+        resample: ResampleMethod::DEFAULT,
+        quantizer: Quantizer::DEFAULT,
+        refine_palette: 0,
+        color_space: ColorSpace::DEFAULT,
+        linear_diffusion: false,
+        serpentine: false,
+        diffusion_strength: 100,
+        colors: 256,
+        penetrate: false,
     };
 }
+impl Default for Sixel<'_> {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
 
 /// # Common methods
 #[rustfmt::skip]
@@ -77,22 +129,101 @@ impl<'a> Sixel<'a> {
     /// Returns an error if the bytes slice have not been set,
     /// if either the width or height is 0,
     /// or the slice is not long enough.
+    ///
+    /// Returns [`BadArgument`][SixelError::BadArgument] if 8-bit controls are enabled,
+    /// since they are not valid UTF-8. Use [`build_bytes`][Self::build_bytes] instead.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let black = [0; 3 * 2 * 12];
+    /// // a single band of sixels, without a graphics new line (`-`)
+    /// let sixel = Sixel::with_bytes_size(&black, 2, 3).build().unwrap();
+    /// assert_eq![sixel, "\x1bPq\"1;1;2;3#0;2;0;0;0#0FF\x1b\\"];
+    /// // two bands of sixels, separated by one graphics new line
+    /// let sixel = Sixel::with_bytes_size(&black, 2, 12).build().unwrap();
+    /// assert_eq![sixel, "\x1bPq\"1;1;2;12#0;2;0;0;0#0~~-~~\x1b\\"];
+    /// ```
     pub fn build(self) -> SixelResult<String> {
-        if self.width == 0 || self.height == 0 {
+        if self.control_8bit {
+            return Err(SixelError::BadArgument);
+        }
+        String::from_utf8(self.build_bytes()?).map_err(|_| SixelError::BadInput)
+    }
+
+    /// Builds a sixel formatted byte vector with the configured options.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes slice have not been set,
+    /// if either the width or height is 0,
+    /// or the slice is not long enough.
+    pub fn build_bytes(self) -> SixelResult<Vec<u8>> {
+        let mut sixel = Vec::new();
+        self.build_to(&mut sixel)?;
+        Ok(sixel)
+    }
+
+    /// Builds the sixel data with the configured options, writing it into `writer`.
+    ///
+    /// The data is written in packets while encoding, without buffering the whole image.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes slice have not been set,
+    /// if either the width or height is 0,
+    /// if the slice is not long enough,
+    /// or [`Io`][SixelError::Io] if the writer fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(8);
+    /// let mut sixel = Vec::new();
+    /// Sixel::with_bytes_size(&rgb, 4, 4).build_to(&mut sixel).unwrap();
+    /// assert![sixel.starts_with(b"\x1bPq") && sixel.ends_with(b"\x1b\\")];
+    ///
+    /// // the 8-bit controls are written as single raw bytes
+    /// let mut sixel = Vec::new();
+    /// Sixel::with_bytes_size(&rgb, 4, 4).control_8bit(true).build_to(&mut sixel).unwrap();
+    /// assert![sixel.starts_with(b"\x90q") && sixel.ends_with(b"\x9C")];
+    /// assert![!sixel.contains(&0x1B) && !sixel.contains(&0xC2)];
+    /// ```
+    pub fn build_to<W: IoWrite>(self, writer: W) -> SixelResult<()> {
+        if self.width <= 0 || self.height <= 0 {
             return Err(SixelError::BadInput);
         }
         if let Some(bytes) = self.bytes {
-            if bytes.len() < self.format.required_bytes(self.width, self.height) {
-                Err(SixelError::BadInput)
-            } else {
-                sixel_string(bytes, self.width, self.height,
-                    self.format, self.diffuse, self.largest, self.rep, self.quality)
-            }
+            self.encode_to(bytes, writer)
         } else {
             Err(SixelError::BadInput)
         }
     }
 
+    /// Builds the sixel data with the configured options, writing it into a `fmt::Write`r.
+    ///
+    /// # Errors
+    /// Returns an error if the bytes slice have not been set,
+    /// if either the width or height is 0,
+    /// if the slice is not long enough,
+    /// or [`Io`][SixelError::Io] if the writer fails.
+    ///
+    /// Returns [`BadArgument`][SixelError::BadArgument] if 8-bit controls are enabled,
+    /// since they are not valid UTF-8.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(8);
+    /// let mut sixel = String::from("image: ");
+    /// Sixel::with_bytes_size(&rgb, 4, 4).build_fmt(&mut sixel).unwrap();
+    /// assert![sixel.starts_with("image: \x1bPq")];
+    /// ```
+    pub fn build_fmt<W: fmt::Write>(self, writer: &mut W) -> SixelResult<()> {
+        if self.control_8bit {
+            return Err(SixelError::BadArgument);
+        }
+        self.build_to(FmtWriter(writer))
+    }
+
     /* */
 
     /// Sets the byte slice of image data.
@@ -118,6 +249,75 @@ impl<'a> Sixel<'a> {
         self
     }
 
+    /// Resizes the image to the given size before encoding, using the given method.
+    ///
+    /// It replaces any previous [`fit_within`][Self::fit_within] size.
+    ///
+    /// The indexed pixel formats are always resized with
+    /// the [`Nearest`][ResampleMethod::Nearest] method.
+    /// The grayscale pixel formats are resized as 8-bit gray levels,
+    /// which keep their palette or palette preset.
+    ///
+    /// Building returns [`BadArgument`][SixelError::BadArgument]
+    /// if the new size has more than 2<sup>26</sup> pixels.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherMode, ResampleMethod, Sixel, SixelError};
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(8);
+    /// let sixel = Sixel::with_bytes_size(&rgb, 4, 4)
+    ///     .resize(8, 2, ResampleMethod::Lanczos3).build().unwrap();
+    /// assert![sixel.starts_with("\x1bPq\"1;1;8;2#")];
+    ///
+    /// let huge = Sixel::with_bytes_size(&rgb, 4, 4).resize(i32::MAX, i32::MAX, ResampleMethod::Nearest);
+    /// assert_eq![huge.build(), Err(SixelError::BadArgument)];
+    ///
+    /// // 4-bit gray levels 5 and 9, mapped onto the gray of the xterm colors
+    /// let gray = Sixel::with_bytes_size(&[0x59], 2, 1).format_g4().palette_preset(DitherMode::XTerm16);
+    /// assert![gray.clone().build().unwrap().ends_with("#8@@\x1b\\")];
+    /// let sixel = gray.resize(2, 2, ResampleMethod::Nearest).build().unwrap();
+    /// assert![sixel.ends_with("#8BB\x1b\\")];
+    ///
+    /// // and onto their own grayscale palette by default
+    /// let gray = Sixel::with_bytes_size(&[0x59], 2, 1).format_g4();
+    /// let sixel = gray.resize(2, 2, ResampleMethod::Nearest).build().unwrap();
+    /// assert![sixel.contains("#15;2;100;100;100#5B#9B\x1b\\")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn resize(mut self, width: i32, height: i32, method: ResampleMethod) -> Self {
+        self.resize = Some((width, height));
+        self.fit_within = None;
+        self.resample = method;
+        self
+    }
+    /// Resizes the image to the largest size that fits within the given size,
+    /// preserving its aspect ratio.
+    ///
+    /// It replaces any previous [`resize`][Self::resize] size,
+    /// and uses the [`resample`][Self::resample] method.
+    /// Like it, building fails if the new size has more than 2<sup>26</sup> pixels.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(100);
+    /// let sixel = Sixel::with_bytes_size(&rgb, 20, 10).fit_within(8, 8).build().unwrap();
+    /// assert![sixel.starts_with("\x1bPq\"1;1;8;4#")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn fit_within(mut self, width: i32, height: i32) -> Self {
+        self.fit_within = Some((width, height));
+        self.resize = None;
+        self
+    }
+    /// Sets the method of resampling used when resizing the image.
+    ///
+    /// Defaults to [`Bilinear`][ResampleMethod::Bilinear].
+    #[inline] #[must_use]
+    pub const fn resample(mut self, method: ResampleMethod) -> Self {
+        self.resample = method; self
+    }
+
     /**/
 
     /// Sets the pixel format.
@@ -126,6 +326,30 @@ impl<'a> Sixel<'a> {
         self.format = format; self
     }
     /// Sets the method for diffusion.
+    ///
+    /// It's ignored when the palette is computed from an image with fewer colors,
+    /// since every sampled color is then in the palette.
+    ///
+    /// The ordered dithers, like [`Bayer4`][Diffusion::Bayer4] and
+    /// [`BlueNoise`][Diffusion::BlueNoise], only depend on the position of each pixel,
+    /// so the unchanged areas of consecutive frames don't shimmer.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Diffusion, Sixel, SixelImage};
+    /// let top_rows = |frame: &[u8]| {
+    ///     let sixel = Sixel::with_bytes_size(frame, 16, 12)
+    ///         .palette(&[[0, 0, 0], [255, 255, 255]])
+    ///         .diffuse(Diffusion::Bayer4)
+    ///         .build_bytes()
+    ///         .unwrap();
+    ///     SixelImage::decode(&sixel).unwrap().to_rgb888([0, 0, 0])[..3 * 16 * 6].to_vec()
+    /// };
+    /// let mut frame = [100; 3 * 16 * 12];
+    /// let first = top_rows(&frame);
+    /// frame[3 * 16 * 6..].fill(200);
+    /// assert_eq![first, top_rows(&frame)];
+    /// ```
     #[inline] #[must_use]
     pub const fn diffuse(mut self, diffuse: Diffusion) -> Self {
         self.diffuse = diffuse; self
@@ -141,10 +365,357 @@ impl<'a> Sixel<'a> {
         self.rep = rep; self
     }
     /// Sets the quality.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Quality, Sixel, SixelImage};
+    /// let rgb: Vec<u8> = (0..16 * 12).flat_map(|i| [i as u8, 255 - i as u8, 128]).collect();
+    /// let sixel = Sixel::with_bytes_size(&rgb, 16, 12).quality(Quality::HighColor).build().unwrap();
+    /// let decoded = SixelImage::decode(sixel.as_bytes()).unwrap().to_rgb888([0; 3]);
+    /// // with 5 bits per component, and a small dither
+    /// assert![decoded.iter().zip(&rgb).all(|(a, b)| a.abs_diff(*b) <= 16)];
+    /// ```
     #[inline] #[must_use]
     pub const fn quality(mut self, quality: Quality) -> Self {
         self.quality = quality; self
     }
+    /// Sets the maximum number of colors of the computed palette, from 1 to 256.
+    /// Defaults to 256.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Diffusion, Sixel, SixelImage};
+    /// let rgb: Vec<u8> = (0..=255).flat_map(|i| [i, 255 - i, i / 2]).collect();
+    /// let sixel = Sixel::with_bytes_size(&rgb, 16, 16).colors(8).build().unwrap();
+    /// assert![SixelImage::decode(sixel.as_bytes()).unwrap().palette.len() <= 8];
+    ///
+    /// assert![Sixel::with_bytes_size(&rgb, 16, 16).colors(0).build().is_err()];
+    ///
+    /// // an image that uses all the 256 colors, with error diffusion and a positional mask
+    /// let rgb: Vec<u8> = (0..64 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 96]).collect();
+    /// for diffuse in [Diffusion::FS, Diffusion::ADither] {
+    ///     let sixel = Sixel::with_bytes_size(&rgb, 64, 64).diffuse(diffuse).build().unwrap();
+    ///     assert_eq![SixelImage::decode(sixel.as_bytes()).unwrap().palette.len(), 256];
+    /// }
+    /// ```
+    #[inline] #[must_use]
+    pub const fn colors(mut self, colors: u16) -> Self {
+        self.colors = colors; self
+    }
+    /// Sets the method of color quantization.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Quantizer, Sixel};
+    /// let rgb: Vec<u8> = (0..=255).flat_map(|i| [i, 255 - i, i / 2]).collect();
+    /// let sixel = Sixel::with_bytes_size(&rgb, 16, 16).quantizer(Quantizer::Octree).build().unwrap();
+    /// assert![sixel.starts_with("\x1bPq\"1;1;16;16#")];
+    ///
+    /// // Wu's quantizer keeps the smooth gradients closer to the original
+    /// let rgb: Vec<u8> = (0..64 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 96]).collect();
+    /// let mse = |q| Sixel::with_bytes_size(&rgb, 64, 64).quantizer(q).refine_palette(1).palette_mse();
+    /// let (median_cut, _) = mse(Quantizer::MedianCut).unwrap().unwrap();
+    /// let (wu, _) = mse(Quantizer::Wu).unwrap().unwrap();
+    /// assert![wu < median_cut];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn quantizer(mut self, quantizer: Quantizer) -> Self {
+        self.quantizer = quantizer; self
+    }
+    /// Sets the maximum number of k-means iterations that refine the computed palette,
+    /// or 0 to not refine it. Defaults to 0.
+    ///
+    /// The refinement improves the palette chosen by the quantizer,
+    /// at the cost of some speed. See [`palette_mse`][Self::palette_mse].
+    #[inline] #[must_use]
+    pub const fn refine_palette(mut self, iterations: u32) -> Self {
+        self.refine_palette = iterations; self
+    }
+    /// Sets the color space for measuring the distances between colors,
+    /// when splitting the median cut boxes and looking up the nearest palette color.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{ColorSpace, Sixel};
+    /// let rgb: Vec<u8> = (0..=255).flat_map(|i| [i, 255 - i, i / 2]).collect();
+    /// let sixel = Sixel::with_bytes_size(&rgb, 16, 16).color_space(ColorSpace::CieLab).build().unwrap();
+    /// assert![sixel.starts_with("\x1bPq\"1;1;16;16#")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn color_space(mut self, color_space: ColorSpace) -> Self {
+        self.color_space = color_space; self
+    }
+    /// Sets whether to diffuse the error in linear light, instead of in gamma encoded sRGB.
+    ///
+    /// It keeps the brightness of the dithered gradients, for both the palette and the
+    /// [`HighColor`][Quality::HighColor] encoding.
+    /// See [`DitherConf::set_linear_diffusion`].
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Diffusion, Sixel, SixelImage};
+    /// // the sRGB gray 128 is about 22% as bright as white
+    /// let gray = [128; 3 * 64 * 64];
+    /// let white_ratio = |linear| {
+    ///     let sixel = Sixel::with_bytes_size(&gray, 64, 64)
+    ///         .palette(&[[0, 0, 0], [255, 255, 255]])
+    ///         .diffuse(Diffusion::FS)
+    ///         .linear_diffusion(linear)
+    ///         .build_bytes()
+    ///         .unwrap();
+    ///     let rgb = SixelImage::decode(&sixel).unwrap().to_rgb888([0, 0, 0]);
+    ///     rgb.iter().filter(|&&c| c == 255).count() as f64 / rgb.len() as f64
+    /// };
+    /// assert![(white_ratio(false) - 0.50).abs() < 0.03];
+    /// assert![(white_ratio(true) - 0.22).abs() < 0.03];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn linear_diffusion(mut self, linear: bool) -> Self {
+        self.linear_diffusion = linear; self
+    }
+    /// Sets whether to scan in serpentine, alternating the direction of each row.
+    ///
+    /// See [`DitherConf::set_serpentine`].
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Diffusion, Sixel};
+    /// let gray = [100; 3 * 64 * 64];
+    /// let sixel = Sixel::with_bytes_size(&gray, 64, 64)
+    ///     .palette(&[[0, 0, 0], [255, 255, 255]])
+    ///     .diffuse(Diffusion::Sierra3)
+    ///     .serpentine(true)
+    ///     .build()
+    ///     .unwrap();
+    /// assert![sixel.starts_with("\x1bPq\"1;1;64;64#")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn serpentine(mut self, serpentine: bool) -> Self {
+        self.serpentine = serpentine; self
+    }
+    /// Sets the percentage of the error to diffuse, from 0 to 100.
+    ///
+    /// It only applies to the error diffusion methods of the palette encoding,
+    /// so it's ignored by the ordered dithers and by [`HighColor`][Quality::HighColor].
+    ///
+    /// See [`DitherConf::set_diffusion_strength`].
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Diffusion, Sixel};
+    /// let pixels: Vec<u8> = (0..=255).flat_map(|c| [c; 3]).collect();
+    /// let sixel = |strength| {
+    ///     Sixel::with_bytes_size(&pixels, 16, 16)
+    ///         .palette(&[[0, 0, 0], [128, 128, 128], [255, 255, 255]])
+    ///         .diffuse(Diffusion::FS)
+    ///         .diffusion_strength(strength)
+    ///         .build()
+    ///         .unwrap()
+    /// };
+    /// let undiffused = Sixel::with_bytes_size(&pixels, 16, 16)
+    ///     .palette(&[[0, 0, 0], [128, 128, 128], [255, 255, 255]])
+    ///     .diffuse(Diffusion::None)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq![sixel(0), undiffused];
+    /// assert_ne![sixel(50), sixel(100)];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn diffusion_strength(mut self, percent: u8) -> Self {
+        self.diffusion_strength = percent; self
+    }
+    /// Sets a predefined fixed palette.
+    ///
+    /// The pixels are mapped directly onto the palette of the given mode,
+    /// without computing a palette from the image,
+    /// and the [`quality`][Self::quality], [`largest`][Self::largest]
+    /// and [`rep`][Self::rep] options are ignored.
+    ///
+    /// The `G*` pixel formats use the grayscale mode of their levels by default.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherMode, Sixel};
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(8);
+    /// let sixel = Sixel::with_bytes_size(&rgb, 4, 4)
+    ///     .palette_preset(DitherMode::VT340Color).build().unwrap();
+    /// assert![sixel.contains("#0;2;20;20;80#1;2;80;14;14#2;2;20;80;20")];
+    ///
+    /// // The monochrome modes draw with the terminal foreground color.
+    /// let sixel = Sixel::with_bytes_size(&rgb, 4, 4)
+    ///     .palette_preset(DitherMode::MonoDark).diffuse_none().build().unwrap();
+    /// assert![!sixel.contains('#')];
+    ///
+    /// // 8-bit gray levels, mapped onto the black and white of the xterm colors
+    /// let sixel = Sixel::with_bytes_size(&[0, 255], 2, 1)
+    ///     .format_g8().palette_preset(DitherMode::XTerm16).build().unwrap();
+    /// assert![sixel.ends_with("#0@#15@\x1b\\")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn palette_preset(mut self, mode: DitherMode) -> Self {
+        self.palette_preset = Some(mode); self
+    }
+    /// Sets a fixed palette of up to 256 colors.
+    ///
+    /// The pixels are mapped and diffused directly onto the given palette,
+    /// and it overrides any [`palette_preset`][Self::palette_preset].
+    ///
+    /// For the `PAL*` pixel formats it's the table of the pixel indices,
+    /// which are encoded as they are, without computing a new palette.
+    /// These formats require either a palette or a palette preset,
+    /// and building fails with [`BadInput`][SixelError::BadInput]
+    /// if any index is beyond it.
+    /// The levels of the `G*` formats are mapped onto it as gray colors.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Sixel, SixelError};
+    /// let palette = [[255, 0, 0], [0, 0, 255]];
+    /// let rgb = [250, 10, 10, 10, 10, 250].repeat(8);
+    /// let sixel = Sixel::with_bytes_size(&rgb, 4, 4).palette(&palette).build().unwrap();
+    /// assert![sixel.contains("#0;2;100;0;0#1;2;0;0;100")];
+    ///
+    /// // 4x1 indexed pixels of 2 bits: 0, 1, 2, 3
+    /// let palette = [[0, 0, 0], [255, 0, 0], [0, 255, 0], [0, 0, 255]];
+    /// let sixel = Sixel::with_bytes_size(&[0b00_01_10_11], 4, 1)
+    ///     .format_pal2().palette(&palette).build().unwrap();
+    /// assert_eq![sixel, "\x1bPq\"1;1;4;1\
+    ///     #0;2;0;0;0#1;2;100;0;0#2;2;0;100;0#3;2;0;0;100\
+    ///     #0@#1@#2@#3@\x1b\\"];
+    ///
+    /// // 8-bit gray levels, mapped onto the nearest palette colors
+    /// let palette = [[0, 0, 0], [128, 128, 128], [255, 255, 255]];
+    /// let sixel = Sixel::with_bytes_size(&[250, 120, 10], 3, 1)
+    ///     .format_g8().palette(&palette).build().unwrap();
+    /// assert![sixel.ends_with("#0??@$#1?@$#2@\x1b\\")];
+    ///
+    /// // 8-bit indices beyond the palette
+    /// let sixel = Sixel::with_bytes_size(&[0, 5, 1, 9], 4, 1)
+    ///     .format_pal8().palette(&[[0, 0, 0], [255, 255, 255]]).build();
+    /// assert_eq![sixel, Err(SixelError::BadInput)];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn palette(mut self, palette: &'a [[u8; 3]]) -> Self {
+        self.palette = Some(palette); self
+    }
+    /// Sets the handling of the alpha channel.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{AlphaMode, Sixel, SixelImage};
+    /// // 6x6 RGBA pixels: an opaque red square with a transparent hole in the middle.
+    /// let rgba: Vec<u8> = (0..36)
+    ///     .flat_map(|i| if matches!(i, 14 | 15 | 20 | 21) { [0, 0, 0, 0] } else { [255, 0, 0, 255] })
+    ///     .collect();
+    /// let sixel = Sixel::with_bytes_size(&rgba, 6, 6)
+    ///     .format_rgba8888()
+    ///     .alpha(AlphaMode::Threshold(128))
+    ///     .build_bytes()
+    ///     .unwrap();
+    /// assert![sixel.starts_with(b"\x1bP0;1q")];
+    ///
+    /// let image = SixelImage::decode(&sixel).unwrap();
+    /// let pixels = image.to_rgba8888();
+    /// assert_eq![pixels[14 * 4 + 3], 0]; // transparent
+    /// assert_eq![pixels[13 * 4 + 3], 255]; // opaque
+    /// ```
+    #[inline] #[must_use]
+    pub const fn alpha(mut self, alpha: AlphaMode) -> Self {
+        self.alpha = alpha; self
+    }
+    /// Sets the pixel aspect ratio, as a `pan` vertical by `pad` horizontal proportion.
+    ///
+    /// It's output in the raster attributes, or as the closest P1 parameter when they
+    /// are omitted, which supports the ratios 1:1, 2:1, 3:1 and 5:1. Defaults to 1:1.
+    ///
+    /// Zero values are treated as 1.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let black = [0, 0, 0];
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1).aspect_ratio(2, 1).build().unwrap();
+    /// assert![sixel.starts_with("\x1bPq\"2;1;1;1#")];
+    ///
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1)
+    ///     .aspect_ratio(2, 1).raster_attributes(false).build().unwrap();
+    /// assert![sixel.starts_with("\x1bPq#")];
+    ///
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1).raster_attributes(false).build().unwrap();
+    /// assert![sixel.starts_with("\x1bP9q#")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn aspect_ratio(mut self, pan: u16, pad: u16) -> Self {
+        self.aspect_ratio = (if pan == 0 { 1 } else { pan }, if pad == 0 { 1 } else { pad });
+        self
+    }
+    /// Sets the background selection (P2).
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{BackgroundSelect, Sixel};
+    /// let black = [0, 0, 0];
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1)
+    ///     .background(BackgroundSelect::Transparent).grid_size(50).build().unwrap();
+    /// assert![sixel.starts_with("\x1bP0;1;50q\"1;1;1;1#")];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn background(mut self, background: BackgroundSelect) -> Self {
+        self.background = background; self
+    }
+    /// Sets the horizontal grid size (P3), in decipoints, or 0 for the default.
+    #[inline] #[must_use]
+    pub const fn grid_size(mut self, grid_size: u16) -> Self {
+        self.grid_size = grid_size; self
+    }
+    /// Sets whether to output the raster attributes.
+    ///
+    /// `true` outputs the aspect ratio and the size of the image (default),
+    /// `false` omits them, for terminals that don't support them.
+    #[inline] #[must_use]
+    pub const fn raster_attributes(mut self, raster_attributes: bool) -> Self {
+        self.raster_attributes = raster_attributes; self
+    }
+    /// Sets whether to use 8-bit C1 control characters.
+    ///
+    /// `true` delimits the sixel data with the single bytes DCS (`0x90`) and ST (`0x9C`),
+    /// `false` uses the 7-bit `ESC P` and `ESC \` sequences (default).
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let black = [0, 0, 0];
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1).control_8bit(true).build_bytes().unwrap();
+    /// assert_eq![sixel, b"\x90q\"1;1;1;1#0;2;0;0;0#0@\x9C"];
+    ///
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1).build_bytes().unwrap();
+    /// assert_eq![sixel, b"\x1BPq\"1;1;1;1#0;2;0;0;0#0@\x1B\\"];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn control_8bit(mut self, control_8bit: bool) -> Self {
+        self.control_8bit = control_8bit; self
+    }
+    /// Sets whether to split the sixel data into DCS packets that penetrate GNU Screen.
+    ///
+    /// Defaults to `false`.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let black = [0, 0, 0];
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1).penetrate(true).build_bytes().unwrap();
+    /// assert_eq![sixel, b"\x1bP\x1bPq\"1;1;1;1#0;2;0;0;0#0@\x1b\\\x1b\\"];
+    ///
+    /// // the packets for the multiplexer keep the 7-bit controls,
+    /// // and only wrap the raw 8-bit DCS for the terminal
+    /// let sixel = Sixel::with_bytes_size(&black, 1, 1)
+    ///     .control_8bit(true).penetrate(true).build_bytes().unwrap();
+    /// assert_eq![sixel, b"\x1bP\x90q\"1;1;1;1#0;2;0;0;0#0@\x1b\\\x1b\\"];
+    /// ```
+    #[inline] #[must_use]
+    pub const fn penetrate(mut self, penetrate: bool) -> Self {
+        self.penetrate = penetrate; self
+    }
 }
 
 macro_rules! add_method {
@@ -193,6 +764,10 @@ impl<'a> Sixel<'a> {
     add_method![rep_average, rep, RepColor::AverageColors];
     add_method![rep_pixels, rep, RepColor::AveragePixels];
     //
+    add_method![quantizer_median_cut, quantizer, Quantizer::MedianCut];
+    add_method![quantizer_octree, quantizer, Quantizer::Octree];
+    add_method![quantizer_wu, quantizer, Quantizer::Wu];
+    //
     add_method![diffuse_auto, diffuse, Diffusion::Auto];
     add_method![diffuse_none, diffuse, Diffusion::None];
     add_method![diffuse_atkinson, diffuse, Diffusion::Atkinson];
@@ -202,65 +777,197 @@ impl<'a> Sixel<'a> {
     add_method![diffuse_burkes, diffuse, Diffusion::Burkes];
     add_method![diffuse_adither, diffuse, Diffusion::ADither];
     add_method![diffuse_xdither, diffuse, Diffusion::XDither];
+    add_method![diffuse_bayer2, diffuse, Diffusion::Bayer2];
+    add_method![diffuse_bayer4, diffuse, Diffusion::Bayer4];
+    add_method![diffuse_bayer8, diffuse, Diffusion::Bayer8];
+    add_method![diffuse_blue_noise, diffuse, Diffusion::BlueNoise];
+    add_method![diffuse_sierra3, diffuse, Diffusion::Sierra3];
+    add_method![diffuse_sierra2, diffuse, Diffusion::Sierra2];
+    add_method![diffuse_sierra_lite, diffuse, Diffusion::SierraLite];
+    add_method![diffuse_stevenson_arce, diffuse, Diffusion::StevensonArce];
     //
     add_method![quality_auto, quality, Quality::Auto];
     add_method![quality_high, quality, Quality::High];
     add_method![quality_low, quality, Quality::Low];
     add_method![quality_full, quality, Quality::Full];
     add_method![quality_high_color, quality, Quality::HighColor];
+    //
+    add_method![background_auto, background, BackgroundSelect::Auto];
+    add_method![background_device, background, BackgroundSelect::Device];
+    add_method![background_transparent, background, BackgroundSelect::Transparent];
+    add_method![background_opaque, background, BackgroundSelect::Opaque];
 }
 
-/// Writes a string of sixel data.
-///
-/// # Example
-/// ```ignore
-/// # use sixela::*;
-/// // 2x2 pixels (Red, Green, Blue, White)
-/// const IMAGE_HEX: &[u8] = b"FF000000FF000000FFFFFFFF";
-///                          //RRGGBBrrggbbRRGGBBrrggbb
-///
-/// println!("{}", sixel_string(
-///     IMAGE_HEX, 2, 2,
-///     PixelFormat::RGB888,
-///     Diffusion::Stucki,
-///     LargestDim::Auto,
-///     RepColor::Auto,
-///     Quality::Auto
-/// ).unwrap());
-/// ```
-#[expect(clippy::too_many_arguments)]
-fn sixel_string(
-    bytes: &[u8],
-    width: i32,
-    height: i32,
-    pixelformat: PixelFormat,
-    method_for_diffuse: Diffusion,
-    method_for_largest: LargestDim,
-    method_for_rep: RepColor,
-    quality_mode: Quality,
-) -> SixelResult<String> {
-    let mut sixel_data: Vec<u8> = Vec::new(); // MAYBE with_capacity
+impl Sixel<'_> {
+    /// Computes the palette with the configured options, without encoding the image,
+    /// and returns its mean squared error per color component
+    /// before and after [refining it][Self::refine_palette].
+    ///
+    /// Returns `None` if the palette is not refined or not computed from the image.
+    ///
+    /// # Errors
+    /// Returns the same errors as [`build_to`][Self::build_to], besides the writing.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Sixel;
+    /// let rgb: Vec<u8> = (0..64 * 64).flat_map(|i| [(i % 64 * 4) as u8, (i / 64 * 4) as u8, 96]).collect();
+    /// let sixel = Sixel::with_bytes_size(&rgb, 64, 64).refine_palette(8);
+    ///
+    /// let (before, after) = sixel.palette_mse().unwrap().unwrap();
+    /// assert![after < before];
+    /// assert_eq![Sixel::with_bytes_size(&rgb, 64, 64).palette_mse(), Ok(None)];
+    /// ```
+    pub fn palette_mse(&self) -> SixelResult<Option<(f64, f64)>> {
+        if self.width <= 0 || self.height <= 0 {
+            return Err(SixelError::BadInput);
+        }
+        let bytes = self.bytes.ok_or(SixelError::BadInput)?;
+        let (bytes, format, width, height) = self.resized(bytes)?;
+        let dither_conf = self.new_dither_conf(&[&bytes], format, width, height)?;
+        Ok(dither_conf.get_palette_mse())
+    }
 
-    let mut sixel_output = SixelOutput::new(&mut sixel_data);
-    sixel_output.set_encode_policy(EncodePolicy::Auto);
-    let mut dither_conf = DitherConf::new(256).unwrap();
+    /// Encodes the given `bytes` with the configured options, writing them into `writer`.
+    fn encode_to<W: IoWrite>(&self, bytes: &[u8], writer: W) -> SixelResult<()> {
+        let (bytes, format, width, height) = self.resized(bytes)?;
+        let mut sixel_output = self.new_output(writer);
+        let mut dither_conf = self.new_dither_conf(&[&bytes], format, width, height)?;
+        sixel_output.encode(&bytes, width, height, &mut dither_conf)
+    }
+
+    /// Returns the `bytes` resized to the target size, with their pixel format and size.
+    pub(crate) fn resized<'b>(
+        &self,
+        bytes: &'b [u8],
+    ) -> SixelResult<(Cow<'b, [u8]>, PixelFormat, usize, usize)> {
+        let (width, height) = (self.width as usize, self.height as usize);
+        match self.target_size()? {
+            Some((new_width, new_height)) if (new_width, new_height) != (width, height) => {
+                let (resized, format) = resample_pixels(
+                    bytes,
+                    self.format,
+                    width,
+                    height,
+                    new_width,
+                    new_height,
+                    self.resample,
+                )?;
+                Ok((Cow::Owned(resized), format, new_width, new_height))
+            }
+            _ => Ok((Cow::Borrowed(bytes), self.format, width, height)),
+        }
+    }
+
+    /// Returns a new output context with the configured options.
+    pub(crate) fn new_output<W: IoWrite>(&self, writer: W) -> SixelOutput<W> {
+        let mut sixel_output = SixelOutput::new(writer);
+        sixel_output.set_encode_policy(EncodePolicy::Auto);
+        sixel_output.set_8bit_availability(self.control_8bit);
+        sixel_output.set_aspect_ratio(self.aspect_ratio.0, self.aspect_ratio.1);
+        sixel_output.set_background_select(self.background);
+        sixel_output.set_grid_size(self.grid_size);
+        sixel_output.set_raster_attributes(self.raster_attributes);
+        sixel_output.set_penetrate_multiplexer(self.penetrate);
+        sixel_output
+    }
+
+    /// Returns a new dither configuration with the configured options,
+    /// with its palette initialized from the given (resized) `frames`.
+    pub(crate) fn new_dither_conf(
+        &self,
+        frames: &[&[u8]],
+        format: PixelFormat,
+        width: usize,
+        height: usize,
+    ) -> SixelResult<DitherConf> {
+        let preset = self.fixed_preset();
+        let mut dither_conf = if let Some(palette) = self.palette {
+            let mut dither_conf = DitherConf::new(palette.len())?;
+            dither_conf.set_palette(palette)?;
+            dither_conf
+        } else if let Some(mode) = preset {
+            DitherConf::new_mode(mode)
+        } else if matches!(
+            format,
+            PixelFormat::PAL1 | PixelFormat::PAL2 | PixelFormat::PAL4 | PixelFormat::PAL8
+        ) {
+            return Err(SixelError::BadArgument);
+        } else if matches!(self.quality, Quality::HighColor) {
+            DitherConf::new_highcolor()
+        } else {
+            DitherConf::new(self.colors as usize)?
+        };
+        dither_conf.set_alpha_mode(self.alpha);
+        dither_conf.set_color_space(self.color_space);
+        let mut diffuse = self.diffuse;
+        if self.computes_palette() {
+            dither_conf.set_optimize_palette(true);
+            dither_conf.set_method_for_largest(self.largest);
+            dither_conf.set_method_for_rep(self.rep);
+            dither_conf.set_quantizer(self.quantizer);
+            dither_conf.set_refine_iterations(self.refine_palette);
+            dither_conf.set_quality_mode(self.quality);
+            dither_conf.initialize_frames(frames, width, height, format)?;
+            // like libsixel, don't diffuse if the palette has all the sampled colors
+            if dither_conf
+                .get_num_of_histogram_colors()
+                .is_some_and(|n| n <= self.colors as usize)
+            {
+                diffuse = Diffusion::None;
+            }
+        }
+        dither_conf.set_pixelformat(format);
+        dither_conf.set_diffusion_method(diffuse);
+        dither_conf.set_linear_diffusion(self.linear_diffusion);
+        dither_conf.set_serpentine(self.serpentine);
+        dither_conf.set_diffusion_strength(self.diffusion_strength);
+        Ok(dither_conf)
+    }
 
-    dither_conf.set_optimize_palette(true);
+    /// Returns the predefined fixed palette mode to use, if any.
+    fn fixed_preset(&self) -> Option<DitherMode> {
+        // the grayscale formats are indices into the matching grayscale palette, by default
+        self.palette_preset.or(DitherMode::gray(self.format))
+    }
+
+    /// Returns `true` if the palette is computed from the image.
+    pub(crate) fn computes_palette(&self) -> bool {
+        self.palette.is_none()
+            && self.fixed_preset().is_none()
+            && !matches!(self.quality, Quality::HighColor)
+    }
 
-    dither_conf.initialize(
-        bytes,
-        width,
-        height,
-        pixelformat,
-        method_for_largest,
-        method_for_rep,
-        quality_mode,
-    )?;
-    dither_conf.set_pixelformat(pixelformat);
-    dither_conf.set_diffusion_method(method_for_diffuse);
+    /// Returns the size to resize the image to, if any.
+    fn target_size(&self) -> SixelResult<Option<(usize, usize)>> {
+        let (width, height) = (self.width as u64, self.height as u64);
+        let size = match (self.resize, self.fit_within) {
+            (Some((w, h)), _) if w > 0 && h > 0 => (w as u64, h as u64),
+            (_, Some((w, h))) if w > 0 && h > 0 => {
+                let (w, h) = (w as u64, h as u64);
+                if width * h <= height * w {
+                    (((width * h + height / 2) / height).max(1), h)
+                } else {
+                    (w, ((height * w + width / 2) / width).max(1))
+                }
+            }
+            (None, None) => return Ok(None),
+            _ => return Err(SixelError::BadArgument),
+        };
+        Ok(Some((size.0 as usize, size.1 as usize)))
+    }
+}
 
-    let mut bytes = bytes.to_vec();
-    sixel_output.encode(&mut bytes, width, height, 0, &mut dither_conf)?;
+/// Adapts a [`fmt::Write`] into an [`IoWrite`], for valid UTF-8 data.
+struct FmtWriter<'a, W: fmt::Write>(&'a mut W);
 
-    Ok(String::from_utf8_lossy(&sixel_data).to_string())
+impl<W: fmt::Write> IoWrite for FmtWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let s = core::str::from_utf8(buf).map_err(|_| IoError::from(IoErrorKind::InvalidData))?;
+        self.0.write_str(s).map_err(|_| IoErrorKind::Other)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> IoResult<()> {
+        Ok(())
+    }
 }
@@ -10,18 +10,42 @@
 // - fn dither_func_burkes
 // - fn dither_func_a_dither
 // - fn dither_func_x_dither
+// - fn dither_func_ordered
+// - fn dither_func_kernel
 
-use crate::Diffusion;
+use crate::{
+    quant::{ordered_threshold, DiffusionKernel},
+    Diffusion,
+};
 
-/// TODO
+/// Dithers the pixel at index `px` before it's truncated to 15 bits per pixel.
+///
+/// The kernel is mirrored horizontally when scanning the row in `reverse`.
+#[expect(clippy::too_many_arguments)]
 pub(super) fn sixel_apply_15bpp_dither(
     pixels: &mut [u8],
+    px: usize,
     x: i32,
     y: i32,
     width: i32,
     height: i32,
     method_for_diffuse: Diffusion,
+    reverse: bool,
 ) {
+    let kernel_only = matches!(
+        method_for_diffuse,
+        Diffusion::Sierra3 | Diffusion::Sierra2 | Diffusion::SierraLite | Diffusion::StevensonArce
+    );
+    let table_kernel = if reverse || kernel_only {
+        DiffusionKernel::new(method_for_diffuse)
+    } else {
+        None
+    };
+    if let Some(kernel) = table_kernel {
+        dither_func_kernel(pixels, px, x, y, width, height, kernel, reverse);
+        return;
+    }
+    let pixels = &mut pixels[px..];
     match method_for_diffuse {
         Diffusion::None | Diffusion::Auto => {
             dither_func_none(pixels, width);
@@ -57,6 +81,14 @@ pub(super) fn sixel_apply_15bpp_dither(
         Diffusion::XDither => {
             dither_func_x_dither(pixels, width, x, y);
         }
+        Diffusion::Bayer2 | Diffusion::Bayer4 | Diffusion::Bayer8 | Diffusion::BlueNoise => {
+            dither_func_ordered(pixels, x, y, method_for_diffuse);
+        }
+        // diffused with their kernel
+        Diffusion::Sierra3
+        | Diffusion::Sierra2
+        | Diffusion::SierraLite
+        | Diffusion::StevensonArce => {}
     }
 }
 
@@ -406,3 +438,44 @@ fn dither_func_x_dither(data: &mut [u8], _width: i32, x: i32, y: i32) {
         data[c as usize] = value.clamp(0, 255) as u8;
     }
 }
+
+/// Ordered dithering with the threshold map of the `method`.
+///
+/// Adds from 0 to 7 to each component, before they are truncated to 5 bits.
+fn dither_func_ordered(data: &mut [u8], x: i32, y: i32, method: Diffusion) {
+    if let Some(threshold) = ordered_threshold(method, x, y) {
+        let offset = ((threshold + 1.0) * 4.0) as i32;
+        for value in &mut data[..3] {
+            *value = (*value as i32 + offset).min(255) as u8;
+        }
+    }
+}
+
+/// Dithering following the `kernel`, mirrored horizontally in `reverse`.
+///
+/// Diffuses the error of truncating each component of the pixel at index `px` to 5 bits,
+/// to its neighbor pixels inside the image.
+#[expect(clippy::too_many_arguments)]
+fn dither_func_kernel(
+    pixels: &mut [u8],
+    px: usize,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    kernel: DiffusionKernel,
+    reverse: bool,
+) {
+    let errors = [0, 1, 2].map(|c| pixels[px + c] as i32 & 0x7);
+    for &(dx, dy, share) in kernel.weights {
+        let dx = if reverse { -dx } else { dx };
+        if x + dx < 0 || x + dx >= width || y + dy >= height {
+            continue;
+        }
+        let neighbor = (px as i32 + (dy * width + dx) * 3) as usize;
+        for (c, error) in errors.iter().enumerate() {
+            let value = pixels[neighbor + c] as i32 + error * share / kernel.divisor;
+            pixels[neighbor + c] = value.min(0xff) as u8;
+        }
+    }
+}
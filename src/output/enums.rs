@@ -3,15 +3,20 @@
 // TOC
 // - enum LargestDim
 // - enum RepColor
+// - enum Quantizer
+// - enum ColorSpace
 // - enum Diffusion
 // - enum Quality
 // - enum PixelFormat
+// - enum AlphaMode
+// - enum BackgroundSelect
 // - enum EncodePolicy
 // - enum PaletteType
 // - enum Loop
+// - enum Rewind
+// - enum ResampleMethod
+// - enum Format
 // - //
-//   - enum ResampleMethod
-//   - enum Format
 //   - enum FormatType
 
 use devela::ConstDefault;
@@ -56,6 +61,46 @@ pub enum RepColor {
 #[rustfmt::skip]
 impl ConstDefault for RepColor { const DEFAULT: Self = Self::Auto; }
 
+/// Method of color quantization, for computing the palette of an image.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Quantizer {
+    /// Heckbert's median cut, configured with [`LargestDim`] and [`RepColor`]. (default)
+    #[default]
+    MedianCut,
+    /// Octree, merging the least populated branches of a tree of the color bits.
+    ///
+    /// It's usually faster, and handles images with many gradients better.
+    Octree,
+    /// Xiaolin Wu's variance minimization, splitting the boxes of colors
+    /// where it reduces their variance the most.
+    ///
+    /// It gives better palettes than median cut for smooth gradients, at a similar speed.
+    Wu,
+}
+#[rustfmt::skip]
+impl ConstDefault for Quantizer { const DEFAULT: Self = Self::MedianCut; }
+
+/// Color space for measuring the distances between colors,
+/// when splitting the median cut boxes and looking up the nearest palette color.
+///
+/// The perceptual spaces minimize the visible error, at the cost of some speed.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ColorSpace {
+    /// The gamma encoded sRGB components. (default)
+    #[default]
+    Rgb,
+    /// The linear light sRGB components.
+    LinearRgb,
+    /// Björn Ottosson's Oklab perceptual color space.
+    Oklab,
+    /// The CIE 1976 L\*a\*b\* perceptual color space, with a D65 white point.
+    CieLab,
+}
+#[rustfmt::skip]
+impl ConstDefault for ColorSpace { const DEFAULT: Self = Self::Rgb; }
+
 /// Method of diffusion.
 ///
 /// # Adaptation
@@ -82,6 +127,22 @@ pub enum Diffusion {
     ADither = 7,
     /// Positionally stable arithmetic xor based dither.
     XDither = 8,
+    /// Ordered dither with a 2×2 Bayer matrix.
+    Bayer2 = 9,
+    /// Ordered dither with a 4×4 Bayer matrix.
+    Bayer4 = 10,
+    /// Ordered dither with an 8×8 Bayer matrix.
+    Bayer8 = 11,
+    /// Ordered dither with a 32×32 blue noise threshold map.
+    BlueNoise = 12,
+    /// Diffuse with Frankie Sierra's three row method.
+    Sierra3 = 13,
+    /// Diffuse with Frankie Sierra's two row method.
+    Sierra2 = 14,
+    /// Diffuse with Frankie Sierra's lite method.
+    SierraLite = 15,
+    /// Diffuse with Stevenson and Arce's method.
+    StevensonArce = 16,
 }
 #[rustfmt::skip]
 impl ConstDefault for Diffusion { const DEFAULT: Self = Self::Auto; }
@@ -101,6 +162,17 @@ pub enum Quality {
     /// Low quality palette construction
     Low,
     /// Full quality palette construction
+    ///
+    /// Looks up the color of every pixel, without a cache table.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Quality, Sixel, SixelImage};
+    /// let rgb = [10, 10, 10, 250, 250, 250];
+    /// let sixel = Sixel::with_bytes_size(&rgb, 2, 1).quality(Quality::Full).build().unwrap();
+    /// let image = SixelImage::decode(sixel.as_bytes()).unwrap();
+    /// assert_eq![(image.width, image.height), (2, 1)];
+    /// ```
     Full,
     /// High color
     HighColor,
@@ -172,9 +244,9 @@ impl PixelFormat {
             | PixelFormat::AG88
             | PixelFormat::GA88 => 16,
             PixelFormat::RGB888
-            | PixelFormat::BGR888
-            | PixelFormat::G8
-            | PixelFormat::PAL8 => 24,
+            | PixelFormat::BGR888 => 24,
+            PixelFormat::G8
+            | PixelFormat::PAL8 => 8,
             PixelFormat::ARGB8888
             | PixelFormat::RGBA8888
             | PixelFormat::ABGR8888
@@ -187,10 +259,78 @@ impl PixelFormat {
 
     /// Returns the number of bytes required to store an image of the given dimensions,
     /// using the current pixel format.
-    pub const fn required_bytes(self, width: i32, height: i32) -> usize {
-        let total_bits = width as usize * height as usize * self.bpp();
-        // FIX: devela::bytes_from_bits(total_bits)
-        (total_bits + 7) / 8
+    ///
+    /// Formats of less than 8 bits per pixel have each row padded to a whole byte.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::PixelFormat;
+    /// assert_eq![PixelFormat::RGB888.required_bytes(3, 2), 18];
+    /// assert_eq![PixelFormat::G8.required_bytes(3, 2), 6];
+    /// assert_eq![PixelFormat::PAL8.required_bytes(3, 2), 6];
+    /// // 3 bits or 12 bits per row
+    /// assert_eq![PixelFormat::G1.required_bytes(3, 2), 2];
+    /// assert_eq![PixelFormat::PAL4.required_bytes(3, 2), 4];
+    /// ```
+    pub const fn required_bytes(self, width: usize, height: usize) -> usize {
+        let bpp = self.bpp();
+        if bpp < 8 {
+            (width * bpp).div_ceil(8) * height
+        } else {
+            width * height * bpp.div_ceil(8)
+        }
+    }
+}
+
+/// Handling of the alpha channel of the pixel formats that have one.
+///
+/// It applies to `ARGB8888`, `RGBA8888`, `ABGR8888`, `BGRA8888`, `AG88` and `GA88`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum AlphaMode {
+    /// Ignore the alpha channel, drawing every pixel as opaque. (default)
+    #[default]
+    Ignore,
+    /// Leave undrawn the pixels with an alpha value below the given threshold,
+    /// so that the terminal background shows through them.
+    ///
+    /// One palette color is reserved for the transparent pixels.
+    Threshold(u8),
+    /// Blend the pixels with the given background color.
+    Blend([u8; 3]),
+}
+#[rustfmt::skip]
+impl ConstDefault for AlphaMode { const DEFAULT: Self = Self::Ignore; }
+
+/// Background selection of the sixel image (P2 parameter of DECSIXEL).
+///
+/// Determines what happens to the pixel positions that are not drawn.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum BackgroundSelect {
+    /// Choose `Transparent` for images with a transparent color, and `Device` otherwise.
+    /// (default)
+    #[default]
+    Auto,
+    /// The undrawn pixels are set to the background color (P2 = 0).
+    Device,
+    /// The undrawn pixels remain at their current color (P2 = 1).
+    Transparent,
+    /// The undrawn pixels are set to the background color (P2 = 2).
+    Opaque,
+}
+#[rustfmt::skip]
+impl ConstDefault for BackgroundSelect { const DEFAULT: Self = Self::Auto; }
+
+impl BackgroundSelect {
+    /// Returns the value of the P2 parameter, for an image that may be `transparent`.
+    #[must_use]
+    pub const fn p2(self, transparent: bool) -> u8 {
+        match self {
+            BackgroundSelect::Auto => transparent as u8,
+            BackgroundSelect::Device => 0,
+            BackgroundSelect::Transparent => 1,
+            BackgroundSelect::Opaque => 2,
+        }
     }
 }
 
@@ -199,9 +339,8 @@ impl PixelFormat {
 /// # Adaptation
 /// Derived from `encodePolicy` enum in the `libsixel` C library.
 #[repr(u8)]
-#[allow(dead_code, reason = "Fast variant never constructed")]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
-pub(crate) enum EncodePolicy {
+pub enum EncodePolicy {
     /// Choose encoding policy automatically (default).
     #[default]
     Auto = 0,
@@ -218,9 +357,8 @@ impl ConstDefault for EncodePolicy { const DEFAULT: Self = Self::Auto; }
 /// # Adaptation
 /// Derived from `paletteType` enum in the `libsixel` C library.
 #[repr(u8)]
-#[allow(dead_code, reason = "Some variants are never constructed")]
 #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
-pub(crate) enum PaletteType {
+pub enum PaletteType {
     /// Choose palette type automatically.
     #[default]
     Auto,
@@ -232,15 +370,14 @@ pub(crate) enum PaletteType {
 #[rustfmt::skip]
 impl ConstDefault for PaletteType { const DEFAULT: Self = Self::Auto; }
 
-/// Loop mode.
+/// Loop mode of an animation.
 ///
 /// # Adaptation
 /// Derived from `loopControl` enum in the `libsixel` C library.
 #[repr(u8)]
-#[expect(dead_code, reason = "Only using `Auto` for now")]
 #[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
-enum Loop {
-    /// Honer the setting of GIF header.
+pub enum Loop {
+    /// Honor the loop count, like the setting of a GIF header. (default)
     #[default]
     Auto,
     /// Always enable loop.
@@ -251,53 +388,119 @@ enum Loop {
 #[rustfmt::skip]
 impl ConstDefault for Loop { const DEFAULT: Self = Self::Auto; }
 
-// /// Method of resampling.
-// ///
-// /// # Adaptation
-// /// Derived from `methodForResampling` enum in the `libsixel` C library.
-// #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
-// pub enum ResampleMethod { // TODO:MAYBE
-//     /// Use nearest neighbor method
-//     Nearest,
-//     /// Use guaussian filter
-//     Gaussian,
-//     /// Use hanning filter
-//     Hanning,
-//     /// Use hamming filter
-//     Hamming,
-//     /// Use bilinear filter
-//     Bilinear,
-//     /// Use welfilter
-//     Welsh,
-//     /// Use bicubic filter
-//     Bicubic,
-//     /// Use lanczos-2 filter
-//     Lanczos2,
-//     /// Use lanczos-3 filter
-//     Lanczos3,
-//     /// Use lanczos-4 filter
-//     Lanczos4,
-// }
+/// How the cursor returns to the origin of an animation before each frame,
+/// so that the frames overwrite each other.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum Rewind {
+    /// Save the cursor position before each frame with DECSC (`ESC 7`),
+    /// and restore it after the frame with DECRC (`ESC 8`). (default)
+    #[default]
+    SaveRestore,
+    /// Move the cursor to the given 1-based row and column with CUP (`CSI row ; col H`)
+    /// before each frame.
+    Position(u16, u16),
+}
+#[rustfmt::skip]
+impl ConstDefault for Rewind { const DEFAULT: Self = Self::SaveRestore; }
 
-// /// Image format
-// ///
-// /// # Adaptation
-// /// Derived from `imageFormat` enum in the `libsixel` C library.
-// #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
-// enum Format { // TODO:MAYBE
-//     GIF,   //         0x0 /* read only */
-//     PNG,   //         0x1 /* read/write */
-//     BMP,   //         0x2 /* read only */
-//     JPG,   //         0x3 /* read only */
-//     TGA,   //         0x4 /* read only */
-//     WBMP,  //         0x5 /* read only with --with-gd configure option */
-//     TIFF,  //         0x6 /* read only */
-//     SIXEL, //         0x7 /* read only */
-//     PNM,   //         0x8 /* read only */
-//     GD2,   //         0x9 /* read only with --with-gd configure option */
-//     PSD,   //         0xa /* read only */
-//     HDR,   //         0xb /* read only */
-// }
+/// Method of resampling.
+///
+/// # Adaptation
+/// Derived from `methodForResampling` enum in the `libsixel` C library.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ResampleMethod {
+    /// Use nearest neighbor method.
+    Nearest,
+    /// Use gaussian filter.
+    Gaussian,
+    /// Use hanning filter.
+    Hanning,
+    /// Use hamming filter.
+    Hamming,
+    /// Use bilinear filter. (default)
+    #[default]
+    Bilinear,
+    /// Use welsh filter.
+    Welsh,
+    /// Use bicubic filter.
+    Bicubic,
+    /// Use lanczos-2 filter.
+    Lanczos2,
+    /// Use lanczos-3 filter.
+    Lanczos3,
+    /// Use lanczos-4 filter.
+    Lanczos4,
+}
+#[rustfmt::skip]
+impl ConstDefault for ResampleMethod { const DEFAULT: Self = Self::Bilinear; }
+
+/// Image file format.
+///
+/// # Adaptation
+/// - Derived from `imageFormat` enum in the `libsixel` C library.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Format {
+    /// Graphics Interchange Format.
+    GIF = 0x0,
+    /// Portable Network Graphics.
+    PNG = 0x1,
+    /// Windows and OS/2 bitmap.
+    BMP = 0x2,
+    /// JPEG File Interchange Format.
+    JPG = 0x3,
+    /// Truevision TGA.
+    TGA = 0x4,
+    /// Tagged Image File Format.
+    TIFF = 0x6,
+    /// Sixel DCS sequence.
+    SIXEL = 0x7,
+    /// Netpbm portable any map (PBM, PGM, PPM and PAM).
+    PNM = 0x8,
+    /// Photoshop document.
+    PSD = 0xa,
+    /// Radiance RGBE.
+    HDR = 0xb,
+    /// Quite OK Image format.
+    QOI = 0xc,
+    /// The suckless farbfeld format.
+    Farbfeld = 0xd,
+}
+
+impl Format {
+    /// Detects the format from the magic bytes at the start of an image file.
+    ///
+    /// Returns `None` for unknown formats, and for [`TGA`][Self::TGA] images,
+    /// which don't have magic bytes.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::Format;
+    /// assert_eq![Format::detect(b"qoif\0\0\0\x01"), Some(Format::QOI)];
+    /// assert_eq![Format::detect(b"P6\n1 1\n255\n"), Some(Format::PNM)];
+    /// assert_eq![Format::detect(b"\x1bPq#0;2;0;0;0"), Some(Format::SIXEL)];
+    /// assert_eq![Format::detect(b"unknown"), None];
+    /// ```
+    #[must_use]
+    pub const fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            [b'G', b'I', b'F', b'8', b'7' | b'9', b'a', ..] => Some(Self::GIF),
+            [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n', ..] => Some(Self::PNG),
+            [b'B', b'M', ..] => Some(Self::BMP),
+            [0xFF, 0xD8, 0xFF, ..] => Some(Self::JPG),
+            [b'I', b'I', b'*', 0, ..] | [b'M', b'M', 0, b'*', ..] => Some(Self::TIFF),
+            [0x1B, b'P', ..] | [0x90, ..] => Some(Self::SIXEL),
+            [b'P', b'1'..=b'7', ..] => Some(Self::PNM),
+            [b'8', b'B', b'P', b'S', ..] => Some(Self::PSD),
+            [b'#', b'?', b'R', b'A', b'D', b'I', b'A', b'N', b'C', b'E', ..]
+            | [b'#', b'?', b'R', b'G', b'B', b'E', ..] => Some(Self::HDR),
+            [b'q', b'o', b'i', b'f', ..] => Some(Self::QOI),
+            [b'f', b'a', b'r', b'b', b'f', b'e', b'l', b'd', ..] => Some(Self::Farbfeld),
+            _ => None,
+        }
+    }
+}
 
 // /// Offset value of `PixelFormat`.
 // ///
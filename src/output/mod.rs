@@ -5,17 +5,24 @@
 // - struct SixelOutput
 
 use crate::{
-    dither::DitherConf, pixelformat::sixel_helper_normalize_pixelformat, SixelError, SixelResult,
+    dither::{pal_mono_dark, pal_mono_light, DitherConf, DitherMode},
+    pixelformat::sixel_helper_normalize_pixelformat,
+    quant::{diffuse_linear, srgb_to_linear, take_diffused, DiffusionKernel},
+    SixelError, SixelResult,
 };
 use alloc::{format, vec};
-use devela::{sys::Write as IoWrite, String, Vec};
+use devela::{
+    sys::{IoErrorKind, Write as IoWrite},
+    Vec,
+};
 
 mod dither_fns;
 use dither_fns::*;
 
+mod animation;
 mod builder;
 mod enums;
-pub use {builder::*, enums::*};
+pub use {animation::*, builder::*, enums::*};
 
 pub(crate) const SIXEL_PALETTE_MAX: usize = 256;
 // const SIXEL_USE_DEPRECATED_SYMBOLS: usize = 1;
@@ -24,12 +31,13 @@ pub(crate) const SIXEL_PALETTE_MAX: usize = 256;
 // const SIXEL_HEIGHT_LIMIT: usize = 1_000_000;
 
 // loader settings
-// const SIXEL_DEFAULT_GIF_DELAY: usize = 1;
+/// Default delay of the animation frames, in hundredths of a second.
+pub(crate) const SIXEL_DEFAULT_GIF_DELAY: u64 = 1;
 
-const DCS_START_7BIT: &str = "\x1BP";
-const DCS_START_8BIT: &str = "\u{220}";
-const DCS_END_7BIT: &str = "\x1B\\";
-const DCS_END_8BIT: &str = "\u{234}";
+const DCS_START_7BIT: &[u8] = b"\x1BP";
+const DCS_START_8BIT: &[u8] = b"\x90";
+const DCS_END_7BIT: &[u8] = b"\x1B\\";
+const DCS_END_8BIT: &[u8] = b"\x9C";
 const SCREEN_PACKET_SIZE: usize = 256;
 const PALETTE_HIT: i32 = 1;
 const PALETTE_CHANGE: i32 = 2;
@@ -62,35 +70,41 @@ pub(crate) struct SixelNode {
 /// Abstracts over writing sixel-encoded data,
 /// supporting various output targets such as files or terminal streams.
 ///
+/// The data is written in packets of 16 KiB while encoding,
+/// so it can stream into a locked stdout or a socket without buffering the whole image.
+/// The same output can encode several images, each one as a complete sixel sequence.
+///
+/// See [`DitherConf`] for an example.
+///
 /// # Adaptation
 /// - Derived from `sixel_output` struct in the `libsixel` C library.
 #[derive(Debug, Default, PartialEq, Eq, Hash)]
-pub(crate) struct SixelOutput<W: IoWrite> {
-    /* public fields
-     */
+pub struct SixelOutput<W: IoWrite> {
     /// Palette selection mode.
-    pub palette_type: PaletteType,
+    pub(crate) palette_type: PaletteType,
 
     /// Writer for output, managing data destination.
-    pub fn_write: W,
+    pub(crate) fn_write: W,
+    /// The first error returned by the writer, if any.
+    pub(crate) io_error: Option<IoErrorKind>,
 
     /// Last saved pixel value.
-    pub save_pixel: u8,
+    pub(crate) save_pixel: u8,
     /// Count of consecutive saved pixels.
-    pub save_count: i32,
+    pub(crate) save_count: i32,
     /// Currently active palette index.
-    pub active_palette: i32,
+    pub(crate) active_palette: i32,
 
     /// Collection of sixel nodes for dithering.
-    pub nodes: Vec<SixelNode>,
+    pub(crate) nodes: Vec<SixelNode>,
 
     /// Flag to allow penetration of the multiplexer.
-    pub penetrate_multiplexer: bool,
+    pub(crate) penetrate_multiplexer: bool,
     /// Policy for encoding decisions.
-    pub encode_policy: EncodePolicy,
+    pub(crate) encode_policy: EncodePolicy,
 
     /// Buffer for output data.
-    pub buffer: String,
+    pub(crate) buffer: Vec<u8>,
 
     /* private compatibility flags
      */
@@ -106,7 +120,7 @@ pub(crate) struct SixelOutput<W: IoWrite> {
 
     /// Argument limit for repeat introducer (DECGRI).
     ///
-    /// `false` if limited to 255, `true` if unlimited.
+    /// `true` if limited to 255, `false` if unlimited.
     pub(crate) has_gri_arg_limit: bool,
 
     /// DECSDM (CSI ? 80 h) sixel scrolling glitch flag.
@@ -118,14 +132,35 @@ pub(crate) struct SixelOutput<W: IoWrite> {
     ///
     /// `false` to process, `true` to skip.
     pub(crate) skip_dcs_envelope: bool,
+
+    /* header parameters
+     */
+    /// Pixel aspect ratio, as the (Pan, Pad) raster attributes.
+    pub(crate) aspect_ratio: (u16, u16),
+    /// Background selection (P2).
+    pub(crate) background_select: BackgroundSelect,
+    /// Horizontal grid size (P3), or 0 for the default.
+    pub(crate) grid_size: u16,
+    /// Whether to output the raster attributes.
+    pub(crate) raster_attributes: bool,
+
+    /* differential encoding
+     */
+    /// Whether to encode only the bands that changed from the previous image.
+    pub(crate) differential: bool,
+    /// The palette indices of the previous image, or empty if there's none.
+    pub(crate) previous_pixels: Vec<u8>,
+    /// The palette of the previous image.
+    pub(crate) previous_palette: Vec<u8>,
+    /// The size of the previous image.
+    pub(crate) previous_size: (i32, i32),
 }
 
-#[allow(dead_code, reason = "crate private struct")]
 impl<W: IoWrite> SixelOutput<W> {
     /// Packet size limit.
     pub(crate) const PACKET_SIZE: usize = 16_384;
 
-    /// Create new output context object
+    /// Creates a new output context that writes into `fn_write`.
     #[inline]
     pub fn new(fn_write: W) -> Self {
         Self {
@@ -135,6 +170,7 @@ impl<W: IoWrite> SixelOutput<W> {
             skip_dcs_envelope: false,
             palette_type: PaletteType::Auto,
             fn_write,
+            io_error: None,
             save_pixel: 0,
             save_count: 0,
             active_palette: -1,
@@ -142,10 +178,37 @@ impl<W: IoWrite> SixelOutput<W> {
             penetrate_multiplexer: false,
             encode_policy: EncodePolicy::Auto,
             has_sixel_scrolling: false,
-            buffer: String::new(),
+            buffer: Vec::new(),
+            aspect_ratio: (1, 1),
+            background_select: BackgroundSelect::Auto,
+            grid_size: 0,
+            raster_attributes: true,
+            differential: false,
+            previous_pixels: Vec::new(),
+            previous_palette: Vec::new(),
+            previous_size: (0, 0),
         }
     }
 
+    /// Returns a reference to the underlying writer.
+    #[inline]
+    #[must_use]
+    pub fn get_ref(&self) -> &W {
+        &self.fn_write
+    }
+    /// Returns a mutable reference to the underlying writer.
+    #[inline]
+    #[must_use]
+    pub fn get_mut(&mut self) -> &mut W {
+        &mut self.fn_write
+    }
+    /// Consumes the output context, returning the underlying writer.
+    #[inline]
+    #[must_use]
+    pub fn into_inner(self) -> W {
+        self.fn_write
+    }
+
     /// Get 8bit output mode which indicates whether it uses C1 control characters.
     #[inline]
     #[must_use]
@@ -160,13 +223,37 @@ impl<W: IoWrite> SixelOutput<W> {
 
     /// Set limit for repeat introducer (DECGRI).
     ///
-    /// `false` if limited to 255, `true` if unlimited.
+    /// `true` if limited to 255 (default), `false` if unlimited.
     #[inline]
     pub fn set_gri_arg_limit(&mut self, value: bool) {
         self.has_gri_arg_limit = value;
     }
+    /// Get limit for repeat introducer (DECGRI).
+    #[inline]
+    #[must_use]
+    pub fn get_gri_arg_limit(&self) -> bool {
+        self.has_gri_arg_limit
+    }
 
     /// Set GNU Screen penetration.
+    ///
+    /// The output is split into DCS packets that fit in the screen buffer.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherConf, SixelOutput};
+    /// let mut dither = DitherConf::new(2).unwrap();
+    /// dither.set_palette(&[[0, 0, 0], [255, 255, 255]]).unwrap();
+    /// // a noise that doesn't fit in a single packet
+    /// let noise = |i: u32| (i.wrapping_mul(2_654_435_761) >> 31) as u8 * 255;
+    /// let pixels: Vec<u8> = (0..256 * 256).flat_map(|i| [noise(i); 3]).collect();
+    ///
+    /// let mut output = SixelOutput::new(Vec::new());
+    /// output.set_penetrate_multiplexer(true);
+    /// output.encode(&pixels, 256, 256, &mut dither).unwrap();
+    /// let sixel = output.into_inner();
+    /// assert![sixel.len() > 16_384 && sixel.ends_with(b"\x1b\\\x1b\\")];
+    /// ```
     #[inline]
     pub fn set_penetrate_multiplexer(&mut self, penetrate: bool) {
         self.penetrate_multiplexer = penetrate;
@@ -189,6 +276,224 @@ impl<W: IoWrite> SixelOutput<W> {
     pub fn set_encode_policy(&mut self, encode_policy: EncodePolicy) {
         self.encode_policy = encode_policy;
     }
+
+    /// Set the pixel aspect ratio, as a `pan` vertical by `pad` horizontal proportion.
+    ///
+    /// It's output in the raster attributes, or as the closest P1 parameter when they
+    /// are omitted, which supports the ratios 1:1, 2:1, 3:1 and 5:1. Defaults to 1:1.
+    ///
+    /// Zero values are treated as 1.
+    #[inline]
+    pub fn set_aspect_ratio(&mut self, pan: u16, pad: u16) {
+        self.aspect_ratio = (pan.max(1), pad.max(1));
+    }
+
+    /// Set the background selection (P2).
+    #[inline]
+    pub fn set_background_select(&mut self, background_select: BackgroundSelect) {
+        self.background_select = background_select;
+    }
+
+    /// Set the horizontal grid size (P3), in decipoints, or 0 for the default.
+    #[inline]
+    pub fn set_grid_size(&mut self, grid_size: u16) {
+        self.grid_size = grid_size;
+    }
+
+    /// Set whether to output the raster attributes.
+    ///
+    /// `true` outputs the aspect ratio and the size of the image (default),
+    /// `false` omits them, for terminals that don't support them.
+    #[inline]
+    pub fn set_raster_attributes(&mut self, raster_attributes: bool) {
+        self.raster_attributes = raster_attributes;
+    }
+
+    /// Set whether to encode only the bands that changed from the previous image.
+    ///
+    /// Each image is compared with the palette indices of the previous one. When both
+    /// have the same size and palette, only the changed columns of the changed bands
+    /// of 6 pixels are drawn, leaving the rest of the previous image unchanged (P2 = 1),
+    /// and an image without changes outputs nothing.
+    ///
+    /// The changed regions are positioned with the sixel graphics cursor: each unchanged
+    /// band costs a graphics new line (`-`), and the unchanged columns before a changed
+    /// region a repeat of empty sixels (`!Pn?`). The text cursor can't position them,
+    /// since it moves by character cells, whose height is rarely a multiple of 6 pixels.
+    ///
+    /// A full image is encoded when most of the bands changed,
+    /// or when the image has a transparent color.
+    /// The [`HighColor`][crate::Quality::HighColor] mode always encodes full images.
+    ///
+    /// Setting it forgets the previous image, e.g. after clearing the screen.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherConf, DitherMode, SixelOutput};
+    /// let mut dither = DitherConf::new(2).unwrap();
+    /// dither.set_palette(&[[0, 0, 0], [255, 255, 255]]).unwrap();
+    /// let mut output = SixelOutput::new(Vec::new());
+    /// output.set_differential(true);
+    ///
+    /// // 2x12 pixels, with two bands of 6 pixels
+    /// let mut frame = [0; 2 * 12 * 3];
+    /// output.encode(&frame, 2, 12, &mut dither).unwrap();
+    /// let full = core::mem::take(output.get_mut());
+    ///
+    /// // no changes
+    /// output.encode(&frame, 2, 12, &mut dither).unwrap();
+    /// assert![output.get_ref().is_empty()];
+    ///
+    /// // only the second column of the second band is drawn, with both colors
+    /// frame[(11 * 2 + 1) * 3..].fill(255);
+    /// output.encode(&frame, 2, 12, &mut dither).unwrap();
+    /// assert_eq![output.get_ref(), b"\x1bP0;1q\"1;1;2;12#0;2;0;0;0#1;2;100;100;100-#0?^$#1?_\x1b\\"];
+    ///
+    /// // a full palette can be compared too, since no index is needed for the unchanged pixels
+    /// let mut dither = DitherConf::new_mode(DitherMode::XTerm256);
+    /// let mut frame = [0; 8 * 24 * 3];
+    /// output.encode(&frame, 8, 24, &mut dither).unwrap();
+    /// output.get_mut().clear();
+    /// // a white pixel in the third column of the third band
+    /// frame[(12 * 8 + 2) * 3..][..3].fill(255);
+    /// output.encode(&frame, 8, 24, &mut dither).unwrap();
+    /// assert![output.get_ref().ends_with(b"#255;2;93;93;93--#0??}$#15??@-\x1b\\")];
+    /// ```
+    #[inline]
+    pub fn set_differential(&mut self, differential: bool) {
+        self.differential = differential;
+        self.previous_pixels.clear();
+    }
+
+    /// Encodes an image as a complete sixel sequence, using the given dither configuration.
+    ///
+    /// The `pixels` are interpreted with the pixel format of the `dither` configuration.
+    /// Unless it's in [`HighColor`][crate::Quality::HighColor] mode,
+    /// its palette must be initialized, either from an image with
+    /// [`initialize`][DitherConf::initialize] or directly with
+    /// [`set_palette`][DitherConf::set_palette].
+    ///
+    /// # Errors
+    /// Returns [`BadInput`][SixelError::BadInput] if the size is 0,
+    /// `pixels` is not long enough for the given size and pixel format,
+    /// or the indices of a `PAL*` pixel format are beyond the palette,
+    /// [`BadIntegerOverflow`][SixelError::BadIntegerOverflow] if the image is too big,
+    /// and [`Io`][SixelError::Io] if the writer fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{DitherConf, SixelError, SixelOutput};
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(8);
+    /// let mut palette = DitherConf::new(2).unwrap();
+    /// palette.set_palette(&[[255, 0, 0], [0, 0, 255]]).unwrap();
+    ///
+    /// // the errors of a full writer are returned in every quality mode
+    /// for mut dither in [palette, DitherConf::new_highcolor()] {
+    ///     let mut small = [0; 8];
+    ///     let mut output = SixelOutput::new(&mut small[..]);
+    ///     assert![matches![output.encode(&rgb, 4, 4, &mut dither), Err(SixelError::Io(_))]];
+    /// }
+    /// ```
+    pub fn encode(
+        &mut self,
+        pixels: &[u8],
+        width: usize,
+        height: usize,
+        dither: &mut DitherConf,
+    ) -> SixelResult<()> {
+        let (width, height) = check_image(pixels, width, height, dither.pixelformat)?;
+        self.io_error = None;
+        match dither.quality_mode {
+            crate::Quality::Auto
+            | crate::Quality::High
+            | crate::Quality::Low
+            | crate::Quality::Full => {
+                self.encode_dither(pixels, width, height, dither)?;
+            }
+            crate::Quality::HighColor => {
+                self.encode_highcolor(pixels, width, height, dither)?;
+            }
+        }
+        self.io_error.map_or(Ok(()), |kind| Err(SixelError::Io(kind)))
+    }
+}
+
+/// Writes all the `bytes` into the `writer`, unless there's already an `error`.
+///
+/// Only the first error is recorded, and nothing else is written after it.
+fn write_all<W: IoWrite>(writer: &mut W, error: &mut Option<IoErrorKind>, bytes: &[u8]) {
+    if error.is_none() {
+        if let Err(e) = writer.write_all(bytes) {
+            *error = Some(e.kind());
+        }
+    }
+}
+
+/// Returns which `pixels` are outside the changed regions,
+/// an empty vector if nothing changed, or `None` if most of the bands changed.
+///
+/// The changed regions span from the first to the last changed column of each band.
+fn diff_bands(pixels: &[u8], previous: &[u8], width: usize, height: usize) -> Option<Vec<bool>> {
+    let bands = height.div_ceil(6);
+    let ranges: Vec<Option<(usize, usize)>> = (0..bands)
+        .map(|band| {
+            let rows = band * 6 * width..((band + 1) * 6).min(height) * width;
+            let (new, old) = (&pixels[rows.clone()], &previous[rows]);
+            new.chunks_exact(width)
+                .zip(old.chunks_exact(width))
+                .fold(None, |range, (a, b)| match a.iter().zip(b).position(|(a, b)| a != b) {
+                    Some(first) => {
+                        let last = a.iter().zip(b).rposition(|(a, b)| a != b).unwrap_or(first);
+                        Some(range.map_or((first, last), |(x0, x1): (usize, usize)| {
+                            (x0.min(first), x1.max(last))
+                        }))
+                    }
+                    None => range,
+                })
+        })
+        .collect();
+    let changed = ranges.iter().filter(|range| range.is_some()).count();
+    if changed == 0 {
+        return Some(Vec::new());
+    } else if changed * 2 > bands {
+        return None;
+    }
+    let mut unchanged = vec![true; pixels.len()];
+    for (band, range) in ranges.iter().enumerate() {
+        let rows = band * 6 * width..((band + 1) * 6).min(height) * width;
+        if let Some((x0, x1)) = range {
+            for row in unchanged[rows].chunks_exact_mut(width) {
+                row[*x0..=*x1].fill(false);
+            }
+        }
+    }
+    Some(unchanged)
+}
+
+/// Checks the size of an image and returns it converted to `i32`.
+///
+/// # Errors
+/// Returns [`BadInput`][SixelError::BadInput] if the size is 0
+/// or `data` is not long enough, and
+/// [`BadIntegerOverflow`][SixelError::BadIntegerOverflow] if there are too many pixels.
+pub(crate) fn check_image(
+    data: &[u8],
+    width: usize,
+    height: usize,
+    pixelformat: PixelFormat,
+) -> SixelResult<(i32, i32)> {
+    if width == 0 || height == 0 {
+        return Err(SixelError::BadInput);
+    }
+    let w = i32::try_from(width).map_err(|_| SixelError::BadIntegerOverflow)?;
+    let h = i32::try_from(height).map_err(|_| SixelError::BadIntegerOverflow)?;
+    if w.checked_mul(h).and_then(|n| n.checked_mul(4)).is_none() {
+        return Err(SixelError::BadIntegerOverflow);
+    }
+    if data.len() < pixelformat.required_bytes(width, height) {
+        return Err(SixelError::BadInput);
+    }
+    Ok((w, h))
 }
 
 // original code from tosixel.rs
@@ -201,16 +506,17 @@ impl<W: IoWrite> SixelOutput<W> {
     /// Segments data according to `SCREEN_PACKET_SIZE`, splitting if necessary.
     fn penetrate(
         &mut self,
-        nwrite: usize,   // output size
-        dcs_start: &str, // DCS introducer
-        dcs_end: &str,   // DCS terminato
+        nwrite: usize,    // output size
+        dcs_start: &[u8], // DCS introducer
+        dcs_end: &[u8],   // DCS terminator
     ) {
         let splitsize = SCREEN_PACKET_SIZE - dcs_start.len() - dcs_end.len();
         let mut pos = 0;
         while pos < nwrite {
-            let _ = self.fn_write.write(dcs_start.as_bytes());
-            let _ = self.fn_write.write(self.buffer[pos..pos + splitsize].as_bytes());
-            let _ = self.fn_write.write(dcs_end.as_bytes());
+            let end = nwrite.min(pos + splitsize);
+            write_all(&mut self.fn_write, &mut self.io_error, dcs_start);
+            write_all(&mut self.fn_write, &mut self.io_error, &self.buffer[pos..end]);
+            write_all(&mut self.fn_write, &mut self.io_error, dcs_end);
             pos += splitsize;
         }
     }
@@ -223,62 +529,62 @@ impl<W: IoWrite> SixelOutput<W> {
             if self.penetrate_multiplexer {
                 self.penetrate(SixelOutput::<W>::PACKET_SIZE, DCS_START_7BIT, DCS_END_7BIT);
             } else {
-                let _ =
-                    self.fn_write.write(self.buffer[..SixelOutput::<W>::PACKET_SIZE].as_bytes());
+                let packet = &self.buffer[..SixelOutput::<W>::PACKET_SIZE];
+                write_all(&mut self.fn_write, &mut self.io_error, packet);
             }
             self.buffer.drain(0..SixelOutput::<W>::PACKET_SIZE);
         }
     }
 
-    /// Writes a single character to the output.
+    /// Writes a single byte to the output.
     #[inline]
-    pub fn putc(&mut self, value: char) {
+    pub(crate) fn putc(&mut self, value: u8) {
         self.buffer.push(value);
     }
 
-    /// Writes a string to the output.
+    /// Writes a byte string to the output.
     #[inline]
-    pub fn puts(&mut self, value: &str) {
-        self.buffer.push_str(value);
+    pub(crate) fn puts(&mut self, value: &[u8]) {
+        self.buffer.extend_from_slice(value);
     }
 
     /// Writes an integer value to the output as a string.
     #[inline]
     pub(crate) fn puti(&mut self, i: i32) {
-        self.puts(format!("{}", i).as_str());
+        self.puts(format!("{}", i).as_bytes());
     }
 
     /// Writes a byte value to the output as a string.
     #[inline]
     #[expect(unused, reason = "…")]
     pub(crate) fn putb(&mut self, b: u8) {
-        self.puts(format!("{}", b).as_str());
+        self.puts(format!("{}", b).as_bytes());
     }
 
     /// Adds a "flash" signal in the output stream.
-    pub fn put_flash(&mut self) -> SixelResult<()> {
+    pub(crate) fn put_flash(&mut self) -> SixelResult<()> {
         if self.has_gri_arg_limit {
             /* VT240 Max 255 ? */
             while self.save_count > 255 {
                 /* argument of DECGRI('!') is limitted to 255 in real VT */
-                self.puts("!255");
+                self.puts(b"!255");
                 self.advance();
-                self.putc(unsafe { char::from_u32_unchecked(self.save_pixel as u32) });
+                self.putc(self.save_pixel);
                 self.advance();
                 self.save_count -= 255;
             }
         }
         if self.save_count > 3 {
             /* DECGRI Graphics Repeat Introducer ! Pn Ch */
-            self.putc('!');
+            self.putc(b'!');
             self.advance();
             self.puti(self.save_count);
             self.advance();
-            self.putc(unsafe { char::from_u32_unchecked(self.save_pixel as u32) });
+            self.putc(self.save_pixel);
             self.advance();
         } else {
             for _ in 0..self.save_count {
-                self.putc(unsafe { char::from_u32_unchecked(self.save_pixel as u32) });
+                self.putc(self.save_pixel);
                 self.advance();
             }
         }
@@ -288,7 +594,7 @@ impl<W: IoWrite> SixelOutput<W> {
     }
 
     /// Outputs a single pixel to the sixel stream.
-    pub fn put_pixel(&mut self, mut pix: u8) -> SixelResult<()> {
+    pub(crate) fn put_pixel(&mut self, mut pix: u8) -> SixelResult<()> {
         if pix > b'?' {
             pix = b'\0';
         }
@@ -304,17 +610,16 @@ impl<W: IoWrite> SixelOutput<W> {
     }
 
     /// Writes a sixel node to the output, with additional parameters for color and position.
-    pub fn put_node(
+    pub(crate) fn put_node(
         &mut self,     /* output context */
         x: &mut i32,   /* header position */
         np: SixelNode, /* node object */
-        ncolors: i32,  /* number of palette colors */
-        keycolor: i32,
+        designate: bool,
     ) -> SixelResult<()> {
-        if ncolors != 2 || keycolor == -1 {
+        if designate {
             /* designate palette index */
             if self.active_palette != np.pal {
-                self.putc('#');
+                self.putc(b'#');
                 self.advance();
                 self.puti(np.pal);
                 self.advance();
@@ -323,15 +628,11 @@ impl<W: IoWrite> SixelOutput<W> {
         }
 
         while *x < np.sx {
-            if *x != keycolor {
-                self.put_pixel(0)?;
-            }
+            self.put_pixel(0)?;
             *x += 1;
         }
         while *x < np.mx {
-            if *x != keycolor {
-                self.put_pixel(np.map[*x as usize])?;
-            }
+            self.put_pixel(np.map[*x as usize])?;
             *x += 1;
         }
         self.put_flash()?;
@@ -339,11 +640,32 @@ impl<W: IoWrite> SixelOutput<W> {
     }
 
     /// Encodes and outputs the sixel image header with the specified width and height.
-    pub fn encode_header(&mut self, width: i32, height: i32) -> SixelResult<()> {
-        let p = [0, 0, 0];
+    ///
+    /// The `transparent` images select to leave the undrawn pixels unchanged (P2 = 1).
+    pub(crate) fn encode_header(
+        &mut self,
+        width: i32,
+        height: i32,
+        transparent: bool,
+    ) -> SixelResult<()> {
+        let (pan, pad) = self.aspect_ratio;
+        let (n, d) = (u32::from(pan), u32::from(pad));
+        /* the raster attributes override the aspect ratio selected by P1 */
+        let p1 = if self.raster_attributes {
+            0
+        } else if n >= d * 4 {
+            2 /* 5:1 */
+        } else if n * 2 >= d * 5 {
+            3 /* 3:1 */
+        } else if n * 2 >= d * 3 {
+            0 /* 2:1 */
+        } else {
+            9 /* 1:1 */
+        };
+        let p = [p1, i32::from(self.background_select.p2(transparent)), i32::from(self.grid_size)];
         let mut pcount = 3;
 
-        let use_raster_attributes = true;
+        let use_raster_attributes = self.raster_attributes;
 
         if !self.skip_dcs_envelope {
             if self.has_8bit_control {
@@ -369,12 +691,12 @@ impl<W: IoWrite> SixelOutput<W> {
             self.puti(p[0]);
             self.advance();
             if pcount > 1 {
-                self.putc(';');
+                self.putc(b';');
                 self.advance();
                 self.puti(p[1]);
                 self.advance();
                 if pcount > 2 {
-                    self.putc(';');
+                    self.putc(b';');
                     self.advance();
                     self.puti(p[2]);
                     self.advance();
@@ -382,15 +704,23 @@ impl<W: IoWrite> SixelOutput<W> {
             }
         }
 
-        self.putc('q');
+        self.putc(b'q');
         self.advance();
 
         if use_raster_attributes {
-            self.puts("\"1;1;");
+            self.putc(b'"');
+            self.advance();
+            self.puti(i32::from(pan));
+            self.advance();
+            self.putc(b';');
+            self.advance();
+            self.puti(i32::from(pad));
+            self.advance();
+            self.putc(b';');
             self.advance();
             self.puti(width);
             self.advance();
-            self.putc(';');
+            self.putc(b';');
             self.advance();
             self.puti(height);
             self.advance();
@@ -400,7 +730,7 @@ impl<W: IoWrite> SixelOutput<W> {
     }
 
     /// Outputs an RGB color palette definition.
-    pub fn output_rgb_palette_definition(
+    pub(crate) fn output_rgb_palette_definition(
         &mut self,
         palette: &[u8],
         n: i32,
@@ -408,19 +738,19 @@ impl<W: IoWrite> SixelOutput<W> {
     ) -> SixelResult<()> {
         if n != keycolor {
             /* DECGCI Graphics Color Introducer  # Pc ; Pu; Px; Py; Pz */
-            self.putc('#');
+            self.putc(b'#');
             self.advance();
             self.puti(n);
             self.advance();
-            self.puts(";2;");
+            self.puts(b";2;");
             self.advance();
             self.puti((palette[n as usize * 3] as i32 * 100 + 127) / 255);
             self.advance();
-            self.putc(';');
+            self.putc(b';');
             self.advance();
             self.puti((palette[n as usize * 3 + 1] as i32 * 100 + 127) / 255);
             self.advance();
-            self.putc(';');
+            self.putc(b';');
             self.advance();
             self.puti((palette[n as usize * 3 + 2] as i32 * 100 + 127) / 255);
             self.advance();
@@ -429,7 +759,7 @@ impl<W: IoWrite> SixelOutput<W> {
     }
 
     /// Outputs an HLS color palette definition.
-    pub fn output_hls_palette_definition(
+    pub(crate) fn output_hls_palette_definition(
         &mut self,
         palette: &[u8],
         n: i32,
@@ -467,19 +797,19 @@ impl<W: IoWrite> SixelOutput<W> {
                 }
             }
             /* DECGCI Graphics Color Introducer  # Pc ; Pu; Px; Py; Pz */
-            self.putc('#');
+            self.putc(b'#');
             self.advance();
             self.puti(n as i32);
             self.advance();
-            self.puts(";1;");
+            self.puts(b";1;");
             self.advance();
             self.puti(h);
             self.advance();
-            self.putc(';');
+            self.putc(b';');
             self.advance();
             self.puti(l);
             self.advance();
-            self.putc(';');
+            self.putc(b';');
             self.advance();
             self.puti(s);
             self.advance();
@@ -488,8 +818,10 @@ impl<W: IoWrite> SixelOutput<W> {
     }
 
     /// Encodes the sixel image body, including pixel and color data.
+    ///
+    /// The `unchanged` pixels are left undrawn, like the ones of the `keycolor`.
     #[expect(clippy::too_many_arguments)]
-    pub fn encode_body(
+    pub(crate) fn encode_body(
         &mut self,
         pixels: &[u8],
         width: i32,
@@ -499,6 +831,7 @@ impl<W: IoWrite> SixelOutput<W> {
         keycolor: i32,
         bodyonly: bool,
         palstate: Option<&[i32]>,
+        unchanged: Option<&[bool]>,
     ) -> SixelResult<()> {
         if palette.is_empty() {
             return Err(SixelError::BadArgument);
@@ -508,7 +841,12 @@ impl<W: IoWrite> SixelOutput<W> {
 
         let mut map: Vec<u8> = vec![0; len];
 
-        if !bodyonly && (ncolors != 2 || keycolor == (-1)) {
+        /* monochrome palettes with a transparent color use the terminal colors */
+        let designate = ncolors != 2
+            || keycolor == -1
+            || !matches!(palette.get(..6), Some(p) if p == pal_mono_dark || p == pal_mono_light);
+
+        if !bodyonly && designate {
             if matches!(self.palette_type, PaletteType::Hls) {
                 for n in 0..ncolors {
                     self.output_hls_palette_definition(palette, n as i32, keycolor)?;
@@ -550,8 +888,10 @@ impl<W: IoWrite> SixelOutput<W> {
                     " (y * width > INT_MAX - x)");*/
                     return Err(SixelError::BadIntegerOverflow);
                 }
-                pix = pixels[(check_integer_overflow + x) as usize] as i32; /* color index */
-                if pix >= 0 && (pix as usize) < ncolors && pix != keycolor {
+                let pos = (check_integer_overflow + x) as usize;
+                pix = pixels[pos] as i32; /* color index */
+                let drawn = unchanged.is_none_or(|unchanged| !unchanged[pos]);
+                if pix >= 0 && (pix as usize) < ncolors && pix != keycolor && drawn {
                     if pix > i32::MAX / width {
                         /* integer overflow */
                         /*sixel_helper_set_additional_message(
@@ -617,16 +957,16 @@ impl<W: IoWrite> SixelOutput<W> {
                 }
             }
 
-            if y != 5 {
-                /* DECGNL Graphics Next Line */
-                self.putc('-');
+            if y >= 6 {
+                /* DECGNL Graphics Next Line (not before the first band) */
+                self.putc(b'-');
                 self.advance();
             }
             let mut x = 0;
             while let Some(mut np) = self.nodes.pop() {
                 if x > np.sx {
                     /* DECGCR Graphics Carriage Return */
-                    self.putc('$');
+                    self.putc(b'$');
                     self.advance();
                     x = 0;
                 }
@@ -639,7 +979,7 @@ impl<W: IoWrite> SixelOutput<W> {
                         np.map[j as usize] = v;
                     }
                 }
-                self.put_node(&mut x, np, ncolors as i32, keycolor)?;
+                self.put_node(&mut x, np, designate)?;
 
                 let mut ni = self.nodes.len() as i32 - 1;
                 while ni >= 0 {
@@ -660,7 +1000,7 @@ impl<W: IoWrite> SixelOutput<W> {
                         }
                     }
                     let np = self.nodes.remove(ni as usize);
-                    self.put_node(&mut x, np, ncolors as i32, keycolor)?;
+                    self.put_node(&mut x, np, designate)?;
                     ni -= 1;
                 }
 
@@ -673,14 +1013,14 @@ impl<W: IoWrite> SixelOutput<W> {
         }
 
         if palstate.is_some() {
-            self.putc('$');
+            self.putc(b'$');
             self.advance();
         }
         Ok(())
     }
 
     /// Encodes and outputs the sixel image footer.
-    pub fn encode_footer(&mut self) -> SixelResult<()> {
+    pub(crate) fn encode_footer(&mut self) -> SixelResult<()> {
         if !self.skip_dcs_envelope && !self.penetrate_multiplexer {
             if self.has_8bit_control {
                 self.puts(DCS_END_8BIT);
@@ -695,31 +1035,48 @@ impl<W: IoWrite> SixelOutput<W> {
         if !self.buffer.is_empty() {
             if self.penetrate_multiplexer {
                 self.penetrate(self.buffer.len(), DCS_START_7BIT, DCS_END_7BIT);
-                let _ = self.fn_write.write(b"\x1B\\");
+                write_all(&mut self.fn_write, &mut self.io_error, b"\x1B\\");
             } else {
-                let _ = self.fn_write.write(self.buffer.as_bytes());
+                write_all(&mut self.fn_write, &mut self.io_error, &self.buffer);
+            }
+            self.buffer.clear();
+        }
+        if self.io_error.is_none() {
+            if let Err(e) = self.fn_write.flush() {
+                self.io_error = Some(e.kind());
             }
         }
         Ok(())
     }
 
     /// Encodes a sixel dithered image with specified pixels and configuration.
-    pub fn encode_dither(
+    pub(crate) fn encode_dither(
         &mut self,
         pixels: &[u8],
         width: i32,
         height: i32,
         dither: &mut DitherConf,
     ) -> SixelResult<()> {
+        let mut keycolor = dither.keycolor;
+        /* the gray levels are indices only into their own grayscale palette */
+        let indexed = match DitherMode::gray(dither.pixelformat) {
+            Some(mode) => dither.dither_mode == Some(mode),
+            None => matches!(
+                dither.pixelformat,
+                PixelFormat::PAL1 | PixelFormat::PAL2 | PixelFormat::PAL4 | PixelFormat::PAL8
+            ),
+        };
         let input_pixels = match dither.pixelformat {
             PixelFormat::PAL1
             | PixelFormat::PAL2
             | PixelFormat::PAL4
             | PixelFormat::G1
             | PixelFormat::G2
-            | PixelFormat::G4 => {
+            | PixelFormat::G4
+                if indexed =>
+            {
                 let mut paletted_pixels = vec![0; (width * height * 3) as usize];
-                dither.pixelformat = sixel_helper_normalize_pixelformat(
+                sixel_helper_normalize_pixelformat(
                     &mut paletted_pixels,
                     pixels,
                     dither.pixelformat,
@@ -729,54 +1086,117 @@ impl<W: IoWrite> SixelOutput<W> {
                 paletted_pixels
             }
 
-            PixelFormat::PAL8 | PixelFormat::G8 | PixelFormat::GA88 | PixelFormat::AG88 => {
-                pixels.to_vec()
+            PixelFormat::PAL8 | PixelFormat::G8 if indexed => {
+                pixels[..(width * height) as usize].to_vec()
             }
 
             _ => {
                 /* apply palette */
-                dither.apply_palette(pixels, width, height)?
+                let (paletted_pixels, transparent) =
+                    dither.apply_palette_masked(pixels, width, height)?;
+                keycolor = transparent.unwrap_or(keycolor);
+                paletted_pixels
             }
         };
-        self.encode_header(width, height)?;
+        if indexed
+            && input_pixels[..(width * height) as usize]
+                .iter()
+                .any(|&index| i32::from(index) >= dither.ncolors)
+        {
+            return Err(SixelError::BadInput);
+        }
+        let ncolors = dither.ncolors.max(keycolor + 1);
+
+        if self.differential {
+            if let Some(unchanged) =
+                self.diff_previous(&input_pixels, width, height, dither, keycolor)
+            {
+                if unchanged.is_empty() {
+                    return Ok(());
+                }
+                /* the unchanged pixels are left undrawn, keeping the previous image */
+                let background_select = self.background_select;
+                self.background_select = BackgroundSelect::Transparent;
+                self.encode_header(width, height, true)?;
+                self.background_select = background_select;
+                self.encode_body(
+                    &input_pixels,
+                    width,
+                    height,
+                    &dither.palette,
+                    ncolors as usize,
+                    keycolor,
+                    dither.bodyonly,
+                    None,
+                    Some(&unchanged),
+                )?;
+                self.previous_pixels = input_pixels;
+                return self.encode_footer();
+            }
+        }
+        self.encode_header(width, height, keycolor != -1)?;
         self.encode_body(
             &input_pixels,
             width,
             height,
             &dither.palette,
-            dither.ncolors as usize,
-            dither.keycolor,
+            ncolors as usize,
+            keycolor,
             dither.bodyonly,
             None,
+            None,
         )?;
         self.encode_footer()?;
+        if self.differential {
+            self.previous_pixels = input_pixels;
+        }
         Ok(())
     }
 
+    /// Compares the palette indices of an image with the previous one, remembering it.
+    ///
+    /// Returns which pixels are outside the changed regions,
+    /// an empty vector if nothing changed, or `None` to encode the full image.
+    fn diff_previous(
+        &mut self,
+        pixels: &[u8],
+        width: i32,
+        height: i32,
+        dither: &DitherConf,
+        keycolor: i32,
+    ) -> Option<Vec<bool>> {
+        let ncolors = dither.ncolors as usize;
+        let palette = dither.palette.get(..ncolors * 3).unwrap_or(&dither.palette);
+        let comparable = keycolor == -1
+            && self.previous_size == (width, height)
+            && self.previous_pixels.len() == pixels.len()
+            && self.previous_palette == palette;
+        self.previous_size = (width, height);
+        if !comparable {
+            self.previous_palette.clear();
+            self.previous_palette.extend_from_slice(palette);
+            self.previous_pixels.clear();
+            return None;
+        }
+        diff_bands(pixels, &self.previous_pixels, width as usize, height as usize)
+    }
+
     /// Encodes a high-color sixel image.
-    pub fn encode_highcolor(
+    pub(crate) fn encode_highcolor(
         &mut self,
-        pixels: &mut [u8],
+        pixels: &[u8],
         width: i32,
         mut height: i32,
         dither: &mut DitherConf,
     ) -> SixelResult<()> {
         let maxcolors = 1 << 15;
         let mut px_idx = 0;
-        let mut normalized_pixels = vec![0; (width * height * 3) as usize];
-        let pixels = if !matches!(dither.pixelformat, PixelFormat::BGR888) {
-            /* normalize pixelfromat */
-            sixel_helper_normalize_pixelformat(
-                &mut normalized_pixels,
-                pixels,
-                dither.pixelformat,
-                width,
-                height,
-            )?;
-            &mut normalized_pixels
-        } else {
-            pixels
-        };
+        /* the pixels are modified while dithering, so they are always copied */
+        let (mut normalized_pixels, mask) = dither.normalize_pixels(pixels, width, height)?;
+        let pixels = &mut normalized_pixels;
+        /* the palette is built while encoding, and needs room for all the colors */
+        dither.palette.resize(SIXEL_PALETTE_MAX * 3, 0);
+        dither.ncolors = SIXEL_PALETTE_MAX as i32;
         let mut paletted_pixels: Vec<u8> = vec![0; (width * height) as usize];
         let mut rgbhit = vec![0; maxcolors as usize];
         let mut rgb2pal = vec![0; maxcolors as usize];
@@ -787,6 +1207,16 @@ impl<W: IoWrite> SixelOutput<W> {
         let mut palstate: Vec<i32> = vec![0; SIXEL_PALETTE_MAX];
         let mut palhitcount: Vec<i32> = vec![0; SIXEL_PALETTE_MAX];
         let mut marks = vec![false; (width * 6) as usize];
+        let linear_kernel = dither
+            .linear_diffusion
+            .then(|| DiffusionKernel::new(dither.method_for_diffuse))
+            .flatten();
+        // the error diffused in linear light to each pixel component
+        let mut diffused = if linear_kernel.is_some() {
+            vec![0; pixels.len()]
+        } else {
+            Vec::new()
+        };
         while is_running {
             let mut dst = 0;
             let mut nextpal: usize = 0;
@@ -801,18 +1231,31 @@ impl<W: IoWrite> SixelOutput<W> {
             let mut mod_y = 0;
 
             loop {
-                for x in 0..width {
-                    if marks[mptr] {
+                // scan the odd rows from right to left in serpentine
+                let reverse = dither.serpentine && y % 2 == 1;
+                let (row_px_idx, row_dst, row_mptr) = (px_idx, dst, mptr);
+                for i in 0..width {
+                    let x = if reverse { width - 1 - i } else { i };
+                    let px_idx = row_px_idx + x as usize * 3;
+                    let (dst, mptr) = (row_dst + x as usize, row_mptr + x as usize);
+                    let transparent = mask.as_ref().is_some_and(|mask| mask[px_idx / 3]);
+                    if marks[mptr] || transparent {
                         paletted_pixels[dst] = 255;
                     } else {
-                        sixel_apply_15bpp_dither(
-                            &mut pixels[px_idx..],
-                            x,
-                            y,
-                            width,
-                            height,
-                            dither.method_for_diffuse,
-                        );
+                        let values = linear_kernel
+                            .map(|_| take_diffused(&mut pixels[px_idx..], &mut diffused[px_idx..]));
+                        if values.is_none() {
+                            sixel_apply_15bpp_dither(
+                                pixels,
+                                px_idx,
+                                x,
+                                y,
+                                width,
+                                height,
+                                dither.method_for_diffuse,
+                                reverse,
+                            );
+                        }
                         let pix = ((pixels[px_idx] & 0xf8) as i32) << 7
                             | ((pixels[px_idx + 1] & 0xf8) as i32) << 2
                             | ((pixels[px_idx + 2] >> 3) & 0x1f) as i32;
@@ -869,12 +1312,31 @@ impl<W: IoWrite> SixelOutput<W> {
                                 palhitcount[pp] += 1;
                             }
                         }
+                        // diffuse the error from the assigned color, unless it's deferred
+                        if let (Some(kernel), Some(values)) = (linear_kernel, values) {
+                            let pal = paletted_pixels[dst] as usize * 3;
+                            if paletted_pixels[dst] != 255 {
+                                let errors = core::array::from_fn(|n| {
+                                    values[n] - srgb_to_linear(dither.palette[pal + n])
+                                });
+                                diffuse_linear(
+                                    &mut diffused,
+                                    px_idx,
+                                    width,
+                                    height,
+                                    x,
+                                    y,
+                                    errors,
+                                    kernel,
+                                    reverse,
+                                );
+                            }
+                        }
                     }
-
-                    mptr += 1;
-                    dst += 1;
-                    px_idx += 3;
                 }
+                mptr += width as usize;
+                dst += width as usize;
+                px_idx += width as usize * 3;
                 y += 1;
                 if y >= height {
                     if dirty {
@@ -888,7 +1350,7 @@ impl<W: IoWrite> SixelOutput<W> {
                     let orig_height = height;
 
                     if output_count == 0 {
-                        self.encode_header(width, height)?;
+                        self.encode_header(width, height, mask.is_some())?;
                     }
                     output_count += 1;
 
@@ -903,6 +1365,7 @@ impl<W: IoWrite> SixelOutput<W> {
                         255,
                         dither.bodyonly,
                         Some(&palstate),
+                        None,
                     )?;
                     if y >= orig_height {
                         // end outer loop
@@ -923,10 +1386,10 @@ impl<W: IoWrite> SixelOutput<W> {
             }
         }
         if output_count == 0 {
-            self.encode_header(width, height)?;
+            self.encode_header(width, height, mask.is_some())?;
         }
 
-        let _ = self.encode_body(
+        self.encode_body(
             &paletted_pixels,
             width,
             height,
@@ -935,69 +1398,8 @@ impl<W: IoWrite> SixelOutput<W> {
             255,
             dither.bodyonly,
             Some(&palstate),
-        );
-
-        let _ = self.encode_footer();
-
-        Ok(())
-    }
-
-    /// Encodes a sixel image with dither and color depth settings.
-    pub fn encode(
-        &mut self,
-        pixels: &mut [u8],
-        width: i32,
-        height: i32,
-        _depth: i32, /* color depth */
-        dither: &mut DitherConf,
-    ) -> SixelResult<()> /* output context */ {
-        /*
-            println!("sixel_encode: {} x {} depth {}", width, height, _depth);
-            println!("dither:");
-            println!("\treqcolors: {}", dither.reqcolors);
-            println!("\tncolors: {}", dither.ncolors);
-            println!("\torigcolors: {}", dither.origcolors);
-            println!("\toptimized: {}", dither.optimized);
-            println!("\toptimize_palette: {}", dither.optimize_palette);
-            println!("\tcomplexion: {}", dither.complexion);
-            println!("\tbodyonly: {}", dither.bodyonly);
-            println!("\tmethod_for_largest: {:?}", dither.method_for_largest as i32);
-            println!("\tmethod_for_rep: {:?}", dither.method_for_rep as i32);
-            println!("\tmethod_for_diffuse: {:?}", dither.method_for_diffuse as i32);
-            println!("\tquality_mode: {:?}", dither.quality_mode as i32);
-            println!("\tkeycolor: {:?}", dither.keycolor);
-            println!("\tpixelformat: {:?}", dither.pixelformat as i32);
-        */
-        if width < 1 {
-            return Err(SixelError::BadInput);
-            /*
-            sixel_helper_set_additional_message(
-                "sixel_encode: bad width parameter."
-                " (width < 1)");
-            status = SIXEL_BAD_INPUT;
-            goto end;*/
-        }
-
-        if height < 1 {
-            return Err(SixelError::BadInput);
-            /*
-            sixel_helper_set_additional_message(
-                "sixel_encode: bad height parameter."
-                " (height < 1)");
-            status = SIXEL_BAD_INPUT;
-            goto end;*/
-        }
-        match dither.quality_mode {
-            crate::Quality::Auto
-            | crate::Quality::High
-            | crate::Quality::Low
-            | crate::Quality::Full => {
-                self.encode_dither(pixels, width, height, dither)?;
-            }
-            crate::Quality::HighColor => {
-                self.encode_highcolor(pixels, width, height, dither)?;
-            }
-        }
-        Ok(())
+            None,
+        )?;
+        self.encode_footer()
     }
 }
@@ -1,6 +1,7 @@
 // sixela::pixelformat
 
 use crate::{PixelFormat, SixelError, SixelResult};
+use devela::Vec;
 
 /// TODO
 fn get_rgb(data: &[u8], pixelformat: PixelFormat, depth: usize) -> (u8, u8, u8) {
@@ -141,8 +142,8 @@ fn expand_palette(
     for _y in 0..height {
         for _x in 0..max_x {
             for i in 0..8 / bpp {
-                let shift = ((8 / bpp) - 1 - i) * (bpp & (1 << (bpp - 1)));
-                dst[dst_offset] = ((src[src_offset] as i32) >> shift) as u8;
+                let shift = ((8 / bpp) - 1 - i) * bpp;
+                dst[dst_offset] = (src[src_offset] >> shift) & ((1 << bpp) - 1);
                 dst_offset += 1;
             }
             src_offset += 1;
@@ -151,7 +152,7 @@ fn expand_palette(
         let x = width - max_x * 8 / bpp;
         if x > 0 {
             for i in 0..x {
-                dst[dst_offset] = src[src_offset] >> ((8 - (i + 1) * bpp) & ((1 << bpp) - 1));
+                dst[dst_offset] = (src[src_offset] >> (8 - (i + 1) * bpp)) & ((1 << bpp) - 1);
                 dst_offset += 1;
             }
             src_offset += 1;
@@ -212,3 +213,32 @@ pub(crate) fn sixel_helper_normalize_pixelformat(
         }
     }
 }
+
+/// Returns the alpha value of each pixel, or `None` if the pixel format has no alpha channel.
+pub(crate) fn sixel_helper_get_alpha(
+    src: &[u8],
+    pixelformat: PixelFormat,
+    width: i32,
+    height: i32,
+) -> Option<Vec<u8>> {
+    let (depth, offset) = match pixelformat {
+        PixelFormat::ARGB8888 | PixelFormat::ABGR8888 => (4, 0),
+        PixelFormat::RGBA8888 | PixelFormat::BGRA8888 => (4, 3),
+        PixelFormat::AG88 => (2, 0),
+        PixelFormat::GA88 => (2, 1),
+        _ => return None,
+    };
+    let len = (width * height) as usize;
+    Some(src.chunks_exact(depth).take(len).map(|pixel| pixel[offset]).collect())
+}
+
+/// Blends the RGB888 pixels in `dst` with an opaque `background` color,
+/// using the given `alpha` value of each pixel.
+pub(crate) fn sixel_helper_blend_alpha(dst: &mut [u8], alpha: &[u8], background: [u8; 3]) {
+    for (pixel, &a) in dst.chunks_exact_mut(3).zip(alpha) {
+        for (c, bg) in pixel.iter_mut().zip(background) {
+            let (a, c0, bg) = (a as u32, *c as u32, bg as u32);
+            *c = ((c0 * a + bg * (255 - a) + 127) / 255) as u8;
+        }
+    }
+}
@@ -1,6 +1,93 @@
 // sixela::quant::diffuse_fns
+//
+// TOC
+// - struct DiffusionKernel
+// - fn diffuse_kernel
+// - fn diffuse_fs
+// - fn diffuse_atkinson
+// - fn diffuse_jajuni
+// - fn diffuse_stucki
+// - fn diffuse_burkes
 
 use super::error_diffuse;
+use crate::Diffusion;
+
+/// The weights of an error diffusion method.
+#[derive(Clone, Copy, Debug)]
+pub(crate) struct DiffusionKernel {
+    /// The (x, y) offset of each neighbor pixel, and its share of the error.
+    pub(crate) weights: &'static [(i32, i32, i32)],
+    /// The sum of all the shares.
+    pub(crate) divisor: i32,
+}
+
+#[rustfmt::skip]
+impl DiffusionKernel {
+    /// Returns the kernel of an error diffusion method,
+    /// or `None` if it doesn't diffuse the error to the neighbor pixels.
+    #[must_use]
+    pub(crate) const fn new(method: Diffusion) -> Option<Self> {
+        let (weights, divisor): (&[_], _) = match method {
+            Diffusion::FS => (&[(1, 0, 7), (-1, 1, 3), (0, 1, 5), (1, 1, 1)], 16),
+            Diffusion::Atkinson => (&[
+                (1, 0, 1), (2, 0, 1),
+                (-1, 1, 1), (0, 1, 1), (1, 1, 1),
+                (0, 2, 1)], 8),
+            Diffusion::JaJuNi => (&[
+                (1, 0, 7), (2, 0, 5),
+                (-2, 1, 3), (-1, 1, 5), (0, 1, 7), (1, 1, 5), (2, 1, 3),
+                (-2, 2, 1), (-1, 2, 3), (0, 2, 5), (1, 2, 3), (2, 2, 1)], 48),
+            Diffusion::Stucki => (&[
+                (1, 0, 8), (2, 0, 4),
+                (-2, 1, 2), (-1, 1, 4), (0, 1, 8), (1, 1, 4), (2, 1, 2),
+                (-2, 2, 1), (-1, 2, 2), (0, 2, 4), (1, 2, 2), (2, 2, 1)], 42),
+            Diffusion::Burkes => (&[
+                (1, 0, 8), (2, 0, 4),
+                (-2, 1, 2), (-1, 1, 4), (0, 1, 8), (1, 1, 4), (2, 1, 2)], 32),
+            Diffusion::Sierra3 => (&[
+                (1, 0, 5), (2, 0, 3),
+                (-2, 1, 2), (-1, 1, 4), (0, 1, 5), (1, 1, 4), (2, 1, 2),
+                (-1, 2, 2), (0, 2, 3), (1, 2, 2)], 32),
+            Diffusion::Sierra2 => (&[
+                (1, 0, 4), (2, 0, 3),
+                (-2, 1, 1), (-1, 1, 2), (0, 1, 3), (1, 1, 2), (2, 1, 1)], 16),
+            Diffusion::SierraLite => (&[(1, 0, 2), (-1, 1, 1), (0, 1, 1)], 4),
+            Diffusion::StevensonArce => (&[
+                (2, 0, 32),
+                (-3, 1, 12), (-1, 1, 26), (1, 1, 30), (3, 1, 16),
+                (-2, 2, 12), (0, 2, 26), (2, 2, 12),
+                (-3, 3, 5), (-1, 3, 12), (1, 3, 12), (3, 3, 5)], 200),
+            Diffusion::Auto | Diffusion::None | Diffusion::ADither | Diffusion::XDither
+                | Diffusion::Bayer2 | Diffusion::Bayer4 | Diffusion::Bayer8
+                | Diffusion::BlueNoise => return None,
+        };
+        Some(Self { weights, divisor })
+    }
+}
+
+/// Diffuses the error to the neighbor pixels inside the image, following the `kernel`.
+///
+/// The kernel is mirrored horizontally when scanning the row in `reverse`.
+#[expect(clippy::too_many_arguments)]
+pub(super) fn diffuse_kernel(
+    data: &mut [u8],
+    width: i32,
+    height: i32,
+    x: i32,
+    y: i32,
+    depth: i32,
+    error: i32,
+    kernel: DiffusionKernel,
+    reverse: bool,
+) {
+    for &(dx, dy, share) in kernel.weights {
+        let dx = if reverse { -dx } else { dx };
+        if x + dx < 0 || x + dx >= width || y + dy >= height {
+            continue;
+        }
+        error_diffuse(data, (y + dy) * width + x + dx, depth, error, share, kernel.divisor);
+    }
+}
 
 /// Floyd Steinberg diffuse
 ///
@@ -0,0 +1,87 @@
+// sixela::quant::kmeans
+//
+// TOC
+// - fn kmeans_refine_palette
+// - fn nearest_color
+
+use super::Tuple;
+use alloc::vec;
+use devela::{AllocMap as HashMap, Vec};
+
+/// Refines a palette with k-means (Lloyd) iterations over the histogram colors.
+///
+/// Each iteration assigns every histogram color to its nearest palette color,
+/// and then moves each palette color to the weighted average of its assigned colors.
+/// The palette colors without any assigned color are left unchanged.
+/// It stops early if the palette doesn't change.
+///
+/// Each histogram color is taken from the center of its 5-bit bucket.
+///
+/// Returns the mean squared error per color component of the histogram colors,
+/// before and after the refinement.
+pub(super) fn kmeans_refine_palette(
+    palette: &mut [u8],
+    depth: usize,
+    histogram: &HashMap<i32, Tuple>,
+    iterations: u32,
+) -> (f64, f64) {
+    let ncolors = palette.len() / depth;
+    let samples: Vec<(Vec<i32>, u64)> = histogram
+        .values()
+        .map(|t| (t.tuple.iter().map(|c| c | 4).collect(), t.value as u64))
+        .collect();
+    let total: u64 = samples.iter().map(|(_, count)| count).sum();
+    let mse = |palette: &[u8]| {
+        if total == 0 {
+            return 0.0;
+        }
+        let error: u64 = samples
+            .iter()
+            .map(|(color, count)| nearest_color(palette, depth, color).1 * count)
+            .sum();
+        error as f64 / (total * depth as u64) as f64
+    };
+    let before = mse(palette);
+
+    let mut sums = vec![0_u64; ncolors * depth];
+    let mut counts = vec![0_u64; ncolors];
+    for _ in 0..iterations {
+        sums.fill(0);
+        counts.fill(0);
+        for (color, count) in &samples {
+            let (index, _) = nearest_color(palette, depth, color);
+            counts[index] += count;
+            for (sum, &c) in sums[index * depth..].iter_mut().zip(color) {
+                *sum += c as u64 * count;
+            }
+        }
+        let mut changed = false;
+        for (index, &count) in counts.iter().enumerate().filter(|(_, &count)| count > 0) {
+            for n in 0..depth {
+                let c = ((sums[index * depth + n] + count / 2) / count) as u8;
+                changed |= palette[index * depth + n] != c;
+                palette[index * depth + n] = c;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+    (before, mse(palette))
+}
+
+/// Returns the index of the palette color nearest to `color`, and its squared distance.
+fn nearest_color(palette: &[u8], depth: usize, color: &[i32]) -> (usize, u64) {
+    let mut nearest = (0, u64::MAX);
+    for (index, entry) in palette.chunks_exact(depth).enumerate() {
+        let distance: u64 = entry
+            .iter()
+            .zip(color)
+            .map(|(&p, &c)| ((p as i32 - c) * (p as i32 - c)) as u64)
+            .sum();
+        if distance < nearest.1 {
+            nearest = (index, distance);
+        }
+    }
+    nearest
+}
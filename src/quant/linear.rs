@@ -0,0 +1,103 @@
+// sixela::quant::linear
+//
+// TOC
+// - const SRGB_TO_LINEAR
+// - fn srgb_to_linear
+// - fn linear_to_srgb
+// - fn take_diffused
+// - fn diffuse_linear
+
+use super::DiffusionKernel;
+
+/// The linear light value of each sRGB component value, from 0 to 65535.
+#[rustfmt::skip]
+const SRGB_TO_LINEAR: [u16; 256] = [
+    0, 20, 40, 60, 80, 99, 119, 139, 159, 179, 199, 219,
+    241, 264, 288, 313, 340, 367, 396, 427, 458, 491, 526, 562,
+    599, 637, 677, 718, 761, 805, 851, 898, 947, 997, 1048, 1101,
+    1156, 1212, 1270, 1330, 1391, 1453, 1517, 1583, 1651, 1720, 1790, 1863,
+    1937, 2013, 2090, 2170, 2250, 2333, 2418, 2504, 2592, 2681, 2773, 2866,
+    2961, 3058, 3157, 3258, 3360, 3464, 3570, 3678, 3788, 3900, 4014, 4129,
+    4247, 4366, 4488, 4611, 4736, 4864, 4993, 5124, 5257, 5392, 5530, 5669,
+    5810, 5953, 6099, 6246, 6395, 6547, 6700, 6856, 7014, 7174, 7335, 7500,
+    7666, 7834, 8004, 8177, 8352, 8528, 8708, 8889, 9072, 9258, 9445, 9635,
+    9828, 10_022, 10_219, 10_417, 10_619, 10_822, 11_028, 11_235, 11_446, 11_658, 11_873, 12_090,
+    12_309, 12_530, 12_754, 12_980, 13_209, 13_440, 13_673, 13_909, 14_146, 14_387, 14_629, 14_874,
+    15_122, 15_371, 15_623, 15_878, 16_135, 16_394, 16_656, 16_920, 17_187, 17_456, 17_727, 18_001,
+    18_277, 18_556, 18_837, 19_121, 19_407, 19_696, 19_987, 20_281, 20_577, 20_876, 21_177, 21_481,
+    21_787, 22_096, 22_407, 22_721, 23_038, 23_357, 23_678, 24_002, 24_329, 24_658, 24_990, 25_325,
+    25_662, 26_001, 26_344, 26_688, 27_036, 27_386, 27_739, 28_094, 28_452, 28_813, 29_176, 29_542,
+    29_911, 30_282, 30_656, 31_033, 31_412, 31_794, 32_179, 32_567, 32_957, 33_350, 33_745, 34_143,
+    34_544, 34_948, 35_355, 35_764, 36_176, 36_591, 37_008, 37_429, 37_852, 38_278, 38_706, 39_138,
+    39_572, 40_009, 40_449, 40_891, 41_337, 41_785, 42_236, 42_690, 43_147, 43_606, 44_069, 44_534,
+    45_002, 45_473, 45_947, 46_423, 46_903, 47_385, 47_871, 48_359, 48_850, 49_344, 49_841, 50_341,
+    50_844, 51_349, 51_858, 52_369, 52_884, 53_401, 53_921, 54_445, 54_971, 55_500, 56_032, 56_567,
+    57_105, 57_646, 58_190, 58_737, 59_287, 59_840, 60_396, 60_955, 61_517, 62_082, 62_650, 63_221,
+    63_795, 64_372, 64_952, 65_535,
+];
+
+/// Returns the linear light value of an sRGB component value, from 0 to 65535.
+#[inline]
+#[must_use]
+pub(crate) const fn srgb_to_linear(value: u8) -> i32 {
+    SRGB_TO_LINEAR[value as usize] as i32
+}
+
+/// Returns the sRGB component value nearest to a linear light value, clamped to 0..=65535.
+#[must_use]
+pub(crate) fn linear_to_srgb(value: i32) -> u8 {
+    let value = value.clamp(0, u16::MAX as i32);
+    let upper = SRGB_TO_LINEAR.partition_point(|&l| (l as i32) < value);
+    if upper == 0 {
+        return 0;
+    }
+    let lower = upper - 1;
+    if value - srgb_to_linear(lower as u8) <= srgb_to_linear(upper as u8) - value {
+        lower as u8
+    } else {
+        upper as u8
+    }
+}
+
+/// Adds the error diffused to the RGB pixel at the start of `data`,
+/// from the start of `diffused`, which is reset.
+///
+/// Returns the resulting linear light values, which may exceed the valid range,
+/// while the pixel is clamped to it.
+pub(crate) fn take_diffused(data: &mut [u8], diffused: &mut [i32]) -> [i32; 3] {
+    core::array::from_fn(|c| {
+        let value = srgb_to_linear(data[c]) + core::mem::take(&mut diffused[c]);
+        data[c] = linear_to_srgb(value);
+        value
+    })
+}
+
+/// Diffuses the linear light error of the RGB pixel at (`x`, `y`), to its neighbor pixels
+/// inside the image, accumulating it in `diffused`, where the pixel is at index `pixel`.
+///
+/// The kernel is mirrored horizontally when scanning the row in `reverse`.
+///
+/// The accumulated error is not clamped, so that the average brightness is preserved.
+#[expect(clippy::too_many_arguments)]
+pub(crate) fn diffuse_linear(
+    diffused: &mut [i32],
+    pixel: usize,
+    width: i32,
+    height: i32,
+    x: i32,
+    y: i32,
+    errors: [i32; 3],
+    kernel: DiffusionKernel,
+    reverse: bool,
+) {
+    for &(dx, dy, share) in kernel.weights {
+        let dx = if reverse { -dx } else { dx };
+        if x + dx < 0 || x + dx >= width || y + dy >= height {
+            continue;
+        }
+        let offset = (pixel as i32 + (dy * width + dx) * 3) as usize;
+        for (c, error) in errors.iter().enumerate() {
+            diffused[offset + c] += error * share / kernel.divisor;
+        }
+    }
+}
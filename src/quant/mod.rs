@@ -1,14 +1,24 @@
 // sixela::quant
 
 mod diffuse_fns;
-use diffuse_fns::*;
+mod kmeans;
+mod linear;
+mod octree;
+mod ordered;
+mod space;
+mod wu;
+pub(crate) use {diffuse_fns::DiffusionKernel, linear::*, ordered::*};
+use {diffuse_fns::*, kmeans::*, octree::*, space::*, wu::*};
 
 use crate::{
-    pixelformat::sixel_helper_compute_depth, Diffusion, LargestDim, PixelFormat, Quality, RepColor,
-    SixelError, SixelResult,
+    pixelformat::sixel_helper_compute_depth, ColorSpace, Diffusion, LargestDim, PixelFormat,
+    Quality, Quantizer, RepColor, SixelError, SixelResult,
 };
 use alloc::vec;
-use devela::{AllocMap as HashMap, Ordering, Vec};
+use devela::{AllocMap as HashMap, ExtFloat, Ordering, Vec};
+
+/// The length of the color lookup cache tables, indexed by the 15-bit hash of the colors.
+pub(crate) const CACHE_TABLE_SIZE: usize = 1 << (3 * 5);
 
 /// TODO
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -60,33 +70,28 @@ fn new_box_vector(colors: i32, sum: i32, newcolors: i32) -> Vec<BBox> {
 }
 
 /// Go through the box finding the minimum and maximum of each
-/// component - the boundaries of the box.
+/// component - the boundaries of the box, in the coordinates of `points`.
 fn find_box_boundaries(
-    colorfreqtable: &mut HashMap<i32, Tuple>,
-    depth: i32,
+    points: &[[f64; 3]],
     box_start: i32,
     box_size: i32,
-    minval: &mut [i32],
-    maxval: &mut [i32],
+    minval: &mut [f64; 3],
+    maxval: &mut [f64; 3],
 ) {
-    for plane in 0..depth {
-        minval[plane as usize] = colorfreqtable.get(&(box_start)).unwrap().tuple[plane as usize];
-        maxval[plane as usize] = minval[plane as usize];
-    }
-    for i in 1..box_size {
-        for plane in 0..depth {
-            let v = colorfreqtable.get(&(box_start + i)).unwrap().tuple[plane as usize];
-            minval[plane as usize] = minval[plane as usize].min(v);
-            maxval[plane as usize] = maxval[plane as usize].max(v);
+    *minval = points[box_start as usize];
+    *maxval = *minval;
+    for point in &points[box_start as usize + 1..(box_start + box_size) as usize] {
+        for plane in 0..3 {
+            minval[plane] = minval[plane].min(point[plane]);
+            maxval[plane] = maxval[plane].max(point[plane]);
         }
     }
 }
 
-/// TODO
+/// Returns the component with the largest spread.
 #[must_use]
-#[expect(unused, reason = "WIP")]
-fn largest_by_norm(minval: &[i32], maxval: &[i32], depth: i32) -> i32 {
-    let mut largest_spread_so_far = 0;
+fn largest_by_norm(minval: &[f64], maxval: &[f64], depth: i32) -> i32 {
+    let mut largest_spread_so_far = 0.0;
     let mut largest_dimension = 0;
     for plane in 0..depth as usize {
         let spread = maxval[plane] - minval[plane];
@@ -102,8 +107,7 @@ fn largest_by_norm(minval: &[i32], maxval: &[i32], depth: i32) -> i32 {
 /// BLACKANDWHITE, GRAYSCALE, or RGB (which implies pamP->depth is 1 or 3).
 /// To save time, we don't actually check it.
 #[must_use]
-#[expect(unused, reason = "WIP")]
-fn largest_by_luminosity(minval: &[i32], maxval: &[i32], depth: i32) -> i32 {
+fn largest_by_luminosity(minval: &[f64], maxval: &[f64], depth: i32) -> i32 {
     let retval;
     let lumin_factor = [0.2989, 0.5866, 0.1145];
 
@@ -115,7 +119,7 @@ fn largest_by_luminosity(minval: &[i32], maxval: &[i32], depth: i32) -> i32 {
         let mut largest_dimension = 0;
 
         for plane in 0..3 {
-            let spread = lumin_factor[plane] * (maxval[plane] - minval[plane]) as f32;
+            let spread = lumin_factor[plane] * (maxval[plane] - minval[plane]);
             if spread > largest_spread_so_far {
                 largest_dimension = plane;
                 largest_spread_so_far = spread;
@@ -255,46 +259,39 @@ fn split_box(
     boxes: &mut i32,
     bi: usize,
     colorfreqtable: &mut HashMap<i32, Tuple>,
-    depth: i32,
-    _largest: LargestDim,
+    points: &mut [[f64; 3]],
+    space: ColorSpace,
+    largest: LargestDim,
 ) -> SixelResult<()> {
     let box_start = bv[bi].ind;
     let box_size = bv[bi].colors;
     let sm = bv[bi].sum;
 
-    let max_depth = 16;
-    let mut minval = vec![0; max_depth];
-    let mut maxval = vec![0; max_depth];
-
-    /* assert(max_depth >= depth); */
-
-    find_box_boundaries(colorfreqtable, depth, box_start, box_size, &mut minval, &mut maxval);
+    let mut minval = [0.0; 3];
+    let mut maxval = [0.0; 3];
+    find_box_boundaries(points, box_start, box_size, &mut minval, &mut maxval);
 
     /* Find the largest dimension, and sort by that component.  I have
        included two methods for determining the "largest" dimension;
        first by simply comparing the range in RGB space, and second by
        transforming into luminosities before the comparison.
+       The perceptual color spaces already have a lightness component.
     */
-    // let _largest_dimension = match _largest {
-    //     LargestDim::Auto | LargestDim::Norm => largest_by_norm(&minval, &maxval, depth),
-    //     LargestDim::Lum => largest_by_luminosity(&minval, &maxval, depth),
-    // };
-
-    /* TODO: I think this sort should go after creating a box,
-       not before splitting.  Because you need the sort to use
-       the SIXEL_REP_CENTER_BOX method of choosing a color to
-       represent the final boxes
-    */
-
-    /* Set the gross global variable 'compareplanePlane' as a
-       parameter to compareplane(), which is called by qsort().
-    */
-
-    /* Sholdn't be needed - I use a stupid hasmap - should be refactored.
-    compareplanePlane = largest_dimension;
-    qsort((char*) &colorfreqtable.table[box_start], box_size,
-          sizeof(colorfreqtable.table[box_start]),
-          compareplane);*/
+    let largest_dimension = match largest {
+        LargestDim::Lum if matches!(space, ColorSpace::Rgb | ColorSpace::LinearRgb) => {
+            largest_by_luminosity(&minval, &maxval, 3)
+        }
+        _ => largest_by_norm(&minval, &maxval, 3),
+    } as usize;
+
+    let mut entries: Vec<(Tuple, [f64; 3])> = (box_start..box_start + box_size)
+        .map(|i| (colorfreqtable.remove(&i).unwrap(), points[i as usize]))
+        .collect();
+    entries.sort_by(|(_, a), (_, b)| a[largest_dimension].total_cmp(&b[largest_dimension]));
+    for (i, (tuple, point)) in (box_start..).zip(entries) {
+        colorfreqtable.insert(i, tuple);
+        points[i as usize] = point;
+    }
 
     /* Now find the median based on the counts, so that about half
     the pixels (not colors, pixels) are in each subdivision.  */
@@ -329,6 +326,7 @@ fn mediancut(
     colorfreqtable: &mut HashMap<i32, Tuple>,
     depth: i32,
     newcolors: i32,
+    space: ColorSpace,
     largest: LargestDim,
     rep: RepColor,
     colormap: &mut HashMap<i32, Tuple>,
@@ -345,6 +343,15 @@ fn mediancut(
     let mut boxes = 1;
     let mut multi_color_boxes_exist = colorfreqtable.len() > 1;
 
+    // the coordinates of each color in the color space, in the same order
+    let map = SpaceMap::new(space);
+    let mut points: Vec<[f64; 3]> = (0..colorfreqtable.len() as i32)
+        .map(|i| {
+            let tuple = &colorfreqtable.get(&i).unwrap().tuple;
+            map.convert(&[tuple[0] as u8, tuple[1] as u8, tuple[2] as u8])
+        })
+        .collect();
+
     // Main loop: split boxes until we have enough.
     while boxes < newcolors && multi_color_boxes_exist {
         // Find the first splittable box.
@@ -356,7 +363,15 @@ fn mediancut(
         if bi >= boxes {
             multi_color_boxes_exist = false;
         } else {
-            split_box(&mut bv, &mut boxes, bi as usize, colorfreqtable, depth, largest)?;
+            split_box(
+                &mut bv,
+                &mut boxes,
+                bi as usize,
+                colorfreqtable,
+                &mut points,
+                space,
+                largest,
+            )?;
         }
     }
     *colormap = color_map_from_bv(newcolors, &bv, boxes, colorfreqtable, depth, rep);
@@ -380,13 +395,8 @@ struct Tuple {
     pub tuple: Vec<i32>,
 }
 
-/// TODO
-fn compute_histogram(
-    data: &[u8],
-    length: i32,
-    depth: i32,
-    quality: Quality,
-) -> SixelResult<HashMap<i32, Tuple>> {
+/// Returns the distance in bytes between the sampled pixels, for the given quality.
+fn sample_step(length: i32, depth: i32, quality: Quality) -> i32 {
     let (max_sample, mut step) = match quality {
         Quality::Low => (18_383, length / depth / 18_383 * depth),
         Quality::High => (18_383, length / depth / 18_383 * depth),
@@ -402,6 +412,17 @@ fn compute_histogram(
     if step <= 0 {
         step = depth;
     }
+    step
+}
+
+/// TODO
+fn compute_histogram(
+    data: &[u8],
+    length: i32,
+    depth: i32,
+    quality: Quality,
+) -> SixelResult<HashMap<i32, Tuple>> {
+    let step = sample_step(length, depth, quality);
 
     let mut histogram = vec![0; 1 << (depth * 5)];
 
@@ -461,6 +482,7 @@ fn compute_color_map_from_input(
     length: i32,
     depth: i32,
     req_colors: i32,
+    space: ColorSpace,
     largest: LargestDim,
     rep: RepColor,
     quality: Quality,
@@ -484,7 +506,7 @@ fn compute_color_map_from_input(
             colormap.insert(i, colorfreqtable.get(&i).unwrap().clone());
         }
     } else {
-        mediancut(&mut colorfreqtable, depth, req_colors, largest, rep, colormap)?;
+        mediancut(&mut colorfreqtable, depth, req_colors, space, largest, rep, colormap)?;
     }
     Ok(())
 }
@@ -639,7 +661,9 @@ fn lookup_mono_lightbg(
     i32::from(distant < 128 * reqcolor)
 }
 
-/// Choose colors using median-cut method.
+/// Choose colors using the given quantizer method.
+///
+/// The color `space` is used for splitting the boxes of the median cut quantizer.
 #[expect(clippy::too_many_arguments)]
 pub(crate) fn sixel_quant_make_palette(
     data: &[u8],
@@ -648,13 +672,26 @@ pub(crate) fn sixel_quant_make_palette(
     req_colors: i32,
     ncolors: &mut i32,
     origcolors: &mut i32,
+    space: ColorSpace,
     largest: LargestDim,
     rep: RepColor,
     quality: Quality,
+    quantizer: Quantizer,
 ) -> SixelResult<Vec<u8>> {
     let result_depth = sixel_helper_compute_depth(pixelformat);
     // if (result_depth <= 0) { *result = NULL; goto end; }
 
+    if matches!(quantizer, Quantizer::Octree | Quantizer::Wu) {
+        let step = sample_step(length, result_depth, quality);
+        let result = if matches!(quantizer, Quantizer::Octree) {
+            octree_make_palette(data, length, result_depth, step, req_colors, origcolors)
+        } else {
+            wu_make_palette(data, length, result_depth, step, req_colors, origcolors)
+        };
+        *ncolors = (result.len() / result_depth as usize) as i32;
+        return Ok(result);
+    }
+
     let depth = result_depth as usize;
     let mut colormap = HashMap::new();
     let _ = compute_color_map_from_input(
@@ -662,6 +699,7 @@ pub(crate) fn sixel_quant_make_palette(
         length,
         depth as i32,
         req_colors,
+        space,
         largest,
         rep,
         quality,
@@ -669,7 +707,7 @@ pub(crate) fn sixel_quant_make_palette(
         origcolors,
     );
     *ncolors = colormap.len() as i32;
-    let mut result = vec![0; colormap.len() * depth as usize];
+    let mut result = vec![0; colormap.len() * depth];
     for i in 0..colormap.len() {
         for n in 0..depth {
             result[i * depth + n] = colormap.get(&(i as i32)).unwrap().tuple[n] as u8;
@@ -678,11 +716,38 @@ pub(crate) fn sixel_quant_make_palette(
     Ok(result)
 }
 
+/// Refines the palette with up to `iterations` k-means iterations
+/// over the histogram of the given image.
+///
+/// Returns the mean squared error per color component of the histogram colors,
+/// before and after the refinement.
+pub(crate) fn sixel_quant_refine_palette(
+    palette: &mut [u8],
+    data: &[u8],
+    length: i32,
+    pixelformat: PixelFormat,
+    quality: Quality,
+    iterations: u32,
+) -> SixelResult<(f64, f64)> {
+    let depth = sixel_helper_compute_depth(pixelformat);
+    let histogram = compute_histogram(data, length, depth, quality)?;
+    Ok(kmeans_refine_palette(palette, depth as usize, &histogram, iterations))
+}
+
 /// Apply color palette into specified pixel buffers
+///
+/// The nearest palette colors are looked up in the color `space`.
+/// The `complexion` correction only applies to [`ColorSpace::Rgb`].
+///
+/// If `linear` is true, the error is diffused in linear light.
+///
+/// The `transparent` pixels are skipped, so they neither take a palette color
+/// nor diffuse their error into their neighbors, and their `result` is left unchanged.
 #[expect(clippy::too_many_arguments)]
 pub(crate) fn sixel_quant_apply_palette(
     result: &mut [u8],
     data: &mut [u8],
+    transparent: Option<&[bool]>,
     width: i32,
     height: i32,
     depth: i32,
@@ -692,6 +757,10 @@ pub(crate) fn sixel_quant_apply_palette(
     foptimize: bool,
     foptimize_palette: bool,
     complexion: i32,
+    space: ColorSpace,
+    linear: bool,
+    serpentine: bool,
+    strength: i32,
     cachetable: Option<&mut [u16]>,
 ) -> SixelResult<i32> {
     let mut ncolors: i32;
@@ -723,8 +792,53 @@ pub(crate) fn sixel_quant_apply_palette(
                 f_mask = true;
                 diffuse_none
             }
+            Diffusion::Bayer2 | Diffusion::Bayer4 | Diffusion::Bayer8 | Diffusion::BlueNoise => {
+                f_mask = true;
+                diffuse_none
+            }
+            Diffusion::Sierra3
+            | Diffusion::Sierra2
+            | Diffusion::SierraLite
+            | Diffusion::StevensonArce => diffuse_none,
+        }
+    };
+    // the kernel of the methods without their own function, or of any method in serpentine
+    let kernel_only = matches!(
+        diffuse,
+        Diffusion::Sierra3 | Diffusion::Sierra2 | Diffusion::SierraLite | Diffusion::StevensonArce
+    );
+    let table_kernel = if depth == 3 && (serpentine || kernel_only) {
+        DiffusionKernel::new(diffuse)
+    } else {
+        None
+    };
+    // diffuse only the `strength` percentage of the error
+    let diffuse_error = |data: &mut [u8], x: i32, y: i32, error: i32, reverse: bool| {
+        let error = error * strength / 100;
+        if let Some(kernel) = table_kernel {
+            diffuse_kernel(data, width, height, x, y, depth, error, kernel, reverse);
+        } else {
+            f_diffuse(data, width, height, x, y, depth, error);
         }
     };
+    // the amplitude of the ordered dithers, from the spacing of an evenly distributed palette
+    let ordered_spread = 255.0 / ExtFloat::cbrt(reqcolor as f64) as f32 / 2.0;
+    let mask = |x: i32, y: i32, d: i32| match diffuse {
+        Diffusion::ADither => (mask_a(x, y, d) * 32.0) as i32,
+        Diffusion::XDither => (mask_x(x, y, d) * 32.0) as i32,
+        _ => (ordered_threshold(diffuse, x, y).unwrap_or(0.0) * ordered_spread) as i32,
+    };
+    let linear_kernel = if linear && depth == 3 {
+        DiffusionKernel::new(diffuse)
+    } else {
+        None
+    };
+    // the error diffused in linear light to each pixel component
+    let mut diffused = if linear_kernel.is_some() {
+        vec![0; (width * height * 3) as usize]
+    } else {
+        Vec::new()
+    };
     type LookupFunc = fn(&[u8], i32, &[u8], i32, &mut [u16], i32) -> i32;
     let mut f_lookup: Option<LookupFunc> = None;
     if reqcolor == 2 {
@@ -742,6 +856,16 @@ pub(crate) fn sixel_quant_apply_palette(
             f_lookup = Some(lookup_mono_lightbg);
         }
     }
+    // look up the nearest colors in the color space, except for the monochrome palettes
+    let space_lookup = (f_lookup.is_none() && depth == 3 && !matches!(space, ColorSpace::Rgb))
+        .then(|| {
+            let map = SpaceMap::new(space);
+            let points: Vec<[f64; 3]> = palette[..(reqcolor * depth) as usize]
+                .chunks_exact(3)
+                .map(|color| map.convert(color))
+                .collect();
+            (map, points)
+        });
     if f_lookup.is_none() {
         if foptimize && depth == 3 {
             f_lookup = Some(lookup_fast);
@@ -750,11 +874,17 @@ pub(crate) fn sixel_quant_apply_palette(
         }
     }
 
-    let mut cc = vec![0u16, 1 << (depth * 5)];
+    let mut cc = vec![0u16; CACHE_TABLE_SIZE];
     let indextable = match cachetable {
         Some(table) => table,
         None => &mut cc,
     };
+    let f_lookup = f_lookup.unwrap();
+    let skipped = |pos: i32| transparent.is_some_and(|mask| mask[pos as usize]);
+    let lookup = |pixel: &[u8], indextable: &mut [u16]| match &space_lookup {
+        Some((map, points)) => lookup_space(pixel, map, points, foptimize.then_some(indextable)),
+        None => f_lookup(pixel, depth, palette, reqcolor, indextable, complexion),
+    };
 
     if foptimize_palette {
         ncolors = 0;
@@ -767,19 +897,16 @@ pub(crate) fn sixel_quant_apply_palette(
                     let mut copy: Vec<u8> = Vec::new();
 
                     let pos = y * width + x;
+                    if skipped(pos) {
+                        continue;
+                    }
                     for d in 0..depth {
                         let mut val = data[(pos * depth + d) as usize] as i32;
-                        if matches!(diffuse, Diffusion::ADither) {
-                            val += (mask_a(x, y, d) * 32.0) as i32;
-                        } else {
-                            val += (mask_x(x, y, d) * 32.0) as i32;
-                        }
+                        val += mask(x, y, d);
                         copy.push(val.clamp(0, 255) as u8);
                     }
                     //                    &[u8],  i32,   &[u8],    i32,    &mut [u16],    i32
-                    let color_index =
-                        f_lookup.unwrap()(&copy, depth, palette, reqcolor, indextable, complexion)
-                            as usize;
+                    let color_index = lookup(&copy, indextable) as usize;
                     if migration_map[color_index] == 0 {
                         result[pos as usize] = ncolors as u8;
                         for n in 0..depth {
@@ -789,23 +916,26 @@ pub(crate) fn sixel_quant_apply_palette(
                         ncolors += 1;
                         migration_map[color_index] = ncolors;
                     } else {
-                        result[pos as usize] = migration_map[color_index] as u8 - 1;
+                        result[pos as usize] = (migration_map[color_index] - 1) as u8;
                     }
                 }
             }
             *palette = new_palette;
         } else {
             for y in 0..height {
-                for x in 0..width {
+                // scan the odd rows from right to left in serpentine
+                let reverse = serpentine && y % 2 == 1;
+                for i in 0..width {
+                    let x = if reverse { width - 1 - i } else { i };
                     let pos = y * width + x;
-                    let color_index = f_lookup.unwrap()(
-                        &data[(pos * depth) as usize..],
-                        depth,
-                        palette,
-                        reqcolor,
-                        indextable,
-                        complexion,
-                    ) as usize;
+                    if skipped(pos) {
+                        continue;
+                    }
+                    let values = linear_kernel.map(|_| {
+                        let pixel = (pos * 3) as usize;
+                        take_diffused(&mut data[pixel..], &mut diffused[pixel..])
+                    });
+                    let color_index = lookup(&data[(pos * depth) as usize..], indextable) as usize;
                     if migration_map[color_index] == 0 {
                         result[pos as usize] = ncolors as u8;
                         for n in 0..depth {
@@ -815,12 +945,30 @@ pub(crate) fn sixel_quant_apply_palette(
                         ncolors += 1;
                         migration_map[color_index] = ncolors;
                     } else {
-                        result[pos as usize] = migration_map[color_index] as u8 - 1;
+                        result[pos as usize] = (migration_map[color_index] - 1) as u8;
                     }
-                    for n in 0..depth {
-                        let offset = data[(pos * depth + n) as usize] as i32
-                            - palette[color_index * depth as usize + n as usize] as i32;
-                        f_diffuse(&mut data[n as usize..], width, height, x, y, depth, offset);
+                    if let (Some(kernel), Some(values)) = (linear_kernel, values) {
+                        let (pixel, color) = ((pos * 3) as usize, color_index * 3);
+                        let errors = core::array::from_fn(|n| {
+                            (values[n] - srgb_to_linear(palette[color + n])) * strength / 100
+                        });
+                        diffuse_linear(
+                            &mut diffused,
+                            pixel,
+                            width,
+                            height,
+                            x,
+                            y,
+                            errors,
+                            kernel,
+                            reverse,
+                        );
+                    } else {
+                        for n in 0..depth {
+                            let offset = data[(pos * depth + n) as usize] as i32
+                                - palette[color_index * depth as usize + n as usize] as i32;
+                            diffuse_error(&mut data[n as usize..], x, y, offset, reverse);
+                        }
                     }
                 }
             }
@@ -832,38 +980,55 @@ pub(crate) fn sixel_quant_apply_palette(
                 for x in 0..width {
                     let mut copy = Vec::new();
                     let pos = y * width + x;
+                    if skipped(pos) {
+                        continue;
+                    }
                     for d in 0..depth {
                         let mut val = data[(pos * depth + d) as usize] as i32;
-                        if matches!(diffuse, Diffusion::ADither) {
-                            val += (mask_a(x, y, d) * 32.0) as i32;
-                        } else {
-                            val += (mask_x(x, y, d) * 32.0) as i32;
-                        }
-
+                        val += mask(x, y, d);
                         copy.push(val.clamp(0, 255) as u8);
                     }
-                    result[pos as usize] = f_lookup.unwrap()(
-                        &mut copy, depth, palette, reqcolor, indextable, complexion,
-                    ) as u8;
+                    result[pos as usize] = lookup(&copy, indextable) as u8;
                 }
             }
         } else {
             for y in 0..height {
-                for x in 0..width {
+                // scan the odd rows from right to left in serpentine
+                let reverse = serpentine && y % 2 == 1;
+                for i in 0..width {
+                    let x = if reverse { width - 1 - i } else { i };
                     let pos = y * width + x;
-                    let color_index = f_lookup.unwrap()(
-                        &mut data[(pos * depth) as usize..],
-                        depth,
-                        palette,
-                        reqcolor,
-                        indextable,
-                        complexion,
-                    ) as usize;
+                    if skipped(pos) {
+                        continue;
+                    }
+                    let values = linear_kernel.map(|_| {
+                        let pixel = (pos * 3) as usize;
+                        take_diffused(&mut data[pixel..], &mut diffused[pixel..])
+                    });
+                    let color_index = lookup(&data[(pos * depth) as usize..], indextable) as usize;
                     result[pos as usize] = color_index as u8;
-                    for n in 0..depth {
-                        let offset = data[(pos * depth + n) as usize] as i32
-                            - palette[color_index * depth as usize + n as usize] as i32;
-                        f_diffuse(&mut data[n as usize..], width, height, x, y, depth, offset);
+                    if let (Some(kernel), Some(values)) = (linear_kernel, values) {
+                        let (pixel, color) = ((pos * 3) as usize, color_index * 3);
+                        let errors = core::array::from_fn(|n| {
+                            (values[n] - srgb_to_linear(palette[color + n])) * strength / 100
+                        });
+                        diffuse_linear(
+                            &mut diffused,
+                            pixel,
+                            width,
+                            height,
+                            x,
+                            y,
+                            errors,
+                            kernel,
+                            reverse,
+                        );
+                    } else {
+                        for n in 0..depth {
+                            let offset = data[(pos * depth + n) as usize] as i32
+                                - palette[color_index * depth as usize + n as usize] as i32;
+                            diffuse_error(&mut data[n as usize..], x, y, offset, reverse);
+                        }
                     }
                 }
             }
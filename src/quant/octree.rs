@@ -0,0 +1,126 @@
+// sixela::quant::octree
+//
+// TOC
+// - struct OctreeNode
+// - fn octree_make_palette
+
+use crate::SIXEL_PALETTE_MAX;
+use alloc::vec;
+use devela::Vec;
+
+/// The number of levels of the tree below the root,
+/// which classifies the colors with 5 bits per channel.
+const OCTREE_DEPTH: usize = 5;
+
+/// A node of the color octree.
+#[derive(Clone, Debug, Default)]
+struct OctreeNode {
+    /// The indices of the children in the tree, or 0 if there's none.
+    children: [u32; 8],
+    /// The number of pixels in the node.
+    count: u64,
+    /// The sum of each color component of the pixels in the node.
+    sum: [u64; 3],
+}
+
+/// Choose colors using the octree method.
+///
+/// Each sampled pixel is classified down to a leaf of the tree. Then the nodes of the
+/// deepest level are merged into leaves, starting with the ones with the fewest pixels,
+/// until there are no more leaves than `req_colors`.
+/// Each leaf represents the average color of its pixels.
+///
+/// Returns the palette, and updates `origcolors`
+/// with the number of distinct colors at 5 bits per channel.
+pub(super) fn octree_make_palette(
+    data: &[u8],
+    length: i32,
+    depth: i32,
+    step: i32,
+    req_colors: i32,
+    origcolors: &mut i32,
+) -> Vec<u8> {
+    let (depth, step) = (depth as usize, step as usize);
+    let req_colors = req_colors.clamp(1, SIXEL_PALETTE_MAX as i32) as usize;
+
+    let mut nodes = vec![OctreeNode::default()];
+    // the inner nodes of each level, starting with the root
+    let mut levels: [Vec<u32>; OCTREE_DEPTH] = Default::default();
+    levels[0].push(0);
+    let mut leaves = 0;
+
+    let mut i = 0;
+    while i + depth <= length as usize {
+        let rgb = [data[i], data[i + 1], data[i + 2]];
+        let mut node = 0;
+        for level in 0..OCTREE_DEPTH {
+            let shift = 7 - level;
+            let octant =
+                ((rgb[0] >> shift & 1) << 2 | (rgb[1] >> shift & 1) << 1) | (rgb[2] >> shift & 1);
+            let mut child = nodes[node].children[octant as usize];
+            if child == 0 {
+                child = nodes.len() as u32;
+                nodes.push(OctreeNode::default());
+                nodes[node].children[octant as usize] = child;
+                if level + 1 < OCTREE_DEPTH {
+                    levels[level + 1].push(child);
+                } else {
+                    leaves += 1;
+                }
+            }
+            node = child as usize;
+        }
+        nodes[node].count += 1;
+        for (sum, c) in nodes[node].sum.iter_mut().zip(rgb) {
+            *sum += c as u64;
+        }
+        i += step;
+    }
+    *origcolors = leaves as i32;
+
+    // accumulate the pixels of the children into the inner nodes, bottom up
+    for level in levels.iter().rev() {
+        for &node in level {
+            let (mut count, mut sum) = (0, [0; 3]);
+            for &child in nodes[node as usize].children.iter().filter(|&&c| c != 0) {
+                let child = &nodes[child as usize];
+                count += child.count;
+                sum.iter_mut().zip(child.sum).for_each(|(s, c)| *s += c);
+            }
+            nodes[node as usize].count = count;
+            nodes[node as usize].sum = sum;
+        }
+    }
+
+    // merge the deepest nodes with the fewest pixels
+    'reduce: for level in levels.iter_mut().rev() {
+        level.sort_by_key(|&node| nodes[node as usize].count);
+        for &node in level.iter() {
+            if leaves <= req_colors {
+                break 'reduce;
+            }
+            let node = &mut nodes[node as usize];
+            let children = node.children.iter().filter(|&&c| c != 0).count();
+            node.children = [0; 8];
+            leaves = leaves + 1 - children;
+        }
+    }
+
+    // output the average color of each leaf
+    let mut palette = Vec::with_capacity(leaves * depth);
+    let mut stack = vec![0];
+    while let Some(node) = stack.pop() {
+        let node = &nodes[node as usize];
+        if node.children == [0; 8] {
+            if node.count > 0 {
+                palette.extend(node.sum.map(|s| ((s + node.count / 2) / node.count) as u8));
+            }
+        } else {
+            stack.extend(node.children.iter().rev().filter(|&&c| c != 0));
+        }
+    }
+    if palette.is_empty() {
+        palette.resize(depth, 0);
+    }
+    palette
+}
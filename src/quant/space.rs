@@ -0,0 +1,113 @@
+// sixela::quant::space
+//
+// TOC
+// - struct SpaceMap
+// - fn lookup_space
+
+use crate::ColorSpace;
+use devela::ExtFloat;
+
+/// Converts gamma encoded sRGB colors into the coordinates of a [`ColorSpace`].
+#[derive(Clone, Debug)]
+pub(super) struct SpaceMap {
+    space: ColorSpace,
+    /// The linear light value of each sRGB component value, from 0 to 1.
+    linear: [f64; 256],
+}
+
+impl SpaceMap {
+    /// Returns a new converter into the given color space.
+    pub(super) fn new(space: ColorSpace) -> Self {
+        let mut linear = [0.0; 256];
+        if !matches!(space, ColorSpace::Rgb) {
+            for (c, l) in linear.iter_mut().enumerate() {
+                let c = c as f64 / 255.0;
+                *l = if c <= 0.040_45 {
+                    c / 12.92
+                } else {
+                    ExtFloat::powf((c + 0.055) / 1.055, 2.4)
+                };
+            }
+        }
+        Self { space, linear }
+    }
+
+    /// Returns the coordinates of the given sRGB color.
+    ///
+    /// The coordinates of the RGB spaces range from 0 to 255,
+    /// and the lightness of the perceptual spaces ranges from 0 to 100.
+    pub(super) fn convert(&self, rgb: &[u8]) -> [f64; 3] {
+        let lin = [
+            self.linear[rgb[0] as usize],
+            self.linear[rgb[1] as usize],
+            self.linear[rgb[2] as usize],
+        ];
+        match self.space {
+            ColorSpace::Rgb => [rgb[0] as f64, rgb[1] as f64, rgb[2] as f64],
+            ColorSpace::LinearRgb => lin.map(|c| c * 255.0),
+            ColorSpace::Oklab => {
+                let [r, g, b] = lin;
+                let l =
+                    ExtFloat::cbrt(0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b);
+                let m =
+                    ExtFloat::cbrt(0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b);
+                let s =
+                    ExtFloat::cbrt(0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b);
+                [
+                    100.0 * (0.210_454_255_3 * l + 0.793_617_785_0 * m - 0.004_072_046_8 * s),
+                    100.0 * (1.977_998_495_1 * l - 2.428_592_205_0 * m + 0.450_593_709_9 * s),
+                    100.0 * (0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766_0 * s),
+                ]
+            }
+            ColorSpace::CieLab => {
+                let [r, g, b] = lin;
+                // relative to the D65 white point
+                let x = (0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b) / 0.950_47;
+                let y = 0.212_672_9 * r + 0.715_152_2 * g + 0.072_175_0 * b;
+                let z = (0.019_333_9 * r + 0.119_192_0 * g + 0.950_304_1 * b) / 1.088_83;
+                let f = |t: f64| {
+                    const DELTA: f64 = 6.0 / 29.0;
+                    if t > DELTA * DELTA * DELTA {
+                        ExtFloat::cbrt(t)
+                    } else {
+                        t / (3.0 * DELTA * DELTA) + 4.0 / 29.0
+                    }
+                };
+                let (fx, fy, fz) = (f(x), f(y), f(z));
+                [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+            }
+        }
+    }
+}
+
+/// Lookup closest color from the palette `points`, in the color space of `map`.
+///
+/// If there's a `cachetable`, it's used for the colors with the same 15-bit hash,
+/// like `lookup_fast`.
+pub(super) fn lookup_space(
+    pixel: &[u8],
+    map: &SpaceMap,
+    points: &[[f64; 3]],
+    cachetable: Option<&mut [u16]>,
+) -> i32 {
+    let hash = super::compute_hash(pixel, 0, 3) as usize;
+    if let Some(&cache) = cachetable.as_ref().and_then(|table| table.get(hash)) {
+        if cache != 0 {
+            return cache as i32 - 1;
+        }
+    }
+    let point = map.convert(pixel);
+    let mut result = 0;
+    let mut diff = f64::MAX;
+    for (i, p) in points.iter().enumerate() {
+        let distant = (0..3).map(|n| (point[n] - p[n]) * (point[n] - p[n])).sum::<f64>();
+        if distant < diff {
+            diff = distant;
+            result = i;
+        }
+    }
+    if let Some(table) = cachetable {
+        table[hash] = (result + 1) as u16;
+    }
+    result as i32
+}
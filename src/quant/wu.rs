@@ -0,0 +1,239 @@
+// sixela::quant::wu
+//
+// TOC
+// - struct WuMoments
+// - struct WuBox
+// - fn wu_make_palette
+
+use crate::SIXEL_PALETTE_MAX;
+use alloc::vec;
+use devela::Vec;
+
+/// The number of cells per side of the moment tables:
+/// the 32 values of 5 bits per channel, plus a leading zero plane.
+const WU_SIDE: usize = 33;
+
+/// Returns the index of a cell of the moment tables.
+#[inline]
+const fn cell(r: usize, g: usize, b: usize) -> usize {
+    (r * WU_SIDE + g) * WU_SIDE + b
+}
+
+/// The cumulative moments of the color histogram.
+///
+/// Each cell holds the moments of all the colors up to it in every channel,
+/// so that the moments of any box can be computed from its 8 corners.
+struct WuMoments {
+    /// The number of pixels.
+    weight: Vec<f64>,
+    /// The sum of each color component of the pixels.
+    sum: [Vec<f64>; 3],
+    /// The sum of the squared color components of the pixels.
+    squares: Vec<f64>,
+}
+
+impl WuMoments {
+    /// Builds the moments from the pixels sampled every `step` bytes.
+    ///
+    /// Returns the moments and the number of distinct colors at 5 bits per channel.
+    fn new(data: &[u8], length: usize, depth: usize, step: usize) -> (Self, usize) {
+        let size = WU_SIDE * WU_SIDE * WU_SIDE;
+        let mut m = Self {
+            weight: vec![0.0; size],
+            sum: [vec![0.0; size], vec![0.0; size], vec![0.0; size]],
+            squares: vec![0.0; size],
+        };
+        let mut i = 0;
+        while i + depth <= length {
+            let rgb = [data[i], data[i + 1], data[i + 2]];
+            let index = cell(
+                (rgb[0] >> 3) as usize + 1,
+                (rgb[1] >> 3) as usize + 1,
+                (rgb[2] >> 3) as usize + 1,
+            );
+            m.weight[index] += 1.0;
+            for (sum, c) in m.sum.iter_mut().zip(rgb) {
+                sum[index] += c as f64;
+            }
+            m.squares[index] += rgb.iter().map(|&c| c as f64 * c as f64).sum::<f64>();
+            i += step;
+        }
+        let colors = m.weight.iter().filter(|&&w| w > 0.0).count();
+
+        // accumulate each table along each axis
+        let [sr, sg, sb] = &mut m.sum;
+        for table in [&mut m.weight, sr, sg, sb, &mut m.squares] {
+            for axis in 0..3 {
+                for r in 1..WU_SIDE {
+                    for g in 1..WU_SIDE {
+                        for b in 1..WU_SIDE {
+                            let prev = match axis {
+                                0 => cell(r - 1, g, b),
+                                1 => cell(r, g - 1, b),
+                                _ => cell(r, g, b - 1),
+                            };
+                            table[cell(r, g, b)] += table[prev];
+                        }
+                    }
+                }
+            }
+        }
+        (m, colors)
+    }
+}
+
+/// A box of colors, from its exclusive lower corner to its inclusive upper corner.
+#[derive(Clone, Copy, Debug)]
+struct WuBox {
+    lower: [usize; 3],
+    upper: [usize; 3],
+}
+
+impl WuBox {
+    /// Returns the number of cells of the box.
+    fn volume(&self) -> usize {
+        (0..3).map(|axis| self.upper[axis] - self.lower[axis]).product()
+    }
+
+    /// Returns the moment of the face of the box at position `pos` of the `axis`,
+    /// with its other corners, such that the moment of the box is
+    /// `face(upper) - face(lower)` along any axis.
+    fn face(&self, table: &[f64], axis: usize, pos: usize) -> f64 {
+        let (a, b) = ((axis + 1) % 3, (axis + 2) % 3);
+        let at = |ca: usize, cb: usize| {
+            let mut c = [0; 3];
+            c[axis] = pos;
+            c[a] = ca;
+            c[b] = cb;
+            table[cell(c[0], c[1], c[2])]
+        };
+        at(self.upper[a], self.upper[b])
+            - at(self.upper[a], self.lower[b])
+            - at(self.lower[a], self.upper[b])
+            + at(self.lower[a], self.lower[b])
+    }
+
+    /// Returns the moment of the whole box.
+    fn moment(&self, table: &[f64]) -> f64 {
+        self.face(table, 0, self.upper[0]) - self.face(table, 0, self.lower[0])
+    }
+
+    /// Returns the [weight, red, green, blue] moments of the whole box.
+    fn moments(&self, m: &WuMoments) -> [f64; 4] {
+        [
+            self.moment(&m.weight),
+            self.moment(&m.sum[0]),
+            self.moment(&m.sum[1]),
+            self.moment(&m.sum[2]),
+        ]
+    }
+
+    /// Returns the weighted variance of the colors of the box.
+    fn variance(&self, m: &WuMoments) -> f64 {
+        if self.volume() <= 1 {
+            return 0.0;
+        }
+        let [w, r, g, b] = self.moments(m);
+        if w <= 0.0 {
+            return 0.0;
+        }
+        self.moment(&m.squares) - (r * r + g * g + b * b) / w
+    }
+
+    /// Returns the best position for cutting the box along the `axis`, if any,
+    /// along with the reduction of the variance it achieves (up to a constant).
+    fn best_cut(&self, m: &WuMoments, axis: usize, whole: [f64; 4]) -> Option<(usize, f64)> {
+        let tables = [&m.weight, &m.sum[0], &m.sum[1], &m.sum[2]];
+        let base = tables.map(|t| self.face(t, axis, self.lower[axis]));
+        let mut best = None;
+        let mut max = 0.0;
+        for pos in self.lower[axis] + 1..self.upper[axis] {
+            let half: [f64; 4] =
+                core::array::from_fn(|i| self.face(tables[i], axis, pos) - base[i]);
+            let rest: [f64; 4] = core::array::from_fn(|i| whole[i] - half[i]);
+            if half[0] <= 0.0 || rest[0] <= 0.0 {
+                continue;
+            }
+            let score = (half[1] * half[1] + half[2] * half[2] + half[3] * half[3]) / half[0]
+                + (rest[1] * rest[1] + rest[2] * rest[2] + rest[3] * rest[3]) / rest[0];
+            if score > max {
+                max = score;
+                best = Some((pos, score));
+            }
+        }
+        best
+    }
+
+    /// Cuts the box in two along the axis that best reduces the variance,
+    /// returning the upper part, or `None` if it can't be cut.
+    fn cut(&mut self, m: &WuMoments) -> Option<Self> {
+        let whole = self.moments(m);
+        let (axis, (pos, _)) = (0..3)
+            .filter_map(|axis| self.best_cut(m, axis, whole).map(|cut| (axis, cut)))
+            .max_by(|(_, (_, a)), (_, (_, b))| a.total_cmp(b))?;
+        let mut upper = *self;
+        self.upper[axis] = pos;
+        upper.lower[axis] = pos;
+        Some(upper)
+    }
+}
+
+/// Choose colors using Xiaolin Wu's variance minimization method.
+///
+/// The sampled pixels are accumulated into cumulative moment tables over the
+/// 5 bits per channel color space. Then the box with the largest variance is
+/// repeatedly cut in two, at the position that minimizes the summed variance of
+/// both parts, until there are `req_colors` boxes or no box can be cut.
+/// Each box represents the average color of its pixels.
+///
+/// Returns the palette, and updates `origcolors`
+/// with the number of distinct colors at 5 bits per channel.
+pub(super) fn wu_make_palette(
+    data: &[u8],
+    length: i32,
+    depth: i32,
+    step: i32,
+    req_colors: i32,
+    origcolors: &mut i32,
+) -> Vec<u8> {
+    let (depth, step) = (depth as usize, step as usize);
+    let req_colors = req_colors.clamp(1, SIXEL_PALETTE_MAX as i32) as usize;
+    let (moments, colors) = WuMoments::new(data, length as usize, depth, step);
+    *origcolors = colors as i32;
+
+    let mut boxes = vec![WuBox { lower: [0; 3], upper: [WU_SIDE - 1; 3] }];
+    let mut variances = vec![0.0];
+    let mut next = 0;
+    while boxes.len() < req_colors {
+        if let Some(upper) = boxes[next].cut(&moments) {
+            variances[next] = boxes[next].variance(&moments);
+            variances.push(upper.variance(&moments));
+            boxes.push(upper);
+        } else {
+            variances[next] = 0.0;
+        }
+        next = (0..boxes.len()).fold(0, |max, i| {
+            if variances[i] > variances[max] {
+                i
+            } else {
+                max
+            }
+        });
+        if variances[next] <= 0.0 {
+            break;
+        }
+    }
+
+    // output the average color of each box
+    let mut palette = Vec::with_capacity(boxes.len() * depth);
+    for wu_box in &boxes {
+        let [w, r, g, b] = wu_box.moments(&moments);
+        if w > 0.0 {
+            palette.extend([r, g, b].map(|c| (c / w + 0.5) as u8));
+        }
+    }
+    if palette.is_empty() {
+        palette.resize(depth, 0);
+    }
+    palette
+}
@@ -0,0 +1,293 @@
+// sixela::resample
+//
+// TOC
+// - fn resample_pixels
+// - fn sixel_helper_scale_image
+// - fn scale_without_resampling
+// - fn scale_with_resampling
+// - fn axis_weights
+// - filter functions
+
+use crate::{
+    pixelformat::sixel_helper_normalize_pixelformat, PixelFormat, ResampleMethod, SixelError,
+    SixelResult,
+};
+use alloc::vec;
+use core::f64::consts::PI;
+use devela::{ExtFloat, Vec};
+
+/// Maximum number of pixels a resized image is allowed to have.
+const RESAMPLE_PIXELS_MAX: usize = 1 << 26;
+
+/// Resizes the `pixels` to `dstw`×`dsth`, returning the new pixels and their pixel format.
+///
+/// The formats of 8 bits per channel keep their format and alpha channel.
+/// The 15 and 16 bit color formats are converted to `RGB888`,
+/// the grayscale formats to `G8` levels scaled to 8 bits,
+/// and the indexed formats to `PAL8` indices, which are always scaled
+/// with the [`Nearest`][ResampleMethod::Nearest] method.
+///
+/// Returns [`BadArgument`][SixelError::BadArgument] if the new size is empty,
+/// or has more than [`RESAMPLE_PIXELS_MAX`] pixels.
+pub(crate) fn resample_pixels(
+    pixels: &[u8],
+    pixelformat: PixelFormat,
+    srcw: usize,
+    srch: usize,
+    dstw: usize,
+    dsth: usize,
+    method: ResampleMethod,
+) -> SixelResult<(Vec<u8>, PixelFormat)> {
+    match dstw.checked_mul(dsth) {
+        Some(size) if size > 0 && size <= RESAMPLE_PIXELS_MAX => {}
+        _ => return Err(SixelError::BadArgument),
+    }
+    if pixels.len() < pixelformat.required_bytes(srcw, srch) {
+        return Err(SixelError::BadInput);
+    }
+    let (src, format, depth, method) = match pixelformat {
+        PixelFormat::RGB888 | PixelFormat::BGR888 => (None, pixelformat, 3, method),
+        PixelFormat::ARGB8888
+        | PixelFormat::RGBA8888
+        | PixelFormat::ABGR8888
+        | PixelFormat::BGRA8888 => (None, pixelformat, 4, method),
+        PixelFormat::AG88 | PixelFormat::GA88 => (None, pixelformat, 2, method),
+        PixelFormat::G8 => (None, pixelformat, 1, method),
+        PixelFormat::PAL8 => (None, pixelformat, 1, ResampleMethod::Nearest),
+        PixelFormat::RGB555 | PixelFormat::RGB565 | PixelFormat::BGR555 | PixelFormat::BGR565 => {
+            let mut normalized = vec![0; srcw * srch * 3];
+            let format = sixel_helper_normalize_pixelformat(
+                &mut normalized,
+                pixels,
+                pixelformat,
+                srcw as i32,
+                srch as i32,
+            )?;
+            (Some(normalized), format, 3, method)
+        }
+        PixelFormat::PAL1 | PixelFormat::PAL2 | PixelFormat::PAL4 => {
+            let mut normalized = vec![0; srcw * srch];
+            sixel_helper_normalize_pixelformat(
+                &mut normalized,
+                pixels,
+                pixelformat,
+                srcw as i32,
+                srch as i32,
+            )?;
+            (Some(normalized), PixelFormat::PAL8, 1, ResampleMethod::Nearest)
+        }
+        PixelFormat::G1 | PixelFormat::G2 | PixelFormat::G4 => {
+            /* unpack the gray levels, and scale them to 8 bits */
+            let mut levels = vec![0; srcw * srch];
+            sixel_helper_normalize_pixelformat(
+                &mut levels,
+                pixels,
+                pixelformat,
+                srcw as i32,
+                srch as i32,
+            )?;
+            let max = (1 << pixelformat.bpp()) - 1;
+            for level in &mut levels {
+                *level = (*level as usize * 255 / max) as u8;
+            }
+            (Some(levels), PixelFormat::G8, 1, method)
+        }
+    };
+    let src = src.as_deref().unwrap_or(pixels);
+    let mut dst = vec![0; dstw * dsth * depth];
+    sixel_helper_scale_image(&mut dst, src, srcw, srch, depth, dstw, dsth, method);
+    Ok((dst, format))
+}
+
+/// Scales the `src` image of `depth` bytes per pixel into `dst`.
+///
+/// # Adaptation
+/// - Derived from `sixel_helper_scale_image` function in the `libsixel` C library.
+#[expect(clippy::too_many_arguments)]
+pub(crate) fn sixel_helper_scale_image(
+    dst: &mut [u8],
+    src: &[u8],
+    srcw: usize,
+    srch: usize,
+    depth: usize,
+    dstw: usize,
+    dsth: usize,
+    method: ResampleMethod,
+) {
+    let (f_resample, n): (fn(f64) -> f64, f64) = match method {
+        ResampleMethod::Nearest => {
+            scale_without_resampling(dst, src, srcw, srch, depth, dstw, dsth);
+            return;
+        }
+        ResampleMethod::Gaussian => (gaussian, 1.0),
+        ResampleMethod::Hanning => (hanning, 1.0),
+        ResampleMethod::Hamming => (hamming, 1.0),
+        ResampleMethod::Bilinear => (bilinear, 1.0),
+        ResampleMethod::Welsh => (welsh, 1.0),
+        ResampleMethod::Bicubic => (bicubic, 2.0),
+        ResampleMethod::Lanczos2 => (lanczos2, 2.0),
+        ResampleMethod::Lanczos3 => (lanczos3, 3.0),
+        ResampleMethod::Lanczos4 => (lanczos4, 4.0),
+    };
+    scale_with_resampling(dst, src, srcw, srch, depth, dstw, dsth, f_resample, n);
+}
+
+/// Scales the image by picking the nearest source pixel.
+fn scale_without_resampling(
+    dst: &mut [u8],
+    src: &[u8],
+    srcw: usize,
+    srch: usize,
+    depth: usize,
+    dstw: usize,
+    dsth: usize,
+) {
+    for h in 0..dsth {
+        let y = h * srch / dsth;
+        for w in 0..dstw {
+            let x = w * srcw / dstw;
+            let pos = (y * srcw + x) * depth;
+            let dst_pos = (h * dstw + w) * depth;
+            dst[dst_pos..dst_pos + depth].copy_from_slice(&src[pos..pos + depth]);
+        }
+    }
+}
+
+/// Scales the image by weighting the source pixels inside the filter support `n`.
+///
+/// The weights of each axis are computed once, since the filter is separable.
+#[expect(clippy::too_many_arguments)]
+fn scale_with_resampling(
+    dst: &mut [u8],
+    src: &[u8],
+    srcw: usize,
+    srch: usize,
+    depth: usize,
+    dstw: usize,
+    dsth: usize,
+    f_resample: fn(f64) -> f64,
+    n: f64,
+) {
+    let x_weights: Vec<_> = (0..dstw).map(|w| axis_weights(w, srcw, dstw, f_resample, n)).collect();
+    let mut offsets = vec![0.0; depth];
+
+    for h in 0..dsth {
+        let (y_begin, y_weights) = axis_weights(h, srch, dsth, f_resample, n);
+        for (w, (x_begin, x_weights)) in x_weights.iter().enumerate() {
+            offsets.fill(0.0);
+            let mut total = 0.0;
+            for (y, weight_y) in (y_begin..).zip(&y_weights) {
+                for (x, weight_x) in (*x_begin..).zip(x_weights) {
+                    let weight = weight_y * weight_x;
+                    let pos = (y * srcw + x) * depth;
+                    for (offset, &c) in offsets.iter_mut().zip(&src[pos..pos + depth]) {
+                        *offset += c as f64 * weight;
+                    }
+                    total += weight;
+                }
+            }
+            if total > 0.0 {
+                let pos = (h * dstw + w) * depth;
+                for (c, offset) in dst[pos..pos + depth].iter_mut().zip(&offsets) {
+                    *c = (offset / total).clamp(0.0, 255.0) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Returns the first source position affected by the destination position `d`,
+/// and the weights of the consecutive source positions.
+fn axis_weights(
+    d: usize,
+    srclen: usize,
+    dstlen: usize,
+    f_resample: fn(f64) -> f64,
+    n: f64,
+) -> (usize, Vec<f64>) {
+    let (srclen_f, dstlen_f) = (srclen as f64, dstlen as f64);
+    let (center, begin, end) = if dstlen >= srclen {
+        let center = (d as f64 + 0.5) * srclen_f / dstlen_f;
+        (center, center - n, center + n)
+    } else {
+        let center = d as f64 + 0.5;
+        (center, (center - n) * srclen_f / dstlen_f, (center + n) * srclen_f / dstlen_f)
+    };
+    let begin = (ExtFloat::floor(begin) as i64).max(0) as usize;
+    let end = (ExtFloat::ceil(end) as i64).clamp(0, srclen as i64) as usize;
+    let weights = (begin..end)
+        .map(|s| {
+            let diff = if dstlen >= srclen {
+                s as f64 + 0.5 - center
+            } else {
+                (s as f64 + 0.5) * dstlen_f / srclen_f - center
+            };
+            f_resample(ExtFloat::abs(diff))
+        })
+        .collect();
+    (begin, weights)
+}
+
+/* filter functions */
+
+fn bilinear(d: f64) -> f64 {
+    if d < 1.0 {
+        1.0 - d
+    } else {
+        0.0
+    }
+}
+fn welsh(x: f64) -> f64 {
+    if x < 1.0 {
+        1.0 - x * x
+    } else {
+        0.0
+    }
+}
+fn bicubic(d: f64) -> f64 {
+    if d <= 1.0 {
+        1.0 + (d - 2.0) * d * d
+    } else if d <= 2.0 {
+        4.0 + d * (-8.0 + d * (5.0 - d))
+    } else {
+        0.0
+    }
+}
+/// Returns `sin(πx) / (πx)`, reducing the argument to `[-π, π]` first.
+fn sinc(x: f64) -> f64 {
+    if ExtFloat::abs(x) < f64::EPSILON {
+        return 1.0;
+    }
+    let reduced = x - 2.0 * ExtFloat::round(x / 2.0);
+    ExtFloat::sin(PI * reduced) / (PI * x)
+}
+fn lanczos2(d: f64) -> f64 {
+    if d < 2.0 {
+        sinc(d) * sinc(d / 2.0)
+    } else {
+        0.0
+    }
+}
+fn lanczos3(d: f64) -> f64 {
+    if d < 3.0 {
+        sinc(d) * sinc(d / 3.0)
+    } else {
+        0.0
+    }
+}
+fn lanczos4(d: f64) -> f64 {
+    if d < 4.0 {
+        sinc(d) * sinc(d / 4.0)
+    } else {
+        0.0
+    }
+}
+fn gaussian(x: f64) -> f64 {
+    ExtFloat::exp(-2.0 * x * x) * ExtFloat::sqrt(2.0 / PI)
+}
+fn hanning(x: f64) -> f64 {
+    0.5 + 0.5 * ExtFloat::cos(x * PI)
+}
+fn hamming(x: f64) -> f64 {
+    0.54 + 0.46 * ExtFloat::cos(x * PI)
+}
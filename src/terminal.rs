@@ -0,0 +1,262 @@
+// sixela::terminal
+//
+// TOC
+// - struct TerminalCaps
+// - struct Csi
+// - fn next_csi
+
+use crate::{Sixel, SixelError, SixelOutput, SixelResult};
+use devela::{
+    sys::{IoErrorKind, Read as IoRead, Write as IoWrite},
+    Vec,
+};
+
+/// The queries sent by [`TerminalCaps::query`], with the primary device attributes last.
+const QUERIES: &[u8] = b"\x1B[?1;1;0S\x1B[?2;1;0S\x1B[16t\x1B[c";
+
+/// The graphics capabilities of a terminal, as reported by its replies to queries.
+///
+/// The replies are requested with:
+/// - DA1 (`CSI c`) for the sixel support.
+/// - XTSMGRAPHICS (`CSI ? 1 ; 1 ; 0 S`) for the number of color registers.
+/// - XTSMGRAPHICS (`CSI ? 2 ; 1 ; 0 S`) for the maximum sixel geometry.
+/// - XTWINOPS (`CSI 16 t`) for the size of a character cell in pixels.
+///
+/// # Example
+/// ```
+/// # use sixela::{SixelOutput, TerminalCaps};
+/// // a fake pseudo-terminal that replies to every query
+/// let replies = b"\x1b[?1;0;1024S\x1b[?2;0;1920;1080S\x1b[6;20;10t\x1b[?62;4;22c";
+/// let mut sent = Vec::new();
+/// let caps = TerminalCaps::query(&replies[..], &mut sent).unwrap();
+/// assert_eq![sent, b"\x1b[?1;1;0S\x1b[?2;1;0S\x1b[16t\x1b[c"];
+///
+/// assert![caps.sixel];
+/// assert_eq![caps.colors, Some(1024)];
+/// assert_eq![caps.max_geometry, Some((1920, 1080))];
+/// assert_eq![caps.cell_size, Some((10, 20))];
+///
+/// let mut output = SixelOutput::new(Vec::new());
+/// caps.configure(&mut output);
+/// assert![!output.get_8bit_availability() && !output.get_gri_arg_limit()];
+/// ```
+#[cfg_attr(feature = "nightly", doc(cfg(feature = "std")))]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TerminalCaps {
+    /// Whether the terminal supports sixel graphics (DA1 attribute 4).
+    pub sixel: bool,
+    /// Whether the terminal replied with 8-bit C1 controls.
+    pub control_8bit: bool,
+    /// The number of color registers, if reported.
+    pub colors: Option<u16>,
+    /// The maximum sixel geometry in pixels, as (width, height), if reported.
+    pub max_geometry: Option<(u32, u32)>,
+    /// The size of a character cell in pixels, as (width, height), if reported.
+    pub cell_size: Option<(u16, u16)>,
+}
+
+impl TerminalCaps {
+    /// Writes the queries into `writer` and parses the replies read from `reader`.
+    ///
+    /// The reading stops when the reply to the primary device attributes arrives,
+    /// which every terminal sends after the rest of the replies, or when the reader
+    /// reaches its end or times out with [`WouldBlock`][IoErrorKind::WouldBlock]
+    /// or [`TimedOut`][IoErrorKind::TimedOut].
+    ///
+    /// For an actual terminal, the reader must be in raw mode, preferably with a timeout,
+    /// since a terminal that doesn't reply would block the reading.
+    ///
+    /// # Errors
+    /// Returns [`Io`][SixelError::Io] if the writer or the reader fails.
+    pub fn query<R: IoRead, W: IoWrite>(mut reader: R, mut writer: W) -> SixelResult<Self> {
+        writer.write_all(QUERIES).map_err(|e| SixelError::Io(e.kind()))?;
+        writer.flush().map_err(|e| SixelError::Io(e.kind()))?;
+
+        let mut replies = Vec::new();
+        let mut chunk = [0; 256];
+        while !Self::has_device_attributes(&replies) {
+            match reader.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => replies.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == IoErrorKind::Interrupted => continue,
+                Err(e) if matches!(e.kind(), IoErrorKind::WouldBlock | IoErrorKind::TimedOut) => {
+                    break
+                }
+                Err(e) => return Err(SixelError::Io(e.kind())),
+            }
+        }
+        Ok(Self::parse(&replies))
+    }
+
+    /// Parses the replies to the queries, ignoring any other data.
+    ///
+    /// The replies can be in any order, with either 7-bit or 8-bit controls.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::TerminalCaps;
+    /// let caps = TerminalCaps::parse(b"\x9b?1;3;0S\x9b?65;1;9c");
+    /// assert![!caps.sixel && caps.control_8bit];
+    /// assert_eq![caps.colors, None]; // the query failed
+    /// ```
+    #[must_use]
+    pub fn parse(replies: &[u8]) -> Self {
+        let mut caps = Self::default();
+        let mut pos = 0;
+        while let Some(csi) = next_csi(replies, &mut pos) {
+            caps.control_8bit |= csi.control_8bit;
+            let p = &csi.params[..];
+            match (csi.private, csi.final_byte) {
+                // DA1: CSI ? Pc ; Ps... c
+                (true, b'c') => caps.sixel = p.iter().skip(1).any(|&attr| attr == 4),
+                // XTSMGRAPHICS: CSI ? 1 ; 0 ; Pv S
+                (true, b'S') if p.len() >= 3 && p[0] == 1 && p[1] == 0 => {
+                    caps.colors = Some(p[2].min(u16::MAX as u32) as u16);
+                }
+                // XTSMGRAPHICS: CSI ? 2 ; 0 ; Pw ; Ph S
+                (true, b'S') if p.len() >= 4 && p[0] == 2 && p[1] == 0 => {
+                    caps.max_geometry = Some((p[2], p[3]));
+                }
+                // XTWINOPS: CSI 6 ; Ph ; Pw t
+                (false, b't') if p.len() >= 3 && p[0] == 6 => {
+                    let clamp = |v: u32| v.min(u16::MAX as u32) as u16;
+                    caps.cell_size = Some((clamp(p[2]), clamp(p[1])));
+                }
+                _ => (),
+            }
+        }
+        caps
+    }
+
+    /// Configures the compatibility flags of the `output` for this terminal.
+    ///
+    /// It enables the 8-bit controls only if the terminal replied with them.
+    ///
+    /// It lifts the limit of 255 for the repeat introducer (DECGRI) if the terminal
+    /// replied to XTSMGRAPHICS, which is only implemented by emulators without the
+    /// limit of the DEC terminals. Otherwise the limit is kept, which is always safe.
+    ///
+    /// The sixel scrolling flags are left unchanged, since the replies don't reveal them,
+    /// and the colors and geometry are options of the image, applied by [`apply`][Self::apply].
+    pub fn configure<W: IoWrite>(&self, output: &mut SixelOutput<W>) {
+        output.set_8bit_availability(self.control_8bit);
+        output.set_gri_arg_limit(self.colors.is_none() && self.max_geometry.is_none());
+    }
+
+    /// Returns the `sixel` builder adapted to this terminal.
+    ///
+    /// It uses the 8-bit controls only if the terminal replied with them,
+    /// which need [`build_bytes`][Sixel::build_bytes] or [`build_to`][Sixel::build_to].
+    ///
+    /// It limits the [`colors`][Sixel::colors] to the reported number of color registers,
+    /// and unless it has an explicit [`resize`][Sixel::resize] size,
+    /// it shrinks the image to [`fit_within`][Sixel::fit_within] the maximum geometry.
+    ///
+    /// # Example
+    /// ```
+    /// # use sixela::{Sixel, TerminalCaps};
+    /// let caps = TerminalCaps::parse(b"\x1b[?1;0;16S\x1b[?2;0;64;48S\x1b[?62;4c");
+    /// let rgb = [255, 0, 0, 0, 0, 255].repeat(100 * 20);
+    ///
+    /// let sixel = caps.apply(Sixel::with_bytes_size(&rgb, 200, 20));
+    /// assert_eq![(sixel.colors, sixel.fit_within), (16, Some((64, 20)))];
+    /// assert![sixel.build().unwrap().starts_with("\x1bPq\"1;1;64;6#")];
+    ///
+    /// // an image that already fits is not enlarged
+    /// let sixel = caps.apply(Sixel::with_bytes_size(&rgb, 20, 20).colors(8));
+    /// assert_eq![(sixel.colors, sixel.fit_within), (8, None)];
+    /// ```
+    #[must_use]
+    pub fn apply<'a>(&self, sixel: Sixel<'a>) -> Sixel<'a> {
+        let mut sixel = sixel.control_8bit(self.control_8bit);
+        if let Some(colors) = self.colors.filter(|&colors| colors > 0) {
+            let colors = sixel.colors.min(colors);
+            sixel = sixel.colors(colors);
+        }
+        let Some((max_width, max_height)) = self.max_geometry else {
+            return sixel;
+        };
+        let clamp = |v: u32| v.min(i32::MAX as u32) as i32;
+        let (max_width, max_height) = (clamp(max_width), clamp(max_height));
+        if max_width == 0 || max_height == 0 || sixel.resize.is_some() {
+            return sixel;
+        }
+        let (width, height) = sixel.fit_within.unwrap_or((sixel.width, sixel.height));
+        if width > max_width || height > max_height {
+            sixel = sixel.fit_within(width.min(max_width), height.min(max_height));
+        }
+        sixel
+    }
+
+    /// Returns the size in pixels of the given number of character cells,
+    /// if the cell size is known.
+    #[must_use]
+    pub const fn cells_to_pixels(&self, columns: u16, rows: u16) -> Option<(u32, u32)> {
+        match self.cell_size {
+            Some((w, h)) => Some((columns as u32 * w as u32, rows as u32 * h as u32)),
+            None => None,
+        }
+    }
+
+    /// Returns `true` if the `replies` contain the reply to the primary device attributes.
+    fn has_device_attributes(replies: &[u8]) -> bool {
+        let mut pos = 0;
+        while let Some(csi) = next_csi(replies, &mut pos) {
+            if csi.private && csi.final_byte == b'c' {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+/// A parsed control sequence.
+struct Csi {
+    /// Whether it starts with the `?` private marker.
+    private: bool,
+    /// The numeric parameters. Missing parameters are 0.
+    params: Vec<u32>,
+    /// The final byte.
+    final_byte: u8,
+    /// Whether it was introduced by the 8-bit CSI control.
+    control_8bit: bool,
+}
+
+/// Returns the next complete control sequence of `bytes` from `pos`, advancing it.
+fn next_csi(bytes: &[u8], pos: &mut usize) -> Option<Csi> {
+    while *pos < bytes.len() {
+        let (start, control_8bit) = match bytes[*pos..] {
+            [0x1B, b'[', ..] => (*pos + 2, false),
+            [0x9B, ..] => (*pos + 1, true),
+            _ => {
+                *pos += 1;
+                continue;
+            }
+        };
+        let private = bytes.get(start) == Some(&b'?');
+        let mut params = Vec::new();
+        let mut param = 0_u32;
+        let mut i = start + private as usize;
+        while let Some(&b) = bytes.get(i) {
+            match b {
+                b'0'..=b'9' => param = param.saturating_mul(10).saturating_add((b - b'0') as u32),
+                b';' => params.push(core::mem::take(&mut param)),
+                0x20..=0x3F => (),
+                0x40..=0x7E => {
+                    params.push(param);
+                    *pos = i + 1;
+                    return Some(Csi { private, params, final_byte: b, control_8bit });
+                }
+                // an interrupted sequence
+                _ => break,
+            }
+            i += 1;
+        }
+        if i >= bytes.len() {
+            *pos = bytes.len();
+            return None;
+        }
+        *pos = i;
+    }
+    None
+}
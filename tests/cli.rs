@@ -0,0 +1,131 @@
+// sixela::tests::cli
+//
+//! Tests of the `sixela` command line tool.
+//!
+//! Runs the binary with the arguments of each test, passing the input image
+//! through the standard input, and decodes the sixel image it writes.
+//
+// TOC
+// - consts
+// - tests
+// - helpers
+
+use sixela::SixelImage;
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+/* consts */
+
+/// A 4x2 PPM image of 8 colors.
+const PPM: &[u8] = b"P6 4 2 255\n\
+    \xff\x00\x00\x00\xff\x00\x00\x00\xff\xff\xff\xff\
+    \x00\x00\x00\xff\xff\x00\x00\xff\xff\xff\x00\xff";
+
+/// A 4x2 PGM image of 8 gray levels.
+const PGM: &[u8] = b"P5 4 2 255\n\x00\x24\x48\x6d\x91\xb6\xda\xff";
+
+/* tests */
+
+#[test]
+fn option_values() {
+    let expected = sixela(&["-p", "2"], PPM).unwrap();
+    assert_eq![decode(&expected).palette.len(), 2];
+    // the values can be attached, or in the next argument
+    for args in [&["-p2"][..], &["--colors=2"], &["--colors", "2"]] {
+        assert_eq![sixela(args, PPM).unwrap(), expected, "{args:?}"];
+    }
+    // the flags can be grouped, and followed by an option with a value
+    let grouped = sixela(&["-8Pp2"], PPM).unwrap();
+    assert_eq![grouped, sixela(&["-8", "-P", "-p", "2"], PPM).unwrap()];
+    assert![grouped.starts_with(b"\x1bP\x90")];
+    // the last one of a repeated option wins
+    assert_eq![sixela(&["-p", "16", "-p", "2"], PPM).unwrap(), expected];
+    // a lone `-` is the standard input, and `--` ends the options
+    assert_eq![sixela(&["-p2", "--", "-"], PPM).unwrap(), expected];
+}
+
+#[test]
+fn option_errors() {
+    let error = |args: &[&str]| sixela(args, PPM).unwrap_err();
+    assert_eq![error(&["-x"]), "sixela: unknown option: -x"];
+    assert_eq![error(&["--colours=2"]), "sixela: unknown option: --colours"];
+    assert_eq![error(&["-p"]), "sixela: missing value for -p"];
+    assert_eq![error(&["--colors"]), "sixela: missing value for --colors"];
+    assert_eq![error(&["--penetrate=yes"]), "sixela: unexpected value for --penetrate"];
+    assert_eq![error(&["-p", "0"]), "sixela: invalid number of colors: 0"];
+    assert_eq![error(&["-d", "floyd"]), "sixela: invalid diffusion type: floyd"];
+    // after `--`, the options are file names
+    assert![error(&["--", "-p2"]).starts_with("sixela: -p2: ")];
+}
+
+#[test]
+fn lengths() {
+    let size = |args: &[&str]| {
+        let image = decode(&sixela(args, PPM).unwrap());
+        (image.width, image.height)
+    };
+    assert_eq![size(&[]), (4, 2)];
+    assert_eq![size(&["-w", "auto", "-h", "auto"]), (4, 2)];
+    // pixels, with or without a unit, keeping the aspect ratio if the other one is auto
+    assert_eq![size(&["-w", "8"]), (8, 4)];
+    assert_eq![size(&["-w", "8px"]), (8, 4)];
+    assert_eq![size(&["-h", "6px"]), (12, 6)];
+    assert_eq![size(&["-w", "2", "-h", "6"]), (2, 6)];
+    // percentages of the original size
+    assert_eq![size(&["-w", "50%"]), (2, 1)];
+    assert_eq![size(&["--width=200%", "--height=300%"]), (8, 6)];
+
+    for invalid in ["0", "-8", "8pt", "%", "50%%", "eight"] {
+        let error = sixela(&["-w", invalid], PPM).unwrap_err();
+        assert_eq![error, format!["sixela: invalid length: {invalid}"]];
+    }
+}
+
+#[test]
+fn grayscale_palette() {
+    // the grayscale images use the grayscale palette by default
+    assert_eq![decode(&sixela(&[], PGM).unwrap()).palette.len(), 256];
+    // unless an option that affects the palette is given,
+    // so that they are encoded as the same image in RGB
+    let ppm = gray_ppm();
+    for args in [&["-p", "2"][..], &["-f", "lum"], &["-s", "center"], &["-q", "full"], &["-I"]] {
+        let gray = sixela(args, PGM).unwrap();
+        assert_eq![gray, sixela(args, &ppm).unwrap(), "{args:?}"];
+        assert![decode(&gray).palette.len() < 256, "{args:?}"];
+    }
+}
+
+/* helpers */
+
+/// Runs `sixela` with the `args` and the `input` as the standard input.
+///
+/// Returns its output on success, or its error message otherwise.
+fn sixela(args: &[&str], input: &[u8]) -> Result<Vec<u8>, String> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_sixela"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    let output = child.wait_with_output().unwrap();
+    if output.status.success() {
+        Ok(output.stdout)
+    } else {
+        Err(String::from_utf8(output.stderr).unwrap().trim_end().to_string())
+    }
+}
+
+fn decode(sixel: &[u8]) -> SixelImage {
+    SixelImage::decode(sixel).unwrap()
+}
+
+/// Returns the [`PGM`] image as a PPM image.
+fn gray_ppm() -> Vec<u8> {
+    let mut ppm = b"P6 4 2 255\n".to_vec();
+    ppm.extend(PGM[11..].iter().flat_map(|&gray| [gray; 3]));
+    ppm
+}
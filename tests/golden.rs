@@ -0,0 +1,304 @@
+// sixela::tests::golden
+//
+//! Golden file regression tests of the sixel encoder.
+//!
+//! Encodes the reference images in `tests/golden/` and compares the output
+//! with the expected one, checked in next to them:
+//! - `libsixel/<image>/<case>.six` are the outputs of libsixel's `img2sixel` 1.7.3
+//!   for each of the [`LIBSIXEL_CASES`], whose decoded pixels must be the same.
+//! - `<image>.txt` lists the length and hash of the output of a representative set of
+//!   combinations of [`Diffusion`], [`LargestDim`], [`RepColor`] and [`Quality`],
+//!   which must be byte-exact.
+//! - `<image>/*.six` are complete outputs of each diffusion and quality with the defaults,
+//!   which are also decoded on a mismatch to report how many pixels changed.
+//!
+//! The libsixel references cover the options that libsixel shares with sixela.
+//! They leave out the [`HighColor`][Quality::HighColor] quality, which libsixel 1.7.3
+//! encodes differently, and the `VT340Color` preset, whose libsixel palette
+//! repeats the gray levels of the monochrome one.
+//! The other expected files are snapshots of sixela's own output,
+//! which guard the rest of the options against unintended changes.
+//!
+//! After an intended change of the output, regenerate the snapshots with:
+//! ```sh
+//! SIXELA_BLESS=1 cargo test --test golden
+//! ```
+//! and a libsixel reference with:
+//! ```sh
+//! img2sixel <args> tests/golden/<image>.ppm > tests/golden/libsixel/<image>/<case>.six
+//! ```
+//
+// TOC
+// - consts
+// - tests
+// - struct Reference
+// - helpers
+
+use sixela::{Diffusion, DitherMode, LargestDim, Quality, RepColor, Sixel, SixelImage};
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/* consts */
+
+/// The reference images, as binary PPM files.
+///
+/// `plasma` has more colors than the palette, even after sampling every 6th pixel
+/// for its histogram, so that the median cut runs with every [`LargestDim`] and [`RepColor`].
+const IMAGES: [&str; 3] = ["gradient", "plasma", "shapes"];
+
+const DIFFUSIONS: [Diffusion; 17] = [
+    Diffusion::Auto,
+    Diffusion::None,
+    Diffusion::Atkinson,
+    Diffusion::FS,
+    Diffusion::JaJuNi,
+    Diffusion::Stucki,
+    Diffusion::Burkes,
+    Diffusion::ADither,
+    Diffusion::XDither,
+    Diffusion::Bayer2,
+    Diffusion::Bayer4,
+    Diffusion::Bayer8,
+    Diffusion::BlueNoise,
+    Diffusion::Sierra3,
+    Diffusion::Sierra2,
+    Diffusion::SierraLite,
+    Diffusion::StevensonArce,
+];
+const LARGESTS: [LargestDim; 3] = [LargestDim::Auto, LargestDim::Norm, LargestDim::Lum];
+const REPS: [RepColor; 4] = [
+    RepColor::Auto,
+    RepColor::Center,
+    RepColor::AverageColors,
+    RepColor::AveragePixels,
+];
+const QUALITIES: [Quality; 5] =
+    [Quality::Auto, Quality::High, Quality::Low, Quality::Full, Quality::HighColor];
+
+/// The environment variable that regenerates the expected files.
+const BLESS: &str = "SIXELA_BLESS";
+
+/// Sets some options of a builder.
+type Options = fn(Sixel) -> Sixel;
+
+/// The cases compared with libsixel: their name, the `img2sixel` arguments,
+/// and the matching options of the builder.
+#[rustfmt::skip]
+const LIBSIXEL_CASES: [(&str, &str, Options); 18] = [
+    ("default", "", |s| s),
+    ("d-none", "-d none", |s| s.diffuse(Diffusion::None)),
+    ("d-atkinson", "-d atkinson", |s| s.diffuse(Diffusion::Atkinson)),
+    ("d-fs", "-d fs", |s| s.diffuse(Diffusion::FS)),
+    ("d-jajuni", "-d jajuni", |s| s.diffuse(Diffusion::JaJuNi)),
+    ("d-stucki", "-d stucki", |s| s.diffuse(Diffusion::Stucki)),
+    ("d-burkes", "-d burkes", |s| s.diffuse(Diffusion::Burkes)),
+    ("f-lum", "-f lum", |s| s.largest(LargestDim::Lum)),
+    ("s-center", "-s center", |s| s.rep(RepColor::Center)),
+    ("s-average", "-s average", |s| s.rep(RepColor::AverageColors)),
+    ("s-histogram", "-s histogram", |s| s.rep(RepColor::AveragePixels)),
+    ("q-high", "-q high", |s| s.quality(Quality::High)),
+    ("q-low", "-q low", |s| s.quality(Quality::Low)),
+    ("q-full", "-q full", |s| s.quality(Quality::Full)),
+    ("p-16", "-p 16", |s| s.colors(16)),
+    ("p-8-f-lum-s-center", "-p 8 -f lum -s center",
+        |s| s.colors(8).largest(LargestDim::Lum).rep(RepColor::Center)),
+    ("b-xterm256-d-fs", "-b xterm256 -d fs",
+        |s| s.palette_preset(DitherMode::XTerm256).diffuse(Diffusion::FS)),
+    ("b-gray4", "-b gray4", |s| s.palette_preset(DitherMode::G4)),
+];
+
+/* tests */
+
+#[test]
+fn golden_libsixel() {
+    for name in IMAGES {
+        let image = Reference::load(name);
+        for (case, args, options) in LIBSIXEL_CASES {
+            let sixel = options(image.builder()).build_bytes().unwrap();
+            let path = golden_path(&format!["libsixel/{name}/{case}.six"]);
+            let expected = fs::read(&path).unwrap_or_else(|e| {
+                panic![
+                    "{}: {e}, generate it with img2sixel {args} tests/golden/{name}.ppm",
+                    path.display()
+                ]
+            });
+            let pixels = decoded(&sixel);
+            if pixels.is_none() || pixels != decoded(&expected) {
+                let diff = pixel_diff(&sixel, &expected);
+                panic!["{}: the pixels differ from libsixel's, {diff}", path.display()];
+            }
+        }
+    }
+}
+
+#[test]
+fn golden_combinations() {
+    for name in IMAGES {
+        let image = Reference::load(name);
+        let mut manifest = String::new();
+        for (diffuse, largest, rep, quality) in combinations() {
+            let sixel = image.encode(diffuse, largest, rep, quality);
+            let (len, hash) = (sixel.len(), fnv1a(&sixel));
+            writeln!(manifest, "{diffuse:?} {largest:?} {rep:?} {quality:?} {len} {hash:016x}")
+                .unwrap();
+        }
+        let path = golden_path(&format!["{name}.txt"]);
+        if blessing() {
+            fs::write(&path, &manifest).unwrap();
+            continue;
+        }
+        let expected = read_expected(&path);
+        let expected = String::from_utf8(expected).unwrap();
+        let changed: Vec<_> = manifest
+            .lines()
+            .zip(expected.lines())
+            .filter(|(line, expected)| line != expected)
+            .map(|(line, _)| line.rsplitn(3, ' ').nth(2).unwrap())
+            .collect();
+        assert![
+            changed.is_empty() && manifest.lines().count() == expected.lines().count(),
+            "{name}: the output of {} combinations changed:\n{}",
+            changed.len(),
+            changed.join("\n")
+        ];
+    }
+}
+
+#[test]
+fn golden_sixels() {
+    for name in IMAGES {
+        let image = Reference::load(name);
+        let (largest, rep) = (LargestDim::Auto, RepColor::Auto);
+        let diffusions = DIFFUSIONS.map(|d| (format!["diffuse-{d:?}"], d, Quality::Auto));
+        let qualities = QUALITIES.map(|q| (format!["quality-{q:?}"], Diffusion::Auto, q));
+        for (file, diffuse, quality) in diffusions.into_iter().chain(qualities) {
+            let sixel = image.encode(diffuse, largest, rep, quality);
+            let path = golden_path(&format!["{name}/{}.six", file.to_lowercase()]);
+            if blessing() {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, &sixel).unwrap();
+                continue;
+            }
+            let expected = read_expected(&path);
+            if sixel != expected {
+                panic!["{}: the output changed, {}", path.display(), pixel_diff(&sixel, &expected)];
+            }
+        }
+    }
+}
+
+/* struct Reference */
+
+/// A reference image, as `RGB888` pixels.
+struct Reference {
+    width: i32,
+    height: i32,
+    pixels: Vec<u8>,
+}
+
+impl Reference {
+    /// Loads the binary PPM reference image with the given name.
+    fn load(name: &str) -> Self {
+        let ppm = fs::read(golden_path(&format!["{name}.ppm"])).unwrap();
+        // the header has no comments: "P6\n<width> <height>\n255\n"
+        let mut fields = ppm.splitn(5, |b| b.is_ascii_whitespace());
+        assert_eq![fields.next(), Some(&b"P6"[..])];
+        let mut number = || std::str::from_utf8(fields.next().unwrap()).unwrap().parse().unwrap();
+        let (width, height, maxval): (i32, i32, i32) = (number(), number(), number());
+        assert_eq![maxval, 255];
+        let pixels = fields.next().unwrap().to_vec();
+        assert_eq![pixels.len(), (width * height * 3) as usize];
+        Self { width, height, pixels }
+    }
+
+    /// Returns a builder of the image, with the default options.
+    fn builder(&self) -> Sixel<'_> {
+        Sixel::with_bytes_size(&self.pixels, self.width, self.height)
+    }
+
+    /// Encodes the image with the given options.
+    fn encode(
+        &self,
+        diffuse: Diffusion,
+        largest: LargestDim,
+        rep: RepColor,
+        quality: Quality,
+    ) -> Vec<u8> {
+        self.builder()
+            .diffuse(diffuse)
+            .largest(largest)
+            .rep(rep)
+            .quality(quality)
+            .build_bytes()
+            .unwrap()
+    }
+}
+
+/* helpers */
+
+fn golden_path(file: &str) -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "tests", "golden", file].iter().collect()
+}
+
+fn blessing() -> bool {
+    std::env::var_os(BLESS).is_some_and(|v| v != "0")
+}
+
+fn read_expected(path: &Path) -> Vec<u8> {
+    fs::read(path).unwrap_or_else(|e| {
+        panic!["{}: {e}, regenerate it with {BLESS}=1 cargo test --test golden", path.display()]
+    })
+}
+
+/// Returns the combinations of options of the manifests: each diffusion and quality
+/// with the defaults for the rest, and each pair of [`LargestDim`] and [`RepColor`]
+/// with every quality but [`HighColor`][Quality::HighColor], which doesn't use them.
+fn combinations() -> Vec<(Diffusion, LargestDim, RepColor, Quality)> {
+    let (d, l, r, q) = (Diffusion::Auto, LargestDim::Auto, RepColor::Auto, Quality::Auto);
+    let mut combinations: Vec<_> = DIFFUSIONS.map(|diffuse| (diffuse, l, r, q)).into();
+    combinations.push((d, l, r, Quality::HighColor));
+    for quality in QUALITIES.into_iter().filter(|q| !matches!(q, Quality::HighColor)) {
+        for largest in LARGESTS {
+            for rep in REPS {
+                if (largest, rep, quality) != (l, r, q) {
+                    combinations.push((d, largest, rep, quality));
+                }
+            }
+        }
+    }
+    combinations
+}
+
+/// Returns the size and the `RGB888` pixels of the decoded sixel image, if it can be decoded.
+fn decoded(sixel: &[u8]) -> Option<(usize, usize, Vec<u8>)> {
+    let image = SixelImage::decode(sixel).ok()?;
+    Some((image.width, image.height, image.to_rgb888([0; 3])))
+}
+
+/// Describes how the decoded pixels of two sixel images differ.
+fn pixel_diff(sixel: &[u8], expected: &[u8]) -> String {
+    let (Ok(image), Ok(expected)) = (SixelImage::decode(sixel), SixelImage::decode(expected))
+    else {
+        return "and it can't be decoded".into();
+    };
+    if (image.width, image.height) != (expected.width, expected.height) {
+        return format!["and its size is {}x{}", image.width, image.height];
+    }
+    let (pixels, expected) = (image.to_rgb888([0; 3]), expected.to_rgb888([0; 3]));
+    let changed = pixels.chunks(3).zip(expected.chunks(3)).filter(|(a, b)| a != b).count();
+    let max_delta = pixels.iter().zip(&expected).map(|(a, b)| a.abs_diff(*b)).max();
+    format![
+        "with {changed} different pixels, by up to {} per channel",
+        max_delta.unwrap_or(0)
+    ]
+}
+
+/// Returns the 64-bit FNV-1a hash of the `bytes`.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}